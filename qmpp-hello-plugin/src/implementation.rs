@@ -24,8 +24,11 @@ type Vec3 = [f64; VECTOR_3D_COORDS];
 #[no_mangle]
 pub extern "C" fn QMPP_Hook_init() {
     let name = String::from("hello");
+    let buttons = String::from("func_button");
+
     unsafe {
         QMPP_register(name.len(), name.as_ptr());
+        QMPP_hook_filter_classname(buttons.len(), buttons.as_ptr());
     }
 }
 
@@ -161,46 +164,15 @@ pub extern "C" fn QMPP_Hook_process() {
     unsafe {
         QMPP_log_info(mesg.len(), mesg.as_ptr());
     }
+}
 
-    let classname_key = b"classname\0".to_vec();
-
-    (0..entity_ct)
-        .filter(|&ehandle| {
-            let mut classname_size = MaybeUninit::<usize>::uninit();
-            let mut classname = Vec::<u8>::new();
-
-            let success = unsafe {
-                QMPP_keyvalue_init_read(
-                    ehandle,
-                    classname_key.as_ptr(),
-                    classname_size.as_mut_ptr(),
-                )
-            };
-
-            if success != 0u32 {
-                let classname_size = unsafe { classname_size.assume_init() };
-
-                classname.reserve(classname_size);
-
-                unsafe {
-                    QMPP_keyvalue_read(classname.as_mut_ptr());
-                    classname.set_len(classname_size);
-                }
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn QMPP_Hook_brush(ehandle: u32, b_idx: u32) {
+    let shandle_ct = unsafe { QMPP_shandle_count(ehandle, b_idx) };
 
-                &classname[..] == b"func_button\0"
-            } else {
-                false
-            }
-        })
-        .flat_map(|ehandle| {
-            let bhandle_ct = unsafe { QMPP_bhandle_count(ehandle) };
-            (0..bhandle_ct).map(move |b_idx| (ehandle, b_idx))
-        })
-        .flat_map(|(ehandle, b_idx)| {
-            let shandle_ct = unsafe { QMPP_shandle_count(ehandle, b_idx) };
-            (0..shandle_ct).map(move |s_idx| (ehandle, b_idx, s_idx))
-        })
-        .map(|(ehandle, b_idx, s_idx)| {
+    (0..shandle_ct)
+        .map(|s_idx| {
             let mut texture = Vec::<u8>::new();
             let mut half_space = MaybeUninit::<HalfSpace>::uninit();
             let mut alignment = MaybeUninit::<Alignment>::uninit();
@@ -316,6 +288,10 @@ pub extern "C" fn QMPP_Hook_process() {
 #[allow(non_snake_case, improper_ctypes)]
 extern "C" {
     pub fn QMPP_register(name_len: usize, name_ptr: *const u8);
+    pub fn QMPP_hook_filter_classname(
+        pattern_len: usize,
+        pattern_ptr: *const u8,
+    );
 
     pub fn QMPP_log_info(mesg_len: usize, mesg_ptr: *const u8);
     pub fn QMPP_log_error(mesg_len: usize, mesg_ptr: *const u8);
@@ -16,6 +16,14 @@ pub extern "C" fn QMPP_register(name_len: usize, name_ptr: *const u8) {
     }
 }
 
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn QMPP_hook_filter_classname(
+    _pattern_len: usize,
+    _pattern_ptr: *const u8,
+) {
+}
+
 #[test]
 fn init() {
     let expected_name: &str = "hello";
@@ -1,10 +1,32 @@
 use crate::host_interface::*;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::iter::Iterator;
+use cstr_core::{CStr, CString};
+use qmpp_shared::LowApiCode;
 
-pub fn entity_handles() -> impl Iterator {
+/// How far a component of a unit normal may be from 1 for the plane to
+/// still count as axial
+const AXIAL_EPSILON: f64 = 1e-6;
+
+pub fn entity_handles() -> impl Iterator<Item = EntityHandle> {
     (0..ehandle_count()).map(EntityHandle::new)
 }
 
+/// Handles of the entities whose value for `key` matches `pattern`, in
+/// ascending order
+pub fn find_entities(
+    key: &CStr,
+    pattern: &CStr,
+    mode: MatchMode,
+) -> FoundEntities {
+    FoundEntities {
+        ehandles: crate::host_interface::find_entities(key, pattern, mode)
+            .into_iter(),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct EntityHandle {
     entity_idx: u32,
 }
@@ -13,4 +35,248 @@ impl EntityHandle {
     pub(crate) fn new(entity_idx: u32) -> EntityHandle {
         EntityHandle { entity_idx }
     }
+
+    pub fn brushes(
+        &self,
+    ) -> Result<impl Iterator<Item = BrushHandle>, LowApiCode> {
+        let entity_idx = self.entity_idx;
+        let brush_count = bhandle_count(entity_idx)?;
+
+        Ok((0..brush_count)
+            .map(move |brush_idx| BrushHandle::new(entity_idx, brush_idx)))
+    }
+
+    /// Quake 3 curve patches of the entity
+    pub fn patches(&self) -> impl Iterator<Item = PatchHandle> {
+        let entity_idx = self.entity_idx;
+
+        (0..phandle_count(entity_idx))
+            .map(move |patch_idx| PatchHandle::new(entity_idx, patch_idx))
+    }
+
+    /// Links to the entities this entity's `target` and `killtarget` keys
+    /// name
+    pub fn links_out(&self) -> Vec<Link> {
+        read_links_out(self.entity_idx)
+            .into_iter()
+            .filter_map(Link::from_raw)
+            .collect()
+    }
+
+    /// Links to this entity from the entities naming it in their `target`
+    /// or `killtarget` keys
+    pub fn links_in(&self) -> Vec<Link> {
+        read_links_in(self.entity_idx)
+            .into_iter()
+            .filter_map(Link::from_raw)
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BrushHandle {
+    entity_idx: u32,
+    brush_idx: u32,
+}
+
+impl BrushHandle {
+    pub(crate) fn new(entity_idx: u32, brush_idx: u32) -> BrushHandle {
+        BrushHandle {
+            entity_idx,
+            brush_idx,
+        }
+    }
+
+    pub fn surfaces(
+        &self,
+    ) -> Result<impl Iterator<Item = SurfaceHandle>, LowApiCode> {
+        let BrushHandle {
+            entity_idx,
+            brush_idx,
+        } = *self;
+
+        let surface_count = shandle_count(entity_idx, brush_idx)?;
+
+        Ok((0..surface_count).map(move |surface_idx| {
+            SurfaceHandle::new(entity_idx, brush_idx, surface_idx)
+        }))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SurfaceHandle {
+    entity_idx: u32,
+    brush_idx: u32,
+    surface_idx: u32,
+}
+
+impl SurfaceHandle {
+    pub(crate) fn new(
+        entity_idx: u32,
+        brush_idx: u32,
+        surface_idx: u32,
+    ) -> SurfaceHandle {
+        SurfaceHandle {
+            entity_idx,
+            brush_idx,
+            surface_idx,
+        }
+    }
+
+    /// Plane the surface lies in, or `None` if its half-space points are
+    /// collinear
+    pub fn plane(&self) -> Option<Plane> {
+        read_surface_plane(self.entity_idx, self.brush_idx, self.surface_idx)
+            .map(|(normal, dist)| Plane { normal, dist })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PatchHandle {
+    entity_idx: u32,
+    patch_idx: u32,
+}
+
+impl PatchHandle {
+    pub(crate) fn new(entity_idx: u32, patch_idx: u32) -> PatchHandle {
+        PatchHandle {
+            entity_idx,
+            patch_idx,
+        }
+    }
+
+    /// Width and height in control points
+    pub fn size(&self) -> (u32, u32) {
+        let [width, height] = read_patch_size(self.entity_idx, self.patch_idx);
+        (width, height)
+    }
+
+    pub fn texture(&self) -> CString {
+        read_patch_texture(self.entity_idx, self.patch_idx)
+    }
+
+    /// Control points as x, y, z, s and t, in rows of `height` points, one
+    /// row for each point across the width
+    pub fn points(&self) -> Vec<[f64; 5]> {
+        read_patch_points(self.entity_idx, self.patch_idx)
+    }
+}
+
+/// Plane a surface lies in
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Plane {
+    /// Unit normal pointing out of the brush
+    pub normal: [f64; 3],
+    /// Distance from the origin along the normal
+    pub dist: f64,
+}
+
+impl Plane {
+    /// Direction the plane faces, going by the largest component of its
+    /// normal; ties go to up or down, then to east or west
+    pub fn facing(&self) -> Facing {
+        let [x, y, z] = self.normal;
+
+        if z.abs() >= x.abs() && z.abs() >= y.abs() {
+            if z > 0.0 {
+                Facing::Up
+            } else {
+                Facing::Down
+            }
+        } else if x.abs() >= y.abs() {
+            if x > 0.0 {
+                Facing::East
+            } else {
+                Facing::West
+            }
+        } else if y > 0.0 {
+            Facing::North
+        } else {
+            Facing::South
+        }
+    }
+
+    /// Axis the plane's normal lies along, or `None` if the plane is sloped
+    pub fn axis(&self) -> Option<Axis> {
+        let axis_idx = self
+            .normal
+            .iter()
+            .position(|coord| (coord.abs() - 1.0).abs() < AXIAL_EPSILON)?;
+
+        Some([Axis::X, Axis::Y, Axis::Z][axis_idx])
+    }
+
+    pub fn is_axial(&self) -> bool {
+        self.axis().is_some()
+    }
+}
+
+/// Direction a plane faces, with east along +X, north along +Y and up along
+/// +Z
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Facing {
+    East,
+    West,
+    North,
+    South,
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
 }
+
+/// Key naming the entity at the end of a link
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LinkKind {
+    Target,
+    Killtarget,
+}
+
+/// Link between two entities through a `target` or `killtarget` key
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The entity at the other end of the link
+    pub entity: EntityHandle,
+}
+
+impl Link {
+    pub(crate) fn new(kind: LinkKind, entity: EntityHandle) -> Link {
+        Link { kind, entity }
+    }
+
+    /// Link read from the host, or `None` for kinds this API doesn't know
+    fn from_raw([kind, ehandle]: RawLink) -> Option<Link> {
+        let kind = match kind {
+            0 => LinkKind::Target,
+            1 => LinkKind::Killtarget,
+            _ => return None,
+        };
+
+        Some(Link::new(kind, EntityHandle::new(ehandle)))
+    }
+}
+
+/// Iterator over the entities found by `find_entities`
+pub struct FoundEntities {
+    ehandles: vec::IntoIter<u32>,
+}
+
+impl Iterator for FoundEntities {
+    type Item = EntityHandle;
+
+    fn next(&mut self) -> Option<EntityHandle> {
+        self.ehandles.next().map(EntityHandle::new)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ehandles.size_hint()
+    }
+}
+
+impl ExactSizeIterator for FoundEntities {}
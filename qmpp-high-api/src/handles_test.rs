@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+use cstr_core::CStr;
+
+use crate::handles::{find_entities, Axis, EntityHandle, Facing, Plane};
+use crate::MatchMode;
+
+const FOUND: [u32; 3] = [1, 4, 9];
+
+struct FindEntitiesCall {
+    key: Vec<u8>,
+    pattern: Vec<u8>,
+    mode: u32,
+}
+
+static mut FIND_ENTITIES_CALL: Option<FindEntitiesCall> = None;
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn QMPP_find_entities_init(
+    key_ptr: *const u8,
+    pattern_ptr: *const u8,
+    mode: u32,
+) -> u32 {
+    let (key, pattern) = unsafe {
+        (
+            CStr::from_ptr(key_ptr.cast()).to_bytes().into(),
+            CStr::from_ptr(pattern_ptr.cast()).to_bytes().into(),
+        )
+    };
+
+    unsafe {
+        FIND_ENTITIES_CALL = Some(FindEntitiesCall { key, pattern, mode });
+    }
+
+    FOUND.len() as u32
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn QMPP_find_entities_read(ehandles_ptr: *mut u32) {
+    unsafe {
+        ehandles_ptr.copy_from_nonoverlapping(FOUND.as_ptr(), FOUND.len());
+    }
+}
+
+#[test]
+fn found_entities_are_handles() {
+    let key = CStr::from_bytes_with_nul(b"classname\0").unwrap();
+    let pattern = CStr::from_bytes_with_nul(b"light*\0").unwrap();
+
+    let found = find_entities(key, pattern, MatchMode::Glob);
+    assert_eq!(found.len(), FOUND.len());
+
+    let handles = found.collect::<Vec<_>>();
+    let expected = FOUND.map(EntityHandle::new);
+    assert_eq!(handles, expected);
+
+    let call = unsafe { FIND_ENTITIES_CALL.take().unwrap() };
+    assert_eq!(call.key, b"classname");
+    assert_eq!(call.pattern, b"light*");
+    assert_eq!(call.mode, 2);
+}
+
+#[test]
+fn axial_planes_face_along_their_axis() {
+    let cases = [
+        ([1.0, 0.0, 0.0], Facing::East, Axis::X),
+        ([-1.0, 0.0, 0.0], Facing::West, Axis::X),
+        ([0.0, 1.0, 0.0], Facing::North, Axis::Y),
+        ([0.0, -1.0, 0.0], Facing::South, Axis::Y),
+        ([0.0, 0.0, 1.0], Facing::Up, Axis::Z),
+        ([0.0, 0.0, -1.0], Facing::Down, Axis::Z),
+    ];
+
+    for (normal, facing, axis) in cases {
+        let plane = Plane { normal, dist: 16.0 };
+        assert_eq!(plane.facing(), facing);
+        assert_eq!(plane.axis(), Some(axis));
+        assert!(plane.is_axial());
+    }
+}
+
+#[test]
+fn sloped_planes_face_their_steepest_direction() {
+    let ramp = Plane {
+        normal: [-0.6, 0.0, 0.8],
+        dist: 0.0,
+    };
+
+    assert_eq!(ramp.facing(), Facing::Up);
+    assert_eq!(ramp.axis(), None);
+
+    let wall = Plane {
+        normal: [0.28, -0.96, 0.0],
+        dist: 0.0,
+    };
+
+    assert_eq!(wall.facing(), Facing::South);
+    assert!(!wall.is_axial());
+
+    // a 45 degree slope is as much up as it is west
+    let half = core::f64::consts::FRAC_1_SQRT_2;
+    let slope = Plane {
+        normal: [-half, 0.0, half],
+        dist: 0.0,
+    };
+
+    assert_eq!(slope.facing(), Facing::Up);
+    assert!(!slope.is_axial());
+}
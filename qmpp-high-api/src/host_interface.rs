@@ -10,12 +10,19 @@ const VECTOR_3D_COORDS: usize = 3;
 const OFFSET_COMPONENTS: usize = 2;
 const ROTATION_COMPONENTS: usize = 1;
 const SCALE_COMPONENTS: usize = 2;
+const TEXTURE_COORDS: usize = 2;
 
 type RawVec3 = [f64; VECTOR_3D_COORDS];
 type RawHalfSpace = [RawVec3; HALF_SPACE_POINTS];
 type RawAlignment =
     [f64; OFFSET_COMPONENTS + ROTATION_COMPONENTS + SCALE_COMPONENTS];
 type RawAxes = [RawVec3; 2];
+/// Unit normal followed by distance from the origin
+type RawPlane = [f64; VECTOR_3D_COORDS + 1];
+/// Position followed by texture coordinates
+pub(crate) type RawPatchPoint = [f64; VECTOR_3D_COORDS + TEXTURE_COORDS];
+/// Link kind followed by the ehandle at the other end of the link
+pub(crate) type RawLink = [u32; 2];
 
 #[allow(non_snake_case, improper_ctypes)]
 extern "C" {
@@ -34,6 +41,17 @@ extern "C" {
     fn QMPP_keys_init_read(ehandle: u32, size_ptr: *mut usize) -> LowApiCode;
     fn QMPP_keys_read(keys_ptr: *mut u8);
 
+    fn QMPP_find_entities_init(
+        key_ptr: *const u8,
+        pattern_ptr: *const u8,
+        mode: u32,
+    ) -> u32;
+    fn QMPP_find_entities_read(ehandles_ptr: *mut u32);
+
+    fn QMPP_links_out_init_read(ehandle: u32) -> u32;
+    fn QMPP_links_in_init_read(ehandle: u32) -> u32;
+    fn QMPP_links_read(links_ptr: *mut RawLink);
+
     fn QMPP_bhandle_count(ehandle: u32, brush_ct_ptr: *mut u32) -> LowApiCode;
 
     fn QMPP_shandle_count(
@@ -57,6 +75,22 @@ extern "C" {
         ptr: *mut RawHalfSpace,
     ) -> LowApiCode;
 
+    fn QMPP_surface_plane_read(
+        ehandle: u32,
+        brush_idx: u32,
+        surface_idx: u32,
+        ptr: *mut RawPlane,
+    ) -> u32;
+
+    fn QMPP_phandle_count(ehandle: u32) -> u32;
+    fn QMPP_patch_size_read(ehandle: u32, patch_idx: u32, ptr: *mut [u32; 2]);
+    fn QMPP_patch_texture_init_read(ehandle: u32, patch_idx: u32) -> usize;
+    fn QMPP_patch_points_read(
+        ehandle: u32,
+        patch_idx: u32,
+        ptr: *mut RawPatchPoint,
+    );
+
     fn QMPP_texture_alignment_read(
         ehandle: u32,
         brush_idx: u32,
@@ -156,6 +190,61 @@ pub fn read_keys(ehandle: u32) -> Result<Vec<CString>, LowApiCode> {
     }
 }
 
+/// How `find_entities` matches values against its pattern
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MatchMode {
+    Exact = 0,
+    Prefix = 1,
+    Glob = 2,
+}
+
+pub fn find_entities(key: &CStr, pattern: &CStr, mode: MatchMode) -> Vec<u32> {
+    let key_bytes = key.to_bytes_with_nul();
+    let pattern_bytes = pattern.to_bytes_with_nul();
+
+    let found_count = unsafe {
+        QMPP_find_entities_init(
+            key_bytes.as_ptr(),
+            pattern_bytes.as_ptr(),
+            mode as u32,
+        )
+    };
+
+    let found_count = found_count as usize;
+    let mut ehandles = Vec::<u32>::with_capacity(found_count);
+
+    // the search stays open until it is read, even when nothing was found
+    unsafe { QMPP_find_entities_read(ehandles.as_mut_ptr()) };
+
+    unsafe {
+        ehandles.set_len(found_count);
+    }
+
+    ehandles
+}
+
+pub fn read_links_out(ehandle: u32) -> Vec<RawLink> {
+    let link_count = unsafe { QMPP_links_out_init_read(ehandle) };
+    read_links(link_count as usize)
+}
+
+pub fn read_links_in(ehandle: u32) -> Vec<RawLink> {
+    let link_count = unsafe { QMPP_links_in_init_read(ehandle) };
+    read_links(link_count as usize)
+}
+
+fn read_links(link_count: usize) -> Vec<RawLink> {
+    let mut links = Vec::<RawLink>::with_capacity(link_count);
+
+    unsafe { QMPP_links_read(links.as_mut_ptr()) };
+
+    unsafe {
+        links.set_len(link_count);
+    }
+
+    links
+}
+
 pub fn bhandle_count(ehandle: u32) -> Result<u32, LowApiCode> {
     let mut brush_idx_ct = MaybeUninit::<u32>::uninit();
 
@@ -290,3 +379,74 @@ pub fn read_half_space(
         Err(status)
     }
 }
+
+/// Plane through a surface's half-space points as its outward unit normal
+/// and distance from the origin, or `None` if the points are collinear
+pub fn read_surface_plane(
+    ehandle: u32,
+    brush_idx: u32,
+    surface_idx: u32,
+) -> Option<(RawVec3, f64)> {
+    let mut plane = MaybeUninit::<RawPlane>::uninit();
+
+    let found = unsafe {
+        QMPP_surface_plane_read(
+            ehandle,
+            brush_idx,
+            surface_idx,
+            plane.as_mut_ptr(),
+        )
+    };
+
+    if found != 0 {
+        let [x, y, z, dist] = unsafe { plane.assume_init() };
+        Some(([x, y, z], dist))
+    } else {
+        None
+    }
+}
+
+pub fn phandle_count(ehandle: u32) -> u32 {
+    unsafe { QMPP_phandle_count(ehandle) }
+}
+
+/// Width and height of a patch in control points
+pub fn read_patch_size(ehandle: u32, patch_idx: u32) -> [u32; 2] {
+    let mut size = MaybeUninit::<[u32; 2]>::uninit();
+
+    unsafe {
+        QMPP_patch_size_read(ehandle, patch_idx, size.as_mut_ptr());
+        size.assume_init()
+    }
+}
+
+pub fn read_patch_texture(ehandle: u32, patch_idx: u32) -> CString {
+    let texture_name_size =
+        unsafe { QMPP_patch_texture_init_read(ehandle, patch_idx) };
+
+    let mut texture_name_buffer = Vec::<u8>::with_capacity(texture_name_size);
+
+    unsafe { QMPP_texture_read(texture_name_buffer.as_mut_ptr()) };
+
+    unsafe {
+        // exclude null terminator
+        texture_name_buffer.set_len(texture_name_size - 1);
+    }
+
+    unsafe { CString::from_vec_unchecked(texture_name_buffer) }
+}
+
+/// Control points of a patch in rows of `height` points, one row for each
+/// point across its width
+pub fn read_patch_points(ehandle: u32, patch_idx: u32) -> Vec<RawPatchPoint> {
+    let [width, height] = read_patch_size(ehandle, patch_idx);
+    let point_count = width as usize * height as usize;
+    let mut points = Vec::<RawPatchPoint>::with_capacity(point_count);
+
+    unsafe {
+        QMPP_patch_points_read(ehandle, patch_idx, points.as_mut_ptr());
+        points.set_len(point_count);
+    }
+
+    points
+}
@@ -0,0 +1,14 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod handles;
+mod host_interface;
+pub mod links;
+
+pub use host_interface::MatchMode;
+
+#[cfg(test)]
+mod handles_test;
+#[cfg(test)]
+mod links_test;
@@ -0,0 +1,72 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::{self, Vec};
+
+use crate::handles::{EntityHandle, Link, LinkKind};
+
+/// Entities reached by following `target` links
+#[derive(PartialEq, Eq, Debug)]
+pub struct TargetChain {
+    /// Every entity reached, in the order first reached, beginning with the
+    /// entity the walk started from
+    pub entities: Vec<EntityHandle>,
+    /// Links back to an entity earlier in the same chain, each as the
+    /// entities at its start and end
+    pub cycles: Vec<(EntityHandle, EntityHandle)>,
+}
+
+/// Follow the `target` links from `start` to the entities they name, and on
+/// through theirs
+pub fn walk_targets(start: EntityHandle) -> TargetChain {
+    walk(start, |entity| entity.links_out())
+}
+
+/// Follow the `target` links into `start` back to the entities naming it,
+/// and on back through the entities naming them
+pub fn walk_targeted_by(start: EntityHandle) -> TargetChain {
+    walk(start, |entity| entity.links_in())
+}
+
+/// Walk depth first from `start` over the `target` links `links` gives for
+/// each entity, visiting each entity once
+pub(crate) fn walk(
+    start: EntityHandle,
+    mut links: impl FnMut(EntityHandle) -> Vec<Link>,
+) -> TargetChain {
+    let mut chain = TargetChain {
+        entities: Vec::from([start]),
+        cycles: Vec::new(),
+    };
+
+    let mut reached = BTreeSet::from([start]);
+    let mut path = Vec::from([(start, targets(&mut links, start))]);
+
+    while let Some((entity, next)) = path.last_mut() {
+        let entity = *entity;
+
+        let Some(target) = next.next() else {
+            path.pop();
+            continue;
+        };
+
+        if path.iter().any(|&(on_path, _)| on_path == target) {
+            chain.cycles.push((entity, target));
+        } else if reached.insert(target) {
+            chain.entities.push(target);
+            path.push((target, targets(&mut links, target)));
+        }
+    }
+
+    chain
+}
+
+fn targets(
+    links: &mut impl FnMut(EntityHandle) -> Vec<Link>,
+    entity: EntityHandle,
+) -> vec::IntoIter<EntityHandle> {
+    links(entity)
+        .into_iter()
+        .filter(|link| link.kind == LinkKind::Target)
+        .map(|link| link.entity)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
@@ -0,0 +1,53 @@
+use alloc::vec::Vec;
+
+use crate::handles::{EntityHandle, Link, LinkKind};
+use crate::links::{walk, TargetChain};
+
+/// Walk over links given as the kind and entity indices at each end
+fn walk_links(links: &[(u32, LinkKind, u32)]) -> TargetChain {
+    walk(EntityHandle::new(0), |entity| {
+        links
+            .iter()
+            .filter(|&&(from, _, _)| EntityHandle::new(from) == entity)
+            .map(|&(_, kind, to)| Link::new(kind, EntityHandle::new(to)))
+            .collect()
+    })
+}
+
+fn handles(indices: &[u32]) -> Vec<EntityHandle> {
+    indices.iter().copied().map(EntityHandle::new).collect()
+}
+
+#[test]
+fn branches_are_walked_once() {
+    let chain = walk_links(&[
+        (0, LinkKind::Target, 1),
+        (0, LinkKind::Target, 2),
+        (1, LinkKind::Target, 3),
+        (2, LinkKind::Target, 3),
+        (3, LinkKind::Killtarget, 4),
+    ]);
+
+    assert_eq!(chain.entities, handles(&[0, 1, 3, 2]));
+    assert!(chain.cycles.is_empty());
+}
+
+#[test]
+fn cycles_are_reported() {
+    let chain = walk_links(&[
+        (0, LinkKind::Target, 1),
+        (1, LinkKind::Target, 2),
+        (2, LinkKind::Target, 0),
+        (2, LinkKind::Target, 3),
+        (3, LinkKind::Target, 3),
+    ]);
+
+    assert_eq!(chain.entities, handles(&[0, 1, 2, 3]));
+    assert_eq!(
+        chain.cycles,
+        [
+            (EntityHandle::new(2), EntityHandle::new(0)),
+            (EntityHandle::new(3), EntityHandle::new(3)),
+        ]
+    );
+}
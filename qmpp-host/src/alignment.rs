@@ -0,0 +1,302 @@
+use std::fmt;
+use std::str::FromStr;
+
+use quake_util::qmap::{Alignment, QuakeMap, Surface, Vec3};
+
+use crate::geometry::{dot, Plane};
+use crate::map_data::{MapData, MapPart};
+
+/// Normal of each direction a surface may face, followed by the texture
+/// axes that standard alignment projects surfaces facing that way onto
+const BASE_AXES: [[Vec3; 3]; 6] = [
+    [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],
+    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+    [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+];
+
+/// Largest difference in texture coordinates per unit that still counts
+/// as the same projection
+const EPSILON: f64 = 1e-6;
+
+/// How far a number may be from an integer to be written as one
+const ROUNDING: f64 = 1e-9;
+
+/// Texture alignment styles of the `.map` format
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AlignmentStyle {
+    /// Offset, rotation and scale of the texture projected along the axis
+    /// the surface faces most
+    Standard,
+    /// Explicit texture axes, as written by Valve's editor
+    Valve220,
+}
+
+impl AlignmentStyle {
+    pub fn of(alignment: &Alignment) -> Self {
+        match alignment.axes {
+            Some(_) => AlignmentStyle::Valve220,
+            None => AlignmentStyle::Standard,
+        }
+    }
+}
+
+impl fmt::Display for AlignmentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlignmentStyle::Standard => write!(f, "standard"),
+            AlignmentStyle::Valve220 => write!(f, "valve220"),
+        }
+    }
+}
+
+impl FromStr for AlignmentStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(style: &str) -> anyhow::Result<Self> {
+        match style.to_ascii_lowercase().as_str() {
+            "standard" => Ok(AlignmentStyle::Standard),
+            "valve220" | "valve" => Ok(AlignmentStyle::Valve220),
+            _ => Err(anyhow::anyhow!("Unknown alignment style \"{}\"", style)),
+        }
+    }
+}
+
+/// A surface's alignment in another style
+pub enum Conversion {
+    /// Projects the texture exactly as before
+    Exact(Alignment),
+    /// Closest projection the style can give, which shears or stretches the
+    /// texture differently
+    Lossy(Alignment),
+}
+
+/// Convert a surface's alignment to `style`, or `None` if its points do not
+/// make a plane
+///
+/// Any standard alignment can be given as Valve220 axes.  Going back, the
+/// axes are projected onto the plane the standard alignment would use, and
+/// the conversion is lossy if they are not then at right angles.
+pub fn convert(surface: &Surface, style: AlignmentStyle) -> Option<Conversion> {
+    let plane = Plane::from_half_space(&surface.half_space)?;
+    let alignment = &surface.alignment;
+
+    if AlignmentStyle::of(alignment) == style {
+        return Some(Conversion::Exact(*alignment));
+    }
+
+    Some(match style {
+        AlignmentStyle::Valve220 => {
+            Conversion::Exact(to_valve(alignment, &plane))
+        }
+        AlignmentStyle::Standard => to_standard(alignment, &plane),
+    })
+}
+
+/// Map with every surface converted to `style`, along with the surfaces
+/// that could not be converted exactly
+///
+/// Surfaces of Quake 3 `brushDef`s are left alone, their projection being
+/// kept as a texture matrix.  So is every surface of a brush with a surface
+/// whose points do not make a plane, rather than mixing styles within the
+/// brush.
+pub fn convert_map(map: &MapData, style: AlignmentStyle) -> ConvertedMap {
+    let mut converted = ConvertedMap {
+        map: QuakeMap::new(),
+        lossy: Vec::new(),
+        unconverted: Vec::new(),
+    };
+
+    converted.map.entities = map.entities.clone();
+
+    for (ent_idx, entity) in converted.map.entities.iter_mut().enumerate() {
+        for (brush_idx, brush) in entity.brushes.iter_mut().enumerate() {
+            if map.extras().is_brush_def(ent_idx, brush_idx) {
+                continue;
+            }
+
+            let conversions = brush
+                .iter()
+                .map(|surface| convert(surface, style))
+                .collect::<Option<Vec<_>>>();
+
+            let Some(conversions) = conversions else {
+                converted
+                    .unconverted
+                    .push(MapPart::Brush(ent_idx, brush_idx));
+                continue;
+            };
+
+            for (surface_idx, (surface, conversion)) in
+                brush.iter_mut().zip(conversions).enumerate()
+            {
+                match conversion {
+                    Conversion::Exact(alignment) => {
+                        surface.alignment = alignment;
+                    }
+                    Conversion::Lossy(alignment) => {
+                        surface.alignment = alignment;
+                        converted.lossy.push(MapPart::Surface(
+                            ent_idx,
+                            brush_idx,
+                            surface_idx,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    converted
+}
+
+/// What `convert_map` made of a map
+pub struct ConvertedMap {
+    pub map: QuakeMap,
+    /// Surfaces whose texture may have moved
+    pub lossy: Vec<MapPart>,
+    /// Brushes left in their old style
+    pub unconverted: Vec<MapPart>,
+}
+
+pub fn same_alignment(a: &Alignment, b: &Alignment) -> bool {
+    a.offset == b.offset
+        && a.rotation == b.rotation
+        && a.scale == b.scale
+        && a.axes == b.axes
+}
+
+fn to_valve(alignment: &Alignment, plane: &Plane) -> Alignment {
+    let [mut u_axis, mut v_axis] = base_axes(plane);
+    let (s, t) = (nonzero_axis(u_axis), nonzero_axis(v_axis));
+
+    let (sin, cos) = match alignment.rotation {
+        0.0 => (0.0, 1.0),
+        90.0 => (1.0, 0.0),
+        180.0 => (0.0, -1.0),
+        270.0 => (-1.0, 0.0),
+        rotation => rotation.to_radians().sin_cos(),
+    };
+
+    for axis in [&mut u_axis, &mut v_axis] {
+        let (a, b) = (axis[s], axis[t]);
+        axis[s] = tidy(cos * a - sin * b);
+        axis[t] = tidy(sin * a + cos * b);
+    }
+
+    Alignment {
+        axes: Some([u_axis, v_axis]),
+        ..*alignment
+    }
+}
+
+fn to_standard(alignment: &Alignment, plane: &Plane) -> Conversion {
+    let Some([u_axis, v_axis]) = alignment.axes else {
+        return Conversion::Exact(*alignment);
+    };
+
+    let [base_u, base_v] = base_axes(plane);
+    let (s, t) = (nonzero_axis(base_u), nonzero_axis(base_v));
+    let depth = 3 - s - t;
+    let normal = plane.normal;
+
+    // texture coordinates change by the same amount for every unit moved
+    // along the plane's projection onto the base axes
+    let flatten = |axis: Vec3, scale: f64| -> ([f64; 2], f64) {
+        let axis = axis.map(|coord| coord / nonzero_scale(scale));
+        let depth_ratio = axis[depth] / normal[depth];
+
+        let flat = [
+            axis[s] - depth_ratio * normal[s],
+            axis[t] - depth_ratio * normal[t],
+        ];
+
+        (flat, depth_ratio * plane.dist)
+    };
+
+    let (u_flat, u_shift) = flatten(u_axis, alignment.scale[0]);
+    let (v_flat, v_shift) = flatten(v_axis, alignment.scale[1]);
+    let u_length = u_flat[0].hypot(u_flat[1]);
+
+    if u_length < EPSILON || v_flat[0].hypot(v_flat[1]) < EPSILON {
+        return Conversion::Lossy(Alignment {
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            axes: None,
+            ..*alignment
+        });
+    }
+
+    // the rotated base U axis runs along the flattened U axis
+    let cos = base_u[s] * u_flat[0] / u_length;
+    let sin = base_u[s] * u_flat[1] / u_length;
+
+    // what of the V axis runs along the base V axis once rotated, and what
+    // is left over, which standard alignment cannot give
+    let v_along = -sin * v_flat[0] + cos * v_flat[1];
+    let v_across = cos * v_flat[0] + sin * v_flat[1];
+
+    let rotation = sin.atan2(cos).to_degrees().rem_euclid(360.0);
+
+    let standard = Alignment {
+        offset: [
+            tidy(alignment.offset[0] + u_shift),
+            tidy(alignment.offset[1] + v_shift),
+        ],
+        rotation: tidy(rotation) % 360.0,
+        scale: [tidy(1.0 / u_length), tidy(base_v[t] / v_along)],
+        axes: None,
+    };
+
+    if v_across.abs() > EPSILON || v_along.abs() < EPSILON {
+        Conversion::Lossy(standard)
+    } else {
+        Conversion::Exact(standard)
+    }
+}
+
+/// Texture axes standard alignment projects a surface onto, the first
+/// listed for the direction the plane faces most
+fn base_axes(plane: &Plane) -> [Vec3; 2] {
+    let mut best = &BASE_AXES[0];
+    let mut best_dot = 0.0;
+
+    for axes in &BASE_AXES {
+        let facing = dot(plane.normal, axes[0]);
+
+        if facing > best_dot {
+            best = axes;
+            best_dot = facing;
+        }
+    }
+
+    [best[1], best[2]]
+}
+
+/// Index of the only coordinate of a base axis that is not zero
+fn nonzero_axis(axis: Vec3) -> usize {
+    axis.iter().position(|&coord| coord != 0.0).unwrap()
+}
+
+/// Scale as compilers use it, which treats zero as one
+fn nonzero_scale(scale: f64) -> f64 {
+    if scale == 0.0 {
+        1.0
+    } else {
+        scale
+    }
+}
+
+/// Round away error from the arithmetic for numbers that are meant to be
+/// integers
+fn tidy(number: f64) -> f64 {
+    let rounded = number.round();
+
+    if (number - rounded).abs() < ROUNDING {
+        rounded + 0.0
+    } else {
+        number
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use quake_util::qmap::{Alignment, Surface};
+
+use crate::alignment::{
+    convert, convert_map, same_alignment, AlignmentStyle, Conversion,
+};
+use crate::map_data::MapPart;
+use crate::pipeline::Processed;
+use crate::reader::read_map;
+use crate::realign;
+
+const FLOOR: [[f64; 3]; 3] =
+    [[16.0, 16.0, 16.0], [16.0, 17.0, 16.0], [17.0, 16.0, 16.0]];
+
+const EAST_WALL: [[f64; 3]; 3] =
+    [[64.0, 16.0, 16.0], [64.0, 16.0, 17.0], [64.0, 17.0, 16.0]];
+
+/// Plane rising one unit for every two units along Y, facing mostly up
+const SLOPE: [[f64; 3]; 3] =
+    [[1.0, 0.0, 8.0], [0.0, 0.0, 8.0], [0.0, 2.0, 9.0]];
+
+/// Cube with a surface whose Valve220 axes are not at right angles
+const CUBE: &str = "\
+{
+\"classname\" \"worldspawn\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 30 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base [ 1 0 0 0 ] [ 1 -1 0 0 ] 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+}
+";
+
+fn surface(half_space: [[f64; 3]; 3], alignment: Alignment) -> Surface {
+    Surface {
+        half_space,
+        texture: c"base".into(),
+        alignment,
+    }
+}
+
+fn standard(offset: [f64; 2], rotation: f64, scale: [f64; 2]) -> Alignment {
+    Alignment {
+        offset,
+        rotation,
+        scale,
+        axes: None,
+    }
+}
+
+fn valve(axes: [[f64; 3]; 2], offset: [f64; 2]) -> Alignment {
+    Alignment {
+        offset,
+        rotation: 0.0,
+        scale: [1.0, 1.0],
+        axes: Some(axes),
+    }
+}
+
+fn exact(conversion: Option<Conversion>) -> Alignment {
+    match conversion {
+        Some(Conversion::Exact(alignment)) => alignment,
+        Some(Conversion::Lossy(_)) => panic!("conversion was lossy"),
+        None => panic!("surface has no plane"),
+    }
+}
+
+#[test]
+fn standard_alignment_gets_rotated_axes() {
+    let wall = surface(EAST_WALL, standard([8.0, 4.0], 90.0, [2.0, 0.5]));
+    let converted = exact(convert(&wall, AlignmentStyle::Valve220));
+
+    assert_eq!(converted.axes, Some([[0.0, 0.0, 1.0], [0.0, 1.0, 0.0]]));
+    assert_eq!(converted.offset, [8.0, 4.0]);
+    assert_eq!(converted.scale, [2.0, 0.5]);
+
+    let floor = surface(FLOOR, standard([0.0, 0.0], 0.0, [1.0, 1.0]));
+    let converted = exact(convert(&floor, AlignmentStyle::Valve220));
+
+    assert_eq!(converted.axes, Some([[1.0, 0.0, 0.0], [0.0, -1.0, 0.0]]));
+}
+
+#[test]
+fn valve_axes_along_base_axes_convert_back_exactly() {
+    let wall = surface(EAST_WALL, standard([8.0, 4.0], 90.0, [2.0, 0.5]));
+    let valve = exact(convert(&wall, AlignmentStyle::Valve220));
+    let back = exact(convert(
+        &surface(EAST_WALL, valve),
+        AlignmentStyle::Standard,
+    ));
+
+    assert_eq!(back.axes, None);
+    assert_eq!(back.offset, [8.0, 4.0]);
+    assert_eq!(back.rotation, 90.0);
+    assert_eq!(back.scale, [2.0, 0.5]);
+}
+
+#[test]
+fn sloped_surfaces_keep_their_texture_coordinates() {
+    let down_slope = [0.0, -2.0 / 5f64.sqrt(), -1.0 / 5f64.sqrt()];
+    let alignment = valve([[1.0, 0.0, 0.0], down_slope], [4.0, 8.0]);
+    let slope = surface(SLOPE, alignment);
+    let converted = exact(convert(&slope, AlignmentStyle::Standard));
+
+    assert_eq!(converted.rotation, 0.0);
+    assert_eq!(converted.scale[0], 1.0);
+
+    // a floor's standard projection runs U along X and V down Y
+    for point in [[0.0, 0.0, 8.0], [3.0, 4.0, 10.0], [-5.0, -6.0, 5.0]] {
+        let dot = |axis: [f64; 3]| {
+            point.iter().zip(axis).map(|(a, b)| a * b).sum::<f64>()
+        };
+
+        let s = point[0] / converted.scale[0] + converted.offset[0];
+        let t = -point[1] / converted.scale[1] + converted.offset[1];
+
+        assert!((s - (dot([1.0, 0.0, 0.0]) + 4.0)).abs() < 1e-9);
+        assert!((t - (dot(down_slope) + 8.0)).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn sheared_axes_are_lossy() {
+    let half = 0.5f64.sqrt();
+    let alignment = valve([[1.0, 0.0, 0.0], [half, -half, 0.0]], [0.0, 0.0]);
+    let floor = surface(FLOOR, alignment);
+
+    assert!(matches!(
+        convert(&floor, AlignmentStyle::Standard),
+        Some(Conversion::Lossy(_))
+    ));
+}
+
+#[test]
+fn whole_maps_are_converted() {
+    let map = read_map("cube.map".into(), CUBE.into()).unwrap();
+
+    let converted = convert_map(&map, AlignmentStyle::Valve220);
+    let brush = &converted.map.entities[0].brushes[0];
+    assert!(converted.lossy.is_empty());
+    assert!(brush.iter().all(|surface| surface.alignment.axes.is_some()));
+
+    let converted = convert_map(&map, AlignmentStyle::Standard);
+    let brush = &converted.map.entities[0].brushes[0];
+    assert_eq!(converted.lossy, [MapPart::Surface(0, 0, 3)]);
+    assert!(converted.unconverted.is_empty());
+    assert!(brush.iter().all(|surface| surface.alignment.axes.is_none()));
+}
+
+#[test]
+fn brushes_with_degenerate_surfaces_are_left_alone() {
+    let text = CUBE.replace(
+        "( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 )",
+        "( 16 16 16 ) ( 17 16 16 ) ( 18 16 16 )",
+    );
+
+    let map = read_map("cube.map".into(), text.into()).unwrap();
+    let converted = convert_map(&map, AlignmentStyle::Valve220);
+
+    assert_eq!(converted.unconverted, [MapPart::Brush(0, 0)]);
+    assert!(converted.lossy.is_empty());
+
+    let brush = &converted.map.entities[0].brushes[0];
+    let original = &map.entities[0].brushes[0];
+
+    assert!(brush.iter().zip(original).all(|(surface, original)| {
+        same_alignment(&surface.alignment, &original.alignment)
+    }));
+}
+
+#[test]
+fn realigned_maps_keep_their_locations() {
+    let map = read_map("cube.map".into(), CUBE.into()).unwrap();
+
+    let mut processed = Processed {
+        map: Arc::new(map),
+        origins: Vec::new(),
+        diff: String::new(),
+    };
+
+    let mut diagnostics = Vec::new();
+    realign(
+        &mut processed,
+        AlignmentStyle::Standard,
+        false,
+        &mut diagnostics,
+    );
+
+    let location = "cube.map:7: worldspawn brush 0 surface 3";
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].location.as_deref(), Some(location));
+    assert_eq!(processed.map.location(MapPart::Surface(0, 0, 3)), location);
+}
+
+#[test]
+fn realigning_is_described_after_plugin_changes() {
+    let map = read_map("cube.map".into(), CUBE.into()).unwrap();
+
+    let mut processed = Processed {
+        map: Arc::new(map),
+        origins: Vec::new(),
+        diff: "plugin changed worldspawn\n".to_string(),
+    };
+
+    realign(
+        &mut processed,
+        AlignmentStyle::Valve220,
+        true,
+        &mut Vec::new(),
+    );
+
+    assert!(processed.diff.starts_with("plugin changed worldspawn\n"));
+    assert!(processed
+        .diff
+        .contains("  ~ brush 0 surface 0 alignment standard -> valve220\n"));
+}
@@ -0,0 +1,211 @@
+use std::io::Write;
+
+use crate::map_data::MapData;
+use crate::patch::EntityOrigin;
+use crate::reader::read_ent;
+use crate::writer::write_ent;
+
+/// Size of a lump's entry in the header: its offset and length
+const LUMP_ENTRY_SIZE: usize = 8;
+/// Alignment of lumps within the file
+const LUMP_ALIGNMENT: usize = 4;
+
+/// A compiled BSP file, kept whole so its entity lump can be replaced
+///
+/// Quake and Half-Life BSPs, including the BSP2 variants, and Quake 2 and 3
+/// BSPs are understood.  Each starts with a directory of lumps whose first
+/// entry is the entity lump, the entities' keys and values in the `.map`
+/// syntax followed by a nul.
+pub struct BspFile {
+    data: Vec<u8>,
+    /// Offset of the lump directory
+    directory: usize,
+    lump_count: usize,
+}
+
+impl BspFile {
+    /// Check the header of a BSP, `name` being used in error messages
+    pub fn parse(name: &str, data: Vec<u8>) -> anyhow::Result<Self> {
+        let (directory, lump_count) = match data.first_chunk::<8>() {
+            Some(
+                [b'B', b'S', b'P', b'2', ..] | [b'2', b'P', b'S', b'B', ..],
+            ) => (4, 15),
+            Some([b'I', b'B', b'S', b'P', version @ ..]) => {
+                match i32::from_le_bytes(*version) {
+                    38 => (8, 19),
+                    46 | 47 => (8, 17),
+                    version => {
+                        return Err(anyhow::anyhow!(
+                            "{}: Unsupported IBSP version {}",
+                            name,
+                            version
+                        ));
+                    }
+                }
+            }
+            Some([version @ .., _, _, _, _]) => {
+                match i32::from_le_bytes(*version) {
+                    29 | 30 => (4, 15),
+                    version => {
+                        return Err(anyhow::anyhow!(
+                            "{}: Unsupported BSP version {}",
+                            name,
+                            version
+                        ));
+                    }
+                }
+            }
+            None => {
+                return Err(anyhow::anyhow!("{}: Too short to be a BSP", name));
+            }
+        };
+
+        let bsp = Self {
+            data,
+            directory,
+            lump_count,
+        };
+
+        let header_end = directory + lump_count * LUMP_ENTRY_SIZE;
+
+        if bsp.data.len() < header_end {
+            return Err(anyhow::anyhow!("{}: Truncated BSP header", name));
+        }
+
+        for lump_idx in 0..lump_count {
+            let (offset, length) = bsp.lump(lump_idx).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}: Lump {} has a negative size",
+                    name,
+                    lump_idx
+                )
+            })?;
+
+            if offset + length > bsp.data.len() {
+                return Err(anyhow::anyhow!(
+                    "{}: Lump {} lies outside the file",
+                    name,
+                    lump_idx
+                ));
+            }
+        }
+
+        if bsp.lump(0).is_some_and(|(offset, _)| offset < header_end) {
+            return Err(anyhow::anyhow!(
+                "{}: Entity lump overlaps the header",
+                name
+            ));
+        }
+
+        Ok(bsp)
+    }
+
+    /// Text of the entity lump, without the nuls that end it
+    pub fn entities(&self) -> &[u8] {
+        let (offset, length) = self.lump(0).unwrap();
+        let lump = &self.data[offset..offset + length];
+        let end = lump
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        &lump[..end]
+    }
+
+    /// The whole file with the entity lump replaced by `text`
+    ///
+    /// Lumps following the entity lump are moved to make room, keeping
+    /// their alignment.
+    pub fn with_entities(&self, text: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (offset, length) = self.lump(0).unwrap();
+        let old_end = offset + length;
+
+        let mut lump = text.to_vec();
+        lump.push(0);
+        let lump_length = lump.len();
+
+        let aligned =
+            |end: usize| end % LUMP_ALIGNMENT == old_end % LUMP_ALIGNMENT;
+
+        while !aligned(offset + lump.len()) {
+            lump.push(0);
+        }
+
+        let shift = |at: usize| at + offset + lump.len() - old_end;
+
+        let mut data = Vec::with_capacity(shift(self.data.len()));
+        data.extend(&self.data[..offset]);
+        data.extend(&lump);
+        data.extend(&self.data[old_end..]);
+
+        self.set_lump_entry(&mut data, 0, offset, lump_length)?;
+
+        for lump_idx in 1..self.lump_count {
+            let (lump_offset, lump_length) = self.lump(lump_idx).unwrap();
+
+            if lump_offset >= old_end {
+                self.set_lump_entry(
+                    &mut data,
+                    lump_idx,
+                    shift(lump_offset),
+                    lump_length,
+                )?;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Offset and length of a lump, or `None` if either is negative
+    fn lump(&self, lump_idx: usize) -> Option<(usize, usize)> {
+        let entry = self.directory + lump_idx * LUMP_ENTRY_SIZE;
+        let field = |at: usize| {
+            let bytes = self.data[at..at + 4].try_into().unwrap();
+            usize::try_from(i32::from_le_bytes(bytes)).ok()
+        };
+
+        Some((field(entry)?, field(entry + 4)?))
+    }
+
+    fn set_lump_entry(
+        &self,
+        data: &mut [u8],
+        lump_idx: usize,
+        offset: usize,
+        length: usize,
+    ) -> anyhow::Result<()> {
+        let entry = self.directory + lump_idx * LUMP_ENTRY_SIZE;
+
+        for (at, value) in [(entry, offset), (entry + 4, length)] {
+            let value = i32::try_from(value).map_err(|_| {
+                anyhow::anyhow!("Lump {} would not fit in a BSP", lump_idx)
+            })?;
+
+            data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the entities of a compiled BSP, which have no brushes, keeping the
+/// rest of the file to write back
+pub fn read_bsp(name: String, data: Vec<u8>) -> anyhow::Result<MapData> {
+    let bsp = BspFile::parse(&name, data)?;
+    let map = read_ent(name, bsp.entities().to_vec())?;
+    Ok(map.with_bsp(bsp))
+}
+
+/// Write `bsp` with its entity lump holding the entities of `map`, as
+/// `write_ent` would write them
+pub fn write_bsp(
+    writer: &mut impl Write,
+    bsp: &BspFile,
+    map: &MapData,
+    original: Option<&MapData>,
+    origins: Option<&[Option<EntityOrigin>]>,
+) -> anyhow::Result<()> {
+    let mut text = Vec::new();
+    write_ent(&mut text, map, original, origins)?;
+    writer.write_all(&bsp.with_entities(&text)?)?;
+    Ok(())
+}
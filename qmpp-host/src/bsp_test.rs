@@ -0,0 +1,110 @@
+use crate::bsp::{read_bsp, write_bsp, BspFile};
+
+const ENTITIES: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"gfx.wad\"
+}
+{
+\"model\" \"*1\"
+\"classname\" \"func_door\"
+}
+";
+
+/// Planes lump, standing for everything after the entities
+const PLANES: &[u8] = b"0123456789abcdef";
+
+const HEADER_SIZE: usize = 4 + 15 * 8;
+
+/// Quake BSP with the entity lump, then the planes lump, then nothing
+fn quake_bsp(entities: &[u8]) -> Vec<u8> {
+    let mut lump = entities.to_vec();
+    lump.push(0);
+
+    lump.resize(lump.len().next_multiple_of(4), 0);
+
+    let planes_offset = HEADER_SIZE + lump.len();
+    let end = planes_offset + PLANES.len();
+    let mut data = 29i32.to_le_bytes().to_vec();
+
+    for (offset, length) in [
+        (HEADER_SIZE, entities.len() + 1),
+        (planes_offset, PLANES.len()),
+    ] {
+        data.extend(&(offset as i32).to_le_bytes());
+        data.extend(&(length as i32).to_le_bytes());
+    }
+
+    for _ in 2..15 {
+        data.extend(&(end as i32).to_le_bytes());
+        data.extend(&0i32.to_le_bytes());
+    }
+
+    data.extend(lump);
+    data.extend(PLANES);
+    data
+}
+
+fn lump(data: &[u8], lump_idx: usize) -> &[u8] {
+    let entry = 4 + lump_idx * 8;
+    let field = |at: usize| {
+        i32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize
+    };
+
+    let offset = field(entry);
+    &data[offset..offset + field(entry + 4)]
+}
+
+#[test]
+fn entities_are_read_without_brushes() {
+    let map =
+        read_bsp("e1m1.bsp".into(), quake_bsp(ENTITIES.as_bytes())).unwrap();
+
+    assert_eq!(map.entities.len(), 2);
+    assert!(map.entities.iter().all(|entity| entity.brushes.is_empty()));
+    assert_eq!(map.entities[1].edict[c"model"].as_c_str(), c"*1");
+    assert_eq!(map.bsp().unwrap().entities(), ENTITIES.as_bytes());
+}
+
+#[test]
+fn resized_entity_lumps_move_later_lumps() {
+    let data = quake_bsp(ENTITIES.as_bytes());
+    let bsp = BspFile::parse("e1m1.bsp", data.clone()).unwrap();
+
+    for text in [&ENTITIES[..ENTITIES.len() - 6], ENTITIES, "{\n}\n"] {
+        let written = bsp.with_entities(text.as_bytes()).unwrap();
+        let reread = BspFile::parse("e1m1.bsp", written.clone()).unwrap();
+
+        assert_eq!(reread.entities(), text.as_bytes());
+        assert_eq!(lump(&written, 1), PLANES);
+        assert_eq!(&written[..4], &data[..4]);
+    }
+
+    let unchanged = bsp.with_entities(ENTITIES.as_bytes()).unwrap();
+    assert!(unchanged == data);
+}
+
+#[test]
+fn processed_entities_are_written_back() {
+    let map =
+        read_bsp("e1m1.bsp".into(), quake_bsp(ENTITIES.as_bytes())).unwrap();
+
+    let mut out = Vec::new();
+    write_bsp(&mut out, map.bsp().unwrap(), &map, Some(&map), None).unwrap();
+    assert!(out == quake_bsp(ENTITIES.as_bytes()));
+}
+
+#[test]
+fn unknown_and_broken_bsps_are_rejected() {
+    let mut data = quake_bsp(ENTITIES.as_bytes());
+    data[..4].copy_from_slice(&28i32.to_le_bytes());
+
+    let e = BspFile::parse("old.bsp", data).err().unwrap();
+    assert_eq!(e.to_string(), "old.bsp: Unsupported BSP version 28");
+
+    let mut data = quake_bsp(ENTITIES.as_bytes());
+    data.truncate(data.len() - 1);
+
+    let e = BspFile::parse("cut.bsp", data).err().unwrap();
+    assert_eq!(e.to_string(), "cut.bsp: Lump 1 lies outside the file");
+}
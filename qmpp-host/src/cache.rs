@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use sha2::{Digest, Sha256};
+
+use wasmtime::{Engine, Module};
+
+const CACHE_EXTENSION: &str = "cwasm";
+const TMP_EXTENSION: &str = "tmp";
+const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+/// On-disk cache of compiled plugin modules
+///
+/// Entries are keyed by a hash of the wasm bytes and the engine
+/// configuration, so changing either results in a recompile.
+pub struct ModuleCache {
+    dir: PathBuf,
+    engine: Engine,
+    /// Fingerprint of `engine`, computed on the first load
+    fingerprint: OnceLock<Vec<u8>>,
+}
+
+impl ModuleCache {
+    /// Cache of modules compiled by `engine`
+    pub fn new(dir: PathBuf, engine: &Engine) -> Self {
+        Self {
+            dir,
+            engine: engine.clone(),
+            fingerprint: OnceLock::new(),
+        }
+    }
+
+    /// Remove every cache entry, along with any temporary files left behind
+    /// by runs that stopped while storing one
+    pub fn clear(&self) -> io::Result<()> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(());
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            let extension = path.extension().and_then(|ext| ext.to_str());
+
+            if extension == Some(CACHE_EXTENSION)
+                || extension == Some(TMP_EXTENSION)
+            {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load a module from the cache, compiling and storing it on a miss
+    ///
+    /// A cache entry that fails to deserialize (e.g. one written by a
+    /// different version of wasmtime) is treated as a miss and overwritten.
+    pub fn load(&self, wasm_path: &Path) -> anyhow::Result<Module> {
+        let wasm = fs::read(wasm_path).map_err(|e| {
+            anyhow::anyhow!("Failed to read {}: {}", wasm_path.display(), e)
+        })?;
+
+        let entry_path = self.entry_path(&wasm)?;
+
+        if entry_path.is_file() {
+            // SAFETY: cache entries are only ever written by `store` below
+            // from the output of `Module::serialize`
+            match unsafe { Module::deserialize_file(&self.engine, &entry_path) }
+            {
+                Ok(module) => {
+                    return Ok(module);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Discarding cache entry {}: {}",
+                        entry_path.display(),
+                        e
+                    );
+                }
+            }
+        }
+
+        let module = Module::new(&self.engine, &wasm)?;
+
+        if let Err(e) = self.store(&entry_path, &module) {
+            eprintln!(
+                "Failed to write cache entry {}: {}",
+                entry_path.display(),
+                e
+            );
+        }
+
+        Ok(module)
+    }
+
+    fn store(&self, entry_path: &Path, module: &Module) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        // write to a temporary file first so concurrent runs never observe a
+        // partially written entry
+        let tmp_path = entry_path.with_extension(format!(
+            "{}.{}.{}",
+            CACHE_EXTENSION,
+            std::process::id(),
+            TMP_EXTENSION
+        ));
+
+        fs::write(&tmp_path, module.serialize()?)?;
+        fs::rename(&tmp_path, entry_path)?;
+        Ok(())
+    }
+
+    fn entry_path(&self, wasm: &[u8]) -> anyhow::Result<PathBuf> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.fingerprint()?);
+        hasher.update(wasm);
+
+        let name = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        Ok(self.dir.join(name).with_extension(CACHE_EXTENSION))
+    }
+
+    fn fingerprint(&self) -> anyhow::Result<&[u8]> {
+        if let Some(fingerprint) = self.fingerprint.get() {
+            return Ok(fingerprint);
+        }
+
+        let fingerprint = engine_fingerprint(&self.engine)?;
+        Ok(self.fingerprint.get_or_init(|| fingerprint))
+    }
+}
+
+// Serialized modules embed the wasmtime version and every compiler setting
+// that affects code generation, so an empty module serves as a stable
+// fingerprint of the engine configuration.
+fn engine_fingerprint(engine: &Engine) -> anyhow::Result<Vec<u8>> {
+    Module::new(engine, EMPTY_MODULE)?.serialize()
+}
+
+/// Compile a module, going through the cache if one is provided
+///
+/// The cache must be one for `engine`.
+pub fn load_module(
+    engine: &Engine,
+    wasm_path: &Path,
+    cache: Option<&ModuleCache>,
+) -> anyhow::Result<Module> {
+    match cache {
+        Some(cache) => cache.load(wasm_path),
+        None => Module::from_file(engine, wasm_path),
+    }
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir).join("qmpp")
+    } else if let Some(home) = std::env::var_os("HOME") {
+        PathBuf::from(home).join(".cache").join("qmpp")
+    } else {
+        std::env::temp_dir().join("qmpp-cache")
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use wasmtime::Engine;
+
+use crate::cache::ModuleCache;
+
+const PLUGIN: &str = r#"(module (func (export "QMPP_Hook_init")))"#;
+const CHANGED_PLUGIN: &str = r#"(module (func (export "QMPP_Hook_process")))"#;
+
+fn entries(dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let mut entries = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let modified = fs::metadata(&path).unwrap().modified().unwrap();
+            (path, modified)
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort();
+    entries
+}
+
+#[test]
+fn modules_are_cached_by_contents() {
+    let dir = std::env::temp_dir()
+        .join(format!("qmpp-cache-test-{}", std::process::id()));
+
+    let cache_dir = dir.join("cache");
+    let wasm_path = dir.join("plugin.wasm");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&wasm_path, PLUGIN).unwrap();
+
+    let engine = Engine::default();
+    let cache = ModuleCache::new(cache_dir.clone(), &engine);
+
+    let module = cache.load(&wasm_path).unwrap();
+    assert!(module.get_export("QMPP_Hook_init").is_some());
+    let stored = entries(&cache_dir);
+    assert_eq!(stored.len(), 1);
+
+    // a hit reads the entry without writing it again
+    cache.load(&wasm_path).unwrap();
+    assert_eq!(entries(&cache_dir), stored);
+
+    // touching the plugin leaves the entry for its contents in place
+    let later = SystemTime::now() + Duration::from_secs(10);
+    let file = File::options().write(true).open(&wasm_path).unwrap();
+    file.set_modified(later).unwrap();
+
+    cache.load(&wasm_path).unwrap();
+    assert_eq!(entries(&cache_dir), stored);
+
+    // changed contents miss the cache and get an entry of their own
+    fs::write(&wasm_path, CHANGED_PLUGIN).unwrap();
+    let module = cache.load(&wasm_path).unwrap();
+    assert!(module.get_export("QMPP_Hook_process").is_some());
+    assert_eq!(entries(&cache_dir).len(), 2);
+
+    // as are temporary files left by a run that stopped while storing one
+    let stored = entries(&cache_dir);
+    let tmp_path = stored[0].0.with_extension("cwasm.12345.tmp");
+    fs::write(&tmp_path, b"partial").unwrap();
+    assert_eq!(entries(&cache_dir).len(), 3);
+
+    cache.clear().unwrap();
+    assert!(entries(&cache_dir).is_empty());
+
+    // clearing a cache that was never written is not an error
+    ModuleCache::new(dir.join("missing"), &engine)
+        .clear()
+        .unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
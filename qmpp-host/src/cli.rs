@@ -0,0 +1,420 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::alignment::AlignmentStyle;
+use crate::cache::default_cache_dir;
+use crate::config::{Config, CONFIG_FILE};
+use crate::logging::LogLevel;
+
+const DEFAULT_MAP_PATH: &str = "qmpp-host/test-res/q25_limits_4lt.map";
+const DEFAULT_PLUGIN_PATH: &str =
+    "target/wasm32-unknown-unknown/release/hello.wasm";
+
+pub const USAGE: &str = "\
+Usage: qmpp-host [OPTIONS] [MAP]...
+
+Each MAP may be a directory, standing for the .map files in it, or a glob
+pattern such as maps/e1*.map.  Maps are processed in parallel.  Maps whose
+names end in .json, whether read or written, are in qmpp's JSON form, and
+those ending in .ent hold entities without brushes.  A compiled .bsp MAP has
+its entities processed, without brushes, and is written back whole with the
+new entities if the output ends in .bsp.
+
+Options:
+  --config <FILE>      Read the pipeline from a configuration file, by default
+                       qmpp.toml if there is one; options given here take
+                       precedence
+  --plugin <WASM>      Plugin module to run, may be repeated, replacing the
+                       plugins from the configuration
+  --plugin-option <PLUGIN>.<KEY>=<VALUE>
+                       Set an option of a plugin, named by its file stem,
+                       may be repeated
+  -o, --output <MAP>   Write the processed map, keeping the input's comments
+                       and formatting wherever plugins left it unchanged
+  -O, --output-dir <DIR>
+                       Write each processed map to a directory, under the
+                       name of its input
+  --reformat           Write the whole output map afresh
+  --alignment <STYLE>  Convert the texture alignment of every surface in the
+                       output map to standard or valve220, warning about
+                       surfaces that cannot keep their texture in place
+  --dry-run            Print what plugins would change instead of writing the
+                       output map
+  -w, --watch          Run again whenever a map, a plugin or the configuration
+                       changes, compiling only the plugins that changed
+  -j, --jobs <N>       Number of maps, and of read-only plugins working on
+                       the same map, to process at once
+  --downgrade <CODE>   Treat errors reported with a code matching the glob
+                       pattern as warnings, may be repeated
+  --diagnostics-json <FILE>
+                       Write every diagnostic plugins report to a JSON file
+  --report <FILE>      Write a JSON report of the plugins run, the time spent
+                       in their hooks, what they changed and what they
+                       reported, as an array if there are several maps
+  --log-level <LEVEL>  Least severe plugin log messages to show: trace,
+                       debug, info (the default), warn or error
+  --plugin-log-level <PLUGIN>=<LEVEL>
+                       Log level for one plugin, named by its file stem,
+                       may be repeated
+  --log-timestamps     Start each log message with the time in UTC
+  --log-file <FILE>    Write plugin log messages to a file instead
+  --cache-dir <DIR>    Directory for compiled plugin modules
+  --no-cache           Always compile plugins, bypassing the cache
+  --clear-cache        Remove all compiled modules from the cache
+  --list-imports       List the functions the host provides to plugins
+  -h, --help           Print this message";
+
+pub struct Options {
+    /// Configuration file named by `--config`
+    pub config_path: Option<PathBuf>,
+    /// Maps, directories or glob patterns given by the user
+    pub map_paths: Vec<PathBuf>,
+    pub plugins: Vec<PluginSpec>,
+    pub output_path: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub reformat: bool,
+    /// Style to convert the output map's texture alignment to
+    pub alignment: Option<AlignmentStyle>,
+    pub dry_run: bool,
+    pub watch: bool,
+    pub report_path: Option<PathBuf>,
+    pub jobs: usize,
+    pub cache: CacheOptions,
+    pub diagnostics: DiagnosticOptions,
+    pub log: LogOptions,
+}
+
+pub struct PluginSpec {
+    pub path: PathBuf,
+    /// Settings the plugin can read by key
+    pub options: BTreeMap<String, String>,
+}
+
+impl PluginSpec {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            options: BTreeMap::new(),
+        }
+    }
+
+    /// Name of the plugin's file without its extension
+    pub fn file_stem(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+pub struct CacheOptions {
+    pub enabled: bool,
+    pub clear: bool,
+    pub dir: PathBuf,
+}
+
+pub struct DiagnosticOptions {
+    /// Glob patterns of codes whose errors are reported as warnings
+    pub downgrade: Vec<String>,
+    pub json_path: Option<PathBuf>,
+}
+
+pub struct LogOptions {
+    pub level: LogLevel,
+    /// Levels for plugins named by their file stems, overriding `level`
+    pub plugin_levels: Vec<(String, LogLevel)>,
+    pub timestamps: bool,
+    pub file_path: Option<PathBuf>,
+}
+
+pub enum Command {
+    Run(Box<Options>),
+    ListImports,
+    Help,
+}
+
+pub fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> anyhow::Result<Command> {
+    let mut config_path = None;
+    let mut map_paths = Vec::new();
+    let mut plugin_paths = Vec::new();
+    let mut plugin_options = Vec::new();
+    let mut plugin_levels = Vec::new();
+    let mut output_path = None;
+    let mut output_dir = None;
+    let mut reformat = false;
+    let mut alignment = None;
+    let mut dry_run = false;
+    let mut watch = false;
+    let mut report_path = None;
+    let mut jobs = None;
+    let mut cache = CacheOptions {
+        enabled: true,
+        clear: false,
+        dir: default_cache_dir(),
+    };
+    let mut downgrade = Vec::new();
+    let mut diagnostics_json = None;
+    let mut log_level = None;
+    let mut log_timestamps = false;
+    let mut log_file = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Command::Help);
+            }
+            "--list-imports" => {
+                return Ok(Command::ListImports);
+            }
+            "--config" => {
+                config_path = Some(expect_value(&mut args, &arg)?.into());
+            }
+            "--plugin" => {
+                plugin_paths.push(expect_value(&mut args, &arg)?.into());
+            }
+            "--plugin-option" => {
+                let value = expect_value(&mut args, &arg)?;
+
+                let parsed = value.split_once('=').and_then(|(name, val)| {
+                    let (plugin, key) = name.split_once('.')?;
+                    Some((plugin.to_string(), key.to_string(), val.to_string()))
+                });
+
+                plugin_options.push(parsed.ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Expected <PLUGIN>.<KEY>=<VALUE> but got \"{}\"",
+                        value
+                    )
+                })?);
+            }
+            "-o" | "--output" => {
+                output_path = Some(expect_value(&mut args, &arg)?.into());
+                output_dir = None;
+            }
+            "-O" | "--output-dir" => {
+                output_dir = Some(expect_value(&mut args, &arg)?.into());
+                output_path = None;
+            }
+            "--reformat" => {
+                reformat = true;
+            }
+            "--alignment" => {
+                alignment = Some(expect_value(&mut args, &arg)?.parse()?);
+            }
+            "--dry-run" => {
+                dry_run = true;
+            }
+            "-w" | "--watch" => {
+                watch = true;
+            }
+            "-j" | "--jobs" => {
+                let value = expect_value(&mut args, &arg)?;
+
+                jobs = match value.parse() {
+                    Ok(jobs) if jobs > 0 => Some(jobs),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Invalid job count \"{}\"",
+                            value
+                        ));
+                    }
+                };
+            }
+            "--downgrade" => {
+                downgrade.push(expect_value(&mut args, &arg)?);
+            }
+            "--report" => {
+                report_path = Some(expect_value(&mut args, &arg)?.into());
+            }
+            "--diagnostics-json" => {
+                diagnostics_json = Some(expect_value(&mut args, &arg)?.into());
+            }
+            "--log-level" => {
+                log_level = Some(expect_value(&mut args, &arg)?.parse()?);
+            }
+            "--plugin-log-level" => {
+                let value = expect_value(&mut args, &arg)?;
+
+                let (plugin, level) =
+                    value.split_once('=').ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Expected <PLUGIN>=<LEVEL> but got \"{}\"",
+                            value
+                        )
+                    })?;
+
+                plugin_levels.push((plugin.to_string(), level.parse()?));
+            }
+            "--log-timestamps" => {
+                log_timestamps = true;
+            }
+            "--log-file" => {
+                log_file = Some(expect_value(&mut args, &arg)?.into());
+            }
+            "--cache-dir" => {
+                cache.dir = expect_value(&mut args, &arg)?.into();
+            }
+            "--no-cache" => {
+                cache.enabled = false;
+            }
+            "--clear-cache" => {
+                cache.clear = true;
+            }
+            _ if arg.starts_with('-') => {
+                return Err(anyhow::anyhow!("Unknown option \"{}\"", arg));
+            }
+            _ => {
+                map_paths.push(PathBuf::from(&arg));
+            }
+        }
+    }
+
+    let config = load_config(config_path.clone())?;
+    let mut plugins = Vec::new();
+    let mut config_levels = Vec::new();
+
+    // plugins given here replace the configuration's along with their
+    // settings
+    if plugin_paths.is_empty() {
+        for plugin in config.plugins {
+            let mut spec = PluginSpec::new(plugin.path);
+
+            if let Some(level) = &plugin.log_level {
+                config_levels.push((spec.file_stem(), level.parse()?));
+            }
+
+            spec.options = plugin
+                .options
+                .into_iter()
+                .map(|(key, value)| (key, value.into_string()))
+                .collect();
+
+            plugins.push(spec);
+        }
+    } else {
+        plugins = plugin_paths.into_iter().map(PluginSpec::new).collect();
+    }
+
+    if plugins.is_empty() {
+        plugins.push(PluginSpec::new(DEFAULT_PLUGIN_PATH.into()));
+    }
+
+    for (plugin, key, value) in plugin_options {
+        let mut found = false;
+
+        for spec in &mut plugins {
+            if spec.file_stem() == plugin {
+                spec.options.insert(key.clone(), value.clone());
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(anyhow::anyhow!("No plugin named \"{}\"", plugin));
+        }
+    }
+
+    for (plugin, _) in &plugin_levels {
+        if !plugins.iter().any(|spec| spec.file_stem() == *plugin) {
+            return Err(anyhow::anyhow!("No plugin named \"{}\"", plugin));
+        }
+    }
+
+    if map_paths.is_empty() {
+        map_paths.extend(config.map);
+        map_paths.extend(config.maps);
+    }
+
+    if map_paths.is_empty() {
+        map_paths.push(DEFAULT_MAP_PATH.into());
+    }
+
+    if output_path.is_none() && output_dir.is_none() {
+        output_path = config.output;
+        output_dir = config.output_dir;
+    }
+
+    if output_path.is_some() && output_dir.is_some() {
+        return Err(anyhow::anyhow!(
+            "Only one of an output map and an output directory may be given"
+        ));
+    }
+
+    let alignment = match (alignment, &config.alignment) {
+        (Some(style), _) => Some(style),
+        (None, Some(style)) => Some(style.parse()?),
+        (None, None) => None,
+    };
+
+    let jobs = match (jobs, config.jobs) {
+        (Some(jobs), _) => jobs,
+        (None, Some(0)) => {
+            return Err(anyhow::anyhow!("Invalid job count \"0\""));
+        }
+        (None, Some(jobs)) => jobs,
+        (None, None) => thread::available_parallelism().map_or(1, usize::from),
+    };
+
+    let diagnostics = DiagnosticOptions {
+        downgrade: config
+            .diagnostics
+            .downgrade
+            .into_iter()
+            .chain(downgrade)
+            .collect(),
+        json_path: diagnostics_json.or(config.diagnostics.json),
+    };
+
+    let log_level = match (log_level, &config.log.level) {
+        (Some(level), _) => level,
+        (None, Some(level)) => level.parse()?,
+        (None, None) => LogLevel::Info,
+    };
+
+    let log = LogOptions {
+        level: log_level,
+        plugin_levels: config_levels.into_iter().chain(plugin_levels).collect(),
+        timestamps: log_timestamps || config.log.timestamps,
+        file_path: log_file.or(config.log.file),
+    };
+
+    Ok(Command::Run(Box::new(Options {
+        config_path,
+        map_paths,
+        plugins,
+        output_path,
+        output_dir,
+        reformat: reformat || config.reformat,
+        alignment,
+        dry_run,
+        watch,
+        report_path: report_path.or(config.report),
+        jobs,
+        cache,
+        diagnostics,
+        log,
+    })))
+}
+
+/// Load the configuration named by `--config`, or the default one if it
+/// exists
+fn load_config(config_path: Option<PathBuf>) -> anyhow::Result<Config> {
+    match config_path {
+        Some(path) => Config::load(&path),
+        None if Path::new(CONFIG_FILE).is_file() => {
+            Config::load(Path::new(CONFIG_FILE))
+        }
+        None => Ok(Config::default()),
+    }
+}
+
+fn expect_value(
+    args: &mut impl Iterator<Item = String>,
+    option: &str,
+) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow::anyhow!("Missing value for \"{}\"", option))
+}
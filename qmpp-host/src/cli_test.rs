@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cli::{parse_args, Command, Options};
+use crate::logging::LogLevel;
+
+const CONFIG: &str = r#"
+jobs = 3
+
+[log]
+level = "warn"
+
+[[plugin]]
+path = "plugins/tidy.wasm"
+log_level = "trace"
+
+[plugin.options]
+prefix = "old_"
+"#;
+
+fn run_options(args: &[&str]) -> anyhow::Result<Box<Options>> {
+    match parse_args(args.iter().map(|arg| arg.to_string()))? {
+        Command::Run(options) => Ok(options),
+        _ => Err(anyhow::anyhow!("Expected a run")),
+    }
+}
+
+/// Write the configuration to a file of its own, returning its path
+fn config_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "qmpp-cli-test-{}-{}",
+        name,
+        std::process::id()
+    ));
+
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("qmpp.toml");
+    fs::write(&path, CONFIG).unwrap();
+    path
+}
+
+#[test]
+fn plugin_log_levels_need_a_plugin() {
+    let e =
+        run_options(&["--plugin", "a.wasm", "--plugin-log-level", "b=debug"])
+            .err()
+            .unwrap();
+
+    assert_eq!(e.to_string(), "No plugin named \"b\"");
+
+    let options =
+        run_options(&["--plugin", "a.wasm", "--plugin-log-level", "a=debug"])
+            .unwrap();
+
+    assert_eq!(options.log.plugin_levels, [("a".into(), LogLevel::Debug)]);
+}
+
+#[test]
+fn config_is_read_from_the_parsed_path() {
+    let path = config_file("parsed");
+    let path_arg = path.to_str().unwrap();
+
+    let options = run_options(&["--config", path_arg, "-j", "2"]).unwrap();
+
+    assert_eq!(options.jobs, 2);
+    assert_eq!(options.log.level, LogLevel::Warn);
+    assert_eq!(options.plugins.len(), 1);
+    assert_eq!(options.plugins[0].options["prefix"], "old_");
+    assert_eq!(
+        options.log.plugin_levels,
+        [("tidy".into(), LogLevel::Trace)]
+    );
+
+    // "--config" given as the value of another option names no config
+    let options = run_options(&["--downgrade", "--config", path_arg]).unwrap();
+    assert_eq!(options.diagnostics.downgrade, ["--config"]);
+    assert_eq!(options.map_paths, std::slice::from_ref(&path));
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn plugins_given_replace_configured_settings() {
+    let path = config_file("replaced");
+
+    let options = run_options(&[
+        "--config",
+        path.to_str().unwrap(),
+        "--plugin",
+        "lint.wasm",
+    ])
+    .unwrap();
+
+    assert_eq!(options.plugins.len(), 1);
+    assert_eq!(options.plugins[0].path, PathBuf::from("lint.wasm"));
+    assert!(options.plugins[0].options.is_empty());
+    assert!(options.log.plugin_levels.is_empty());
+    assert_eq!(options.log.level, LogLevel::Warn);
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
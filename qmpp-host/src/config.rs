@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Name of the configuration file used when none is given
+pub const CONFIG_FILE: &str = "qmpp.toml";
+
+/// Pipeline described by a project's configuration file
+///
+/// Every setting may be left out, and options given on the command line
+/// take precedence.  Relative paths are resolved against the directory of
+/// the configuration file so the pipeline runs the same from anywhere.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub map: Option<PathBuf>,
+    /// Further maps, directories or glob patterns to process
+    pub maps: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub reformat: bool,
+    /// Texture alignment style of the output map, `standard` or `valve220`
+    pub alignment: Option<String>,
+    pub jobs: Option<usize>,
+    pub report: Option<PathBuf>,
+    pub log: LogConfig,
+    pub diagnostics: DiagnosticConfig,
+    /// Plugins in the order they run
+    #[serde(rename = "plugin")]
+    pub plugins: Vec<PluginConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: Option<String>,
+    pub timestamps: bool,
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiagnosticConfig {
+    pub downgrade: Vec<String>,
+    pub json: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub path: PathBuf,
+    pub log_level: Option<String>,
+    /// Settings the plugin reads with `QMPP_option_init_read`
+    #[serde(default)]
+    pub options: BTreeMap<String, OptionValue>,
+}
+
+/// Value of a plugin option, which plugins always receive as a string
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum OptionValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl OptionValue {
+    pub fn into_string(self) -> String {
+        match self {
+            OptionValue::String(text) => text,
+            OptionValue::Integer(number) => number.to_string(),
+            OptionValue::Float(number) => number.to_string(),
+            OptionValue::Boolean(flag) => flag.to_string(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read {}: {}", path.display(), e)
+        })?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&text, dir)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Parse a configuration, resolving relative paths against `dir`
+    pub fn parse(text: &str, dir: &Path) -> anyhow::Result<Self> {
+        let mut config: Config = toml::from_str(text)?;
+
+        for path in [
+            &mut config.map,
+            &mut config.output,
+            &mut config.output_dir,
+            &mut config.report,
+            &mut config.log.file,
+            &mut config.diagnostics.json,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = dir.join(&*path);
+        }
+
+        for map in &mut config.maps {
+            *map = dir.join(&*map);
+        }
+
+        for plugin in &mut config.plugins {
+            plugin.path = dir.join(&plugin.path);
+        }
+
+        Ok(config)
+    }
+}
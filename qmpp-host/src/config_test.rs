@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, OptionValue};
+
+#[test]
+fn pipeline_is_read_in_order() {
+    let text = r#"
+map = "maps/start.map"
+output = "/tmp/start.map"
+jobs = 2
+
+[log]
+level = "debug"
+timestamps = true
+
+[diagnostics]
+downgrade = ["tidy.*"]
+
+[[plugin]]
+path = "plugins/tidy.wasm"
+log_level = "trace"
+
+[plugin.options]
+prefix = "old_"
+passes = 3
+strict = true
+
+[[plugin]]
+path = "plugins/lint.wasm"
+"#;
+
+    let config = Config::parse(text, Path::new("project")).unwrap();
+
+    assert_eq!(config.map, Some(PathBuf::from("project/maps/start.map")));
+    assert_eq!(config.output, Some(PathBuf::from("/tmp/start.map")));
+    assert_eq!(config.jobs, Some(2));
+    assert!(!config.reformat);
+    assert_eq!(config.log.level.as_deref(), Some("debug"));
+    assert!(config.log.timestamps);
+    assert_eq!(config.diagnostics.downgrade, vec!["tidy.*"]);
+
+    let paths = config
+        .plugins
+        .iter()
+        .map(|plugin| plugin.path.clone())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("project/plugins/tidy.wasm"),
+            PathBuf::from("project/plugins/lint.wasm"),
+        ]
+    );
+
+    let tidy = &config.plugins[0];
+    assert_eq!(tidy.log_level.as_deref(), Some("trace"));
+
+    assert_eq!(
+        tidy.options["prefix"],
+        OptionValue::String("old_".to_string())
+    );
+
+    assert_eq!(tidy.options["passes"].clone().into_string(), "3");
+    assert_eq!(tidy.options["strict"].clone().into_string(), "true");
+    assert!(config.plugins[1].options.is_empty());
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    assert!(Config::parse("mpa = \"start.map\"\n", Path::new("")).is_err());
+    assert!(Config::parse("[[plugin]]\n", Path::new("")).is_err());
+}
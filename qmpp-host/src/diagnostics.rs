@@ -0,0 +1,156 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cli::DiagnosticOptions;
+use crate::map_data::{MapData, MapPart};
+use crate::pattern::glob_match;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl TryFrom<i32> for Severity {
+    type Error = anyhow::Error;
+
+    fn try_from(severity: i32) -> anyhow::Result<Self> {
+        match severity {
+            0 => Ok(Severity::Note),
+            1 => Ok(Severity::Warning),
+            2 => Ok(Severity::Error),
+            _ => Err(anyhow::anyhow!("Bad severity {}", severity)),
+        }
+    }
+}
+
+/// A problem a plugin found with the map
+#[derive(Serialize)]
+pub struct Diagnostic {
+    pub plugin: String,
+    pub severity: Severity,
+    /// Short identifier plugins give each kind of problem, may be empty
+    pub code: String,
+    pub message: String,
+    /// Where the problem is, as described by `MapData::location`
+    pub location: Option<String>,
+    pub file: Option<String>,
+    pub first_line: Option<usize>,
+    pub last_line: Option<usize>,
+    pub entity: Option<usize>,
+    pub brush: Option<usize>,
+    pub surface: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn new(
+        plugin: String,
+        severity: Severity,
+        code: String,
+        message: String,
+        map: &MapData,
+        part: Option<MapPart>,
+    ) -> Self {
+        let lines = part.and_then(|part| map.source_lines(part));
+
+        let (brush, surface) = match part {
+            Some(MapPart::Brush(_, brush_idx)) => (Some(brush_idx), None),
+            Some(MapPart::Surface(_, brush_idx, surface_idx)) => {
+                (Some(brush_idx), Some(surface_idx))
+            }
+            _ => (None, None),
+        };
+
+        Self {
+            plugin,
+            severity,
+            code,
+            message,
+            location: part.map(|part| map.location(part)),
+            file: map.source_name().map(str::to_string),
+            first_line: lines.as_ref().map(|lines| *lines.start()),
+            last_line: lines.as_ref().map(|lines| *lines.end()),
+            entity: part.map(MapPart::ent_idx),
+            brush,
+            surface,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+
+        if self.code.is_empty() {
+            write!(f, "{}: ", self.severity)?;
+        } else {
+            write!(f, "{}[{}]: ", self.severity, self.code)?;
+        }
+
+        write!(f, "{} ({})", self.message, self.plugin)
+    }
+}
+
+/// Downgrade errors whose codes match one of the options' patterns and
+/// print every diagnostic
+///
+/// Fails if any errors remain after downgrading.
+pub fn report(
+    diagnostics: &mut [Diagnostic],
+    options: &DiagnosticOptions,
+) -> anyhow::Result<()> {
+    for diagnostic in diagnostics.iter_mut() {
+        let downgraded = options.downgrade.iter().any(|pattern| {
+            glob_match(pattern.as_bytes(), diagnostic.code.as_bytes())
+        });
+
+        if diagnostic.severity == Severity::Error && downgraded {
+            diagnostic.severity = Severity::Warning;
+        }
+    }
+
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic);
+    }
+
+    let error_count = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+
+    if error_count > 0 {
+        Err(anyhow::anyhow!("Plugins reported {} error(s)", error_count))
+    } else {
+        Ok(())
+    }
+}
+
+/// Write diagnostics, from any number of maps, to a JSON file
+pub fn write_json(
+    path: &Path,
+    diagnostics: &[&Diagnostic],
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, diagnostics)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
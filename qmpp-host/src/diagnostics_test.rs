@@ -0,0 +1,84 @@
+use quake_util::qmap;
+
+use crate::cli::DiagnosticOptions;
+use crate::diagnostics::{report, Diagnostic, Severity};
+use crate::map_data::{MapData, MapPart};
+use crate::source::MapSource;
+
+const MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+}
+";
+
+fn map_data() -> MapData {
+    let map = qmap::parse(MAP.as_bytes()).unwrap();
+    MapData::with_source(map, MapSource::new("test.map".into(), MAP.into()))
+}
+
+fn diagnostic(severity: Severity, code: &str) -> Diagnostic {
+    Diagnostic::new(
+        "checker".into(),
+        severity,
+        code.into(),
+        "something is off".into(),
+        &map_data(),
+        Some(MapPart::Surface(0, 0, 1)),
+    )
+}
+
+#[test]
+fn diagnostic_fields() {
+    let diagnostic = diagnostic(Severity::Warning, "tex-align");
+
+    assert_eq!(diagnostic.file.as_deref(), Some("test.map"));
+    assert_eq!(diagnostic.first_line, Some(5));
+    assert_eq!(diagnostic.last_line, Some(5));
+    assert_eq!(
+        (diagnostic.entity, diagnostic.brush, diagnostic.surface),
+        (Some(0), Some(0), Some(1))
+    );
+    assert_eq!(
+        diagnostic.to_string(),
+        "test.map:5: worldspawn brush 0 surface 1: \
+         warning[tex-align]: something is off (checker)"
+    );
+
+    let whole_map = Diagnostic::new(
+        "checker".into(),
+        Severity::Note,
+        String::new(),
+        "nothing found".into(),
+        &map_data(),
+        None,
+    );
+
+    assert_eq!(whole_map.to_string(), "note: nothing found (checker)");
+}
+
+#[test]
+fn downgraded_errors() {
+    let options = DiagnosticOptions {
+        downgrade: vec!["tex-*".into()],
+        json_path: None,
+    };
+
+    let mut diagnostics = vec![
+        diagnostic(Severity::Error, "tex-align"),
+        diagnostic(Severity::Warning, "leak"),
+    ];
+
+    assert!(report(&mut diagnostics, &options).is_ok());
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+
+    diagnostics.push(diagnostic(Severity::Error, "leak"));
+    assert!(report(&mut diagnostics, &options).is_err());
+}
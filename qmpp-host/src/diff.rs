@@ -0,0 +1,111 @@
+use std::ffi::CStr;
+use std::fmt::Write;
+
+use crate::alignment::AlignmentStyle;
+use crate::map_data::{MapData, MapPart};
+use crate::patch::{EntityChange, KeyChange};
+
+/// Describe the changes a plugin made to `map`, one entity at a time
+///
+/// Each entity gets a heading line naming the plugin and the entity,
+/// followed by an indented line per change: `+` for added keys and
+/// brushes, `-` for removed keys and brushes, and `~` for changed keys,
+/// textures and alignments.
+pub fn describe(
+    plugin_name: &str,
+    changes: &[EntityChange],
+    map: &MapData,
+) -> String {
+    let mut text = String::new();
+
+    for change in changes {
+        match change {
+            EntityChange::Modified {
+                ent_idx,
+                keys,
+                removed_brushes,
+                added_brushes,
+                textures,
+                alignments,
+            } => {
+                let location = map.location(MapPart::Entity(*ent_idx));
+                writeln!(text, "{} changed {}", plugin_name, location).unwrap();
+                describe_keys(&mut text, keys);
+
+                for brush_idx in removed_brushes {
+                    writeln!(text, "  - brush {}", brush_idx).unwrap();
+                }
+
+                for brush_idx in added_brushes {
+                    writeln!(text, "  + brush {}", brush_idx).unwrap();
+                }
+
+                for texture in textures {
+                    writeln!(
+                        text,
+                        "  ~ brush {} surface {} texture {} -> {}",
+                        texture.brush_idx,
+                        texture.surface_idx,
+                        quoted(&texture.old),
+                        quoted(&texture.new)
+                    )
+                    .unwrap();
+                }
+
+                for alignment in alignments {
+                    writeln!(
+                        text,
+                        "  ~ brush {} surface {} alignment {} -> {}",
+                        alignment.brush_idx,
+                        alignment.surface_idx,
+                        AlignmentStyle::of(&alignment.old),
+                        AlignmentStyle::of(&alignment.new)
+                    )
+                    .unwrap();
+                }
+            }
+            EntityChange::Removed { ent_idx } => {
+                let location = map.location(MapPart::Entity(*ent_idx));
+                writeln!(text, "{} removed {}", plugin_name, location).unwrap();
+            }
+            EntityChange::Added { ent_idx, keys } => {
+                writeln!(text, "{} added entity {}", plugin_name, ent_idx)
+                    .unwrap();
+
+                describe_keys(&mut text, keys);
+            }
+        }
+    }
+
+    text
+}
+
+fn describe_keys(text: &mut String, keys: &[KeyChange]) {
+    for change in keys {
+        let key = quoted(&change.key);
+
+        match (&change.old, &change.new) {
+            (None, Some(new)) => {
+                writeln!(text, "  + {} {}", key, quoted(new)).unwrap();
+            }
+            (Some(old), None) => {
+                writeln!(text, "  - {} {}", key, quoted(old)).unwrap();
+            }
+            (Some(old), Some(new)) => {
+                writeln!(
+                    text,
+                    "  ~ {} {} -> {}",
+                    key,
+                    quoted(old),
+                    quoted(new)
+                )
+                .unwrap();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+fn quoted(text: &CStr) -> String {
+    format!("\"{}\"", text.to_string_lossy())
+}
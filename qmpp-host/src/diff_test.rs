@@ -0,0 +1,70 @@
+use std::ffi::CString;
+
+use quake_util::qmap;
+
+use crate::diff::describe;
+use crate::map_data::MapData;
+use crate::patch::QuakeMapPatcher;
+use crate::source::MapSource;
+
+const MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"gfx.wad\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+}
+{
+\"classname\" \"light\"
+\"light\" \"200\"
+}
+{
+\"classname\" \"info_null\"
+}
+";
+
+fn value(text: &str) -> CString {
+    CString::new(text).unwrap()
+}
+
+#[test]
+fn changes_are_described() {
+    let map = MapData::with_source(
+        qmap::parse(MAP.as_bytes()).unwrap(),
+        MapSource::new("test.map".into(), MAP.into()),
+    );
+
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+    patcher.set_keyvalue(0, c"message".into(), value("hello"));
+    patcher.set_keyvalue(0, c"classname".into(), value("worldspawn"));
+    patcher.delete_keyvalue(0, c"wad".into());
+    patcher.set_texture(0, 0, 1, c"sky1".into());
+    patcher.set_keyvalue(1, c"light".into(), value("300"));
+    patcher.delete_keyvalue(1, c"style".into());
+    patcher.delete_entity(2);
+
+    let added = patcher.add_entity();
+    patcher.set_keyvalue(added, c"classname".into(), value("info_null"));
+
+    let patched = patcher.patch(&map);
+
+    let expected = "\
+tidy changed test.map:1: worldspawn
+  + \"message\" \"hello\"
+  - \"wad\" \"gfx.wad\"
+  ~ brush 0 surface 1 texture \"base\" -> \"sky1\"
+tidy changed test.map:13: light
+  ~ \"light\" \"200\" -> \"300\"
+tidy removed test.map:17: info_null
+tidy added entity 2
+  + \"classname\" \"info_null\"
+";
+
+    assert_eq!(describe("tidy", &patched.changes, &map), expected);
+}
@@ -0,0 +1,132 @@
+use std::ffi::CString;
+
+use crate::patch::PatchedMap;
+
+/// Contents flags, surface flags and value that Quake 2 maps give each
+/// surface after its texture alignment
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct SurfaceFlags {
+    pub contents: i32,
+    pub flags: i32,
+    pub value: i32,
+}
+
+/// Texture projection of a Quake 3 `brushDef` surface as two rows of a
+/// matrix mapping points on the surface's plane to texture coordinates
+pub type TextureMatrix = [[f64; 3]; 2];
+
+/// A Quake 3 curve patch
+#[derive(Clone, PartialEq, Debug)]
+pub struct PatchMesh {
+    /// `patchDef2`, or `patchDef3` for patches with explicit subdivisions
+    pub def: String,
+    pub texture: CString,
+    pub width: usize,
+    pub height: usize,
+    /// Numbers following the dimensions in the patch's header
+    pub params: Vec<i32>,
+    /// Control points as x, y, z and texture s, t; `width` rows of
+    /// `height` points each
+    pub points: Vec<[f64; 5]>,
+}
+
+/// Parts of a map that the map parser has no place for, kept alongside the
+/// parsed map by entity, brush and surface index
+///
+/// A map without any, such as a Quake 1 map, has no entities here.
+#[derive(Clone, Default)]
+pub struct MapExtras {
+    pub entities: Vec<EntityExtras>,
+}
+
+#[derive(Clone, Default)]
+pub struct EntityExtras {
+    /// Flags of each surface by brush, or `None` for surfaces without any
+    pub surface_flags: Vec<Vec<Option<SurfaceFlags>>>,
+    /// Texture matrix of each surface by brush for brushes written as
+    /// `brushDef`s, whose surfaces the parsed map gives a default alignment
+    pub texture_matrices: Vec<Option<Vec<TextureMatrix>>>,
+    /// Curve patches, which follow the brushes
+    pub patches: Vec<PatchMesh>,
+}
+
+impl MapExtras {
+    pub fn surface_flags(
+        &self,
+        ent_idx: usize,
+        brush_idx: usize,
+        surface_idx: usize,
+    ) -> Option<SurfaceFlags> {
+        *self
+            .entities
+            .get(ent_idx)?
+            .surface_flags
+            .get(brush_idx)?
+            .get(surface_idx)?
+    }
+
+    /// Whether a brush was read as a `brushDef`, whose surfaces are
+    /// projected by texture matrices rather than their alignment
+    pub fn is_brush_def(&self, ent_idx: usize, brush_idx: usize) -> bool {
+        self.entities.get(ent_idx).is_some_and(|extras| {
+            extras
+                .texture_matrices
+                .get(brush_idx)
+                .is_some_and(Option::is_some)
+        })
+    }
+
+    pub fn patches(&self, ent_idx: usize) -> &[PatchMesh] {
+        self.entities
+            .get(ent_idx)
+            .map_or(&[], |extras| extras.patches.as_slice())
+    }
+
+    /// Extras following the entities and brushes of a patched map
+    ///
+    /// Entities and brushes added by the patch have no extras, and the
+    /// extras of removed brushes are dropped with them.
+    pub fn patched(&self, patched: &PatchedMap) -> Self {
+        if self.entities.is_empty() {
+            return Self::default();
+        }
+
+        let entities = patched
+            .origins
+            .iter()
+            .map(|origin| {
+                let Some(origin) = origin else {
+                    return EntityExtras::default();
+                };
+
+                let Some(extras) = self.entities.get(origin.ent_idx) else {
+                    return EntityExtras::default();
+                };
+
+                EntityExtras {
+                    surface_flags: by_brush(
+                        &extras.surface_flags,
+                        &origin.brushes,
+                    ),
+                    texture_matrices: by_brush(
+                        &extras.texture_matrices,
+                        &origin.brushes,
+                    ),
+                    patches: extras.patches.clone(),
+                }
+            })
+            .collect();
+
+        Self { entities }
+    }
+}
+
+/// Per-brush extras of the brushes at `brushes`, up to the first brush the
+/// extras do not reach or that was added
+fn by_brush<T: Clone>(per_brush: &[T], brushes: &[Option<usize>]) -> Vec<T> {
+    brushes
+        .iter()
+        .map_while(|&brush_idx| per_brush.get(brush_idx?))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,440 @@
+use quake_util::qmap::{Brush, HalfSpace, Point, QuakeMap, Vec3};
+
+/// Distance within which a point is considered to lie on a plane
+pub const EPSILON: f64 = 0.01;
+
+/// Half the width of the square each face starts as before being clipped
+const BASE_WINDING_SIZE: f64 = 1048576.0;
+
+/// Brushes with a vertex farther out than this along any axis are treated as
+/// not being closed by their planes
+const MAX_COORD: f64 = 131072.0;
+
+/// A surface's three points are collinear so it has no plane
+pub const FLAG_BAD_PLANE: u32 = 1;
+/// A surface is clipped away entirely by the brush's other planes
+pub const FLAG_REDUNDANT_FACE: u32 = 2;
+/// The brush's planes enclose no volume
+pub const FLAG_EMPTY: u32 = 4;
+/// The brush's planes do not enclose a finite volume
+pub const FLAG_UNBOUNDED: u32 = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub dist: f64,
+}
+
+impl Plane {
+    /// Plane through the half-space's points facing out of the brush, or
+    /// `None` if the points are collinear
+    pub fn from_half_space(half_space: &HalfSpace) -> Option<Self> {
+        let [p0, p1, p2] = half_space;
+        let normal = cross(sub(*p0, *p1), sub(*p2, *p1));
+        let length = dot(normal, normal).sqrt();
+
+        if length < EPSILON {
+            return None;
+        }
+
+        let normal = scale(normal, 1.0 / length);
+
+        Some(Self {
+            normal,
+            dist: dot(*p0, normal),
+        })
+    }
+
+    pub fn distance_to(&self, point: Point) -> f64 {
+        dot(point, self.normal) - self.dist
+    }
+
+    fn coincides(&self, other: &Plane) -> bool {
+        dot(self.normal, other.normal) > 1.0 - EPSILON * EPSILON
+            && (self.dist - other.dist).abs() < EPSILON
+    }
+}
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Bounds {
+    pub mins: Point,
+    pub maxs: Point,
+}
+
+impl Bounds {
+    pub fn point(point: Point) -> Self {
+        Self {
+            mins: point,
+            maxs: point,
+        }
+    }
+
+    fn from_points<'a>(
+        mut points: impl Iterator<Item = &'a Point>,
+    ) -> Option<Self> {
+        let first = *points.next()?;
+
+        Some(points.fold(
+            Self {
+                mins: first,
+                maxs: first,
+            },
+            |bounds, point| bounds.union(&Self::point(*point)),
+        ))
+    }
+
+    pub fn union(&self, other: &Bounds) -> Self {
+        Self {
+            mins: [0, 1, 2].map(|i| self.mins[i].min(other.mins[i])),
+            maxs: [0, 1, 2].map(|i| self.maxs[i].max(other.maxs[i])),
+        }
+    }
+
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        (0..3).all(|i| {
+            self.mins[i] <= other.maxs[i] && other.mins[i] <= self.maxs[i]
+        })
+    }
+
+    /// Box grown by `amount` in every direction
+    pub fn expand(&self, amount: f64) -> Self {
+        Self {
+            mins: self.mins.map(|coord| coord - amount),
+            maxs: self.maxs.map(|coord| coord + amount),
+        }
+    }
+
+    pub fn center(&self) -> Point {
+        [0, 1, 2].map(|i| (self.mins[i] + self.maxs[i]) / 2.0)
+    }
+
+    /// Distance from the point to the nearest point in the box, 0 if the box
+    /// contains it
+    pub fn distance_to(&self, point: Point) -> f64 {
+        let offset = [0, 1, 2].map(|i| {
+            (self.mins[i] - point[i])
+                .max(point[i] - self.maxs[i])
+                .max(0.0)
+        });
+
+        dot(offset, offset).sqrt()
+    }
+}
+
+/// Shape of a brush computed from its half-spaces
+pub struct BrushGeometry {
+    /// Plane for each surface, `None` where the surface's points are
+    /// collinear
+    pub planes: Vec<Option<Plane>>,
+    /// Polygon for each surface, wound clockwise when seen from outside;
+    /// empty for surfaces that do not contribute a face
+    pub faces: Vec<Vec<Point>>,
+    /// Distinct corners of the brush
+    pub vertices: Vec<Point>,
+    pub bounds: Option<Bounds>,
+    /// Combination of the `FLAG_*` constants describing problems
+    pub flags: u32,
+}
+
+impl BrushGeometry {
+    pub fn new(brush: &Brush) -> Self {
+        let mut flags = 0;
+
+        let planes = brush
+            .iter()
+            .map(|surface| Plane::from_half_space(&surface.half_space))
+            .collect::<Vec<_>>();
+
+        if planes.iter().any(Option::is_none) {
+            flags |= FLAG_BAD_PLANE;
+        }
+
+        let faces = planes
+            .iter()
+            .enumerate()
+            .map(|(idx, plane)| match plane {
+                Some(plane) => face_winding(idx, plane, &planes),
+                None => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        if faces
+            .iter()
+            .zip(&planes)
+            .any(|(face, plane)| plane.is_some() && face.is_empty())
+        {
+            flags |= FLAG_REDUNDANT_FACE;
+        }
+
+        let mut vertices = Vec::<Point>::new();
+
+        for &point in faces.iter().flatten() {
+            if !vertices.iter().any(|&vertex| same_point(vertex, point)) {
+                vertices.push(point);
+            }
+        }
+
+        if faces.iter().filter(|face| !face.is_empty()).count() < 4 {
+            flags |= FLAG_EMPTY;
+        }
+
+        if vertices
+            .iter()
+            .flatten()
+            .any(|coord| coord.abs() > MAX_COORD)
+        {
+            flags |= FLAG_UNBOUNDED;
+        }
+
+        let bounds = if flags & (FLAG_EMPTY | FLAG_UNBOUNDED) == 0 {
+            Bounds::from_points(vertices.iter())
+        } else {
+            None
+        };
+
+        Self {
+            planes,
+            faces,
+            vertices,
+            bounds,
+            flags,
+        }
+    }
+
+    /// Whether the point is inside the brush or on its boundary
+    pub fn contains(&self, point: Point) -> bool {
+        self.bounds.is_some()
+            && self
+                .planes
+                .iter()
+                .flatten()
+                .all(|plane| plane.distance_to(point) <= EPSILON)
+    }
+
+    /// Distance from the point to the nearest point of the brush, 0 if the
+    /// brush contains it
+    pub fn distance_to(&self, point: Point) -> f64 {
+        if self.contains(point) {
+            return 0.0;
+        }
+
+        self.faces
+            .iter()
+            .zip(&self.planes)
+            .filter_map(|(face, plane)| {
+                plane.map(|plane| face_distance(face, &plane, point))
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// Geometry for every brush in a map
+pub struct MapGeometry {
+    brushes: Vec<Vec<BrushGeometry>>,
+    entity_bounds: Vec<Option<Bounds>>,
+}
+
+impl MapGeometry {
+    pub fn new(map: &QuakeMap) -> Self {
+        let brushes = map
+            .entities
+            .iter()
+            .map(|entity| {
+                entity.brushes.iter().map(BrushGeometry::new).collect()
+            })
+            .collect::<Vec<Vec<BrushGeometry>>>();
+
+        let entity_bounds = brushes
+            .iter()
+            .map(|brushes| {
+                brushes
+                    .iter()
+                    .filter_map(|brush| brush.bounds)
+                    .reduce(|a, b| a.union(&b))
+            })
+            .collect();
+
+        Self {
+            brushes,
+            entity_bounds,
+        }
+    }
+
+    pub fn brush(
+        &self,
+        ent_idx: usize,
+        brush_idx: usize,
+    ) -> Option<&BrushGeometry> {
+        self.brushes.get(ent_idx)?.get(brush_idx)
+    }
+
+    /// Bounds of all of an entity's well-formed brushes, `None` for point
+    /// entities
+    pub fn entity_bounds(&self, ent_idx: usize) -> Option<Bounds> {
+        self.entity_bounds.get(ent_idx).copied().flatten()
+    }
+}
+
+/// Clip a large square on the plane by every other plane of the brush
+fn face_winding(
+    face_idx: usize,
+    plane: &Plane,
+    planes: &[Option<Plane>],
+) -> Vec<Point> {
+    let mut winding = base_winding(plane);
+
+    for (idx, other) in planes.iter().enumerate() {
+        let other = match other {
+            Some(other) if idx != face_idx => other,
+            _ => continue,
+        };
+
+        if plane.coincides(other) {
+            // keep only the first of several identical planes
+            if idx < face_idx {
+                return Vec::new();
+            }
+
+            continue;
+        }
+
+        winding = clip_winding(&winding, other);
+
+        if winding.len() < 3 {
+            return Vec::new();
+        }
+    }
+
+    winding
+}
+
+fn base_winding(plane: &Plane) -> Vec<Point> {
+    let normal = plane.normal;
+    let major = (0..3)
+        .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+        .unwrap();
+
+    let mut up = if major == 2 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+
+    up = sub(up, scale(normal, dot(up, normal)));
+    up = scale(up, BASE_WINDING_SIZE / dot(up, up).sqrt());
+
+    let right = cross(up, normal);
+    let origin = scale(normal, plane.dist);
+
+    vec![
+        sub(add(origin, up), right),
+        add(add(origin, up), right),
+        sub(add(origin, right), up),
+        sub(sub(origin, right), up),
+    ]
+}
+
+/// Keep the part of the winding behind the plane
+fn clip_winding(winding: &[Point], plane: &Plane) -> Vec<Point> {
+    let dists = winding
+        .iter()
+        .map(|&point| plane.distance_to(point))
+        .collect::<Vec<_>>();
+
+    if dists.iter().all(|&dist| dist <= EPSILON) {
+        return winding.to_vec();
+    }
+
+    let mut clipped = Vec::with_capacity(winding.len() + 1);
+
+    for i in 0..winding.len() {
+        let j = (i + 1) % winding.len();
+        let (point, dist) = (winding[i], dists[i]);
+        let (next, next_dist) = (winding[j], dists[j]);
+
+        if dist <= EPSILON {
+            clipped.push(point);
+        }
+
+        let crosses = (dist < -EPSILON && next_dist > EPSILON)
+            || (dist > EPSILON && next_dist < -EPSILON);
+
+        if crosses {
+            let t = dist / (dist - next_dist);
+            clipped.push(add(point, scale(sub(next, point), t)));
+        }
+    }
+
+    clipped
+}
+
+/// Distance from the point to the nearest point of a convex polygon
+fn face_distance(face: &[Point], plane: &Plane, point: Point) -> f64 {
+    if face.is_empty() {
+        return f64::INFINITY;
+    }
+
+    let height = plane.distance_to(point);
+    let projected = sub(point, scale(plane.normal, height));
+
+    let edges = face.iter().zip(face.iter().cycle().skip(1));
+
+    let sides = edges.clone().map(|(&a, &b)| {
+        let normal = cross(sub(b, a), sub(projected, a));
+        dot(normal, plane.normal)
+    });
+
+    let inside = sides.clone().all(|side| side <= EPSILON)
+        || sides.clone().all(|side| side >= -EPSILON);
+
+    if inside {
+        return height.abs();
+    }
+
+    edges
+        .map(|(&a, &b)| segment_distance(a, b, point))
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn segment_distance(a: Point, b: Point, point: Point) -> f64 {
+    let edge = sub(b, a);
+    let length_sq = dot(edge, edge);
+
+    let t = if length_sq > 0.0 {
+        (dot(sub(point, a), edge) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let offset = sub(point, add(a, scale(edge, t)));
+    dot(offset, offset).sqrt()
+}
+
+fn same_point(a: Point, b: Point) -> bool {
+    (0..3).all(|i| (a[i] - b[i]).abs() < EPSILON)
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(v: Vec3, factor: f64) -> Vec3 {
+    v.map(|coord| coord * factor)
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
@@ -0,0 +1,107 @@
+use quake_util::qmap::{Alignment, Brush, Surface};
+
+use crate::geometry::{
+    Bounds, BrushGeometry, Plane, FLAG_BAD_PLANE, FLAG_EMPTY,
+    FLAG_REDUNDANT_FACE, FLAG_UNBOUNDED,
+};
+
+fn surface(half_space: [[f64; 3]; 3]) -> Surface {
+    Surface {
+        half_space,
+        texture: c"base".into(),
+        alignment: Alignment {
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            axes: None,
+        },
+    }
+}
+
+/// Box from (-16, -16, -16) to (16, 16, 16) as a level editor would write it
+fn cube() -> Brush {
+    vec![
+        surface([
+            [-16.0, -16.0, -16.0],
+            [-16.0, -15.0, -16.0],
+            [-16.0, -16.0, -15.0],
+        ]),
+        surface([
+            [-16.0, -16.0, -16.0],
+            [-16.0, -16.0, -15.0],
+            [-15.0, -16.0, -16.0],
+        ]),
+        surface([
+            [-16.0, -16.0, -16.0],
+            [-15.0, -16.0, -16.0],
+            [-16.0, -15.0, -16.0],
+        ]),
+        surface([[16.0, 16.0, 16.0], [16.0, 17.0, 16.0], [17.0, 16.0, 16.0]]),
+        surface([[16.0, 16.0, 16.0], [17.0, 16.0, 16.0], [16.0, 16.0, 17.0]]),
+        surface([[16.0, 16.0, 16.0], [16.0, 16.0, 17.0], [16.0, 17.0, 16.0]]),
+    ]
+}
+
+#[test]
+fn cube_geometry() {
+    let geometry = BrushGeometry::new(&cube());
+
+    assert_eq!(geometry.flags, 0);
+    assert_eq!(geometry.vertices.len(), 8);
+    assert!(geometry.faces.iter().all(|face| face.len() == 4));
+    assert_eq!(
+        geometry.bounds,
+        Some(Bounds {
+            mins: [-16.0; 3],
+            maxs: [16.0; 3],
+        })
+    );
+}
+
+#[test]
+fn degenerate_brushes() {
+    let mut brush = cube();
+    brush.push(surface([
+        [0.0, 0.0, 100.0],
+        [0.0, 1.0, 100.0],
+        [1.0, 0.0, 100.0],
+    ]));
+    brush.push(surface([[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]));
+    let geometry = BrushGeometry::new(&brush);
+    assert_eq!(geometry.flags, FLAG_REDUNDANT_FACE | FLAG_BAD_PLANE);
+    assert!(geometry.bounds.is_some());
+
+    let mut brush = cube();
+    brush.remove(0);
+    let geometry = BrushGeometry::new(&brush);
+    assert_eq!(geometry.flags, FLAG_UNBOUNDED);
+    assert_eq!(geometry.bounds, None);
+
+    let brush = cube().into_iter().take(3).collect::<Vec<_>>();
+    let geometry = BrushGeometry::new(&brush);
+    assert_eq!(geometry.flags & FLAG_EMPTY, FLAG_EMPTY);
+}
+
+#[test]
+fn planes_face_outward() {
+    let plane = Plane::from_half_space(&cube()[0].half_space).unwrap();
+    assert_eq!(plane.normal, [-1.0, 0.0, 0.0]);
+    assert_eq!(plane.dist, 16.0);
+
+    let plane = Plane::from_half_space(&cube()[3].half_space).unwrap();
+    assert_eq!(plane.normal, [0.0, 0.0, 1.0]);
+    assert_eq!(plane.dist, 16.0);
+}
+
+#[test]
+fn points_against_brush() {
+    let geometry = BrushGeometry::new(&cube());
+
+    assert!(geometry.contains([0.0, 0.0, 0.0]));
+    assert!(geometry.contains([16.0, 0.0, -16.0]));
+    assert!(!geometry.contains([16.5, 0.0, 0.0]));
+
+    assert_eq!(geometry.distance_to([0.0, 0.0, 0.0]), 0.0);
+    assert!((geometry.distance_to([20.0, 0.0, 0.0]) - 4.0).abs() < 1e-9);
+    assert!((geometry.distance_to([19.0, 20.0, 0.0]) - 5.0).abs() < 1e-9);
+}
@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CStr, CString};
+use std::ops::Bound;
+
+use quake_util::qmap::QuakeMap;
+
+use crate::pattern::MatchMode;
+
+/// Entities grouped by key and then by value, for answering queries like
+/// "every entity whose classname starts with `func_`"
+pub struct EntityIndex {
+    by_key: HashMap<CString, BTreeMap<Vec<u8>, Vec<usize>>>,
+}
+
+impl EntityIndex {
+    pub fn new(map: &QuakeMap) -> Self {
+        let mut by_key = HashMap::<CString, BTreeMap<_, Vec<_>>>::new();
+
+        for (ent_idx, entity) in map.entities.iter().enumerate() {
+            for (key, value) in &entity.edict {
+                by_key
+                    .entry(key.clone())
+                    .or_default()
+                    .entry(value.to_bytes().to_vec())
+                    .or_default()
+                    .push(ent_idx);
+            }
+        }
+
+        Self { by_key }
+    }
+
+    /// Indices of entities whose value for `key` matches `pattern`, in
+    /// ascending order
+    pub fn find(
+        &self,
+        key: &CStr,
+        pattern: &[u8],
+        mode: MatchMode,
+    ) -> Vec<usize> {
+        let values = match self.by_key.get(key) {
+            Some(values) => values,
+            None => {
+                return Vec::new();
+            }
+        };
+
+        let mut found = match mode {
+            MatchMode::Exact => {
+                values.get(pattern).cloned().unwrap_or_default()
+            }
+            _ => {
+                // every match starts with the pattern's literal prefix, so
+                // only that range of values needs to be checked
+                let prefix = mode.literal_prefix(pattern);
+
+                values
+                    .range::<[u8], _>((
+                        Bound::Included(prefix),
+                        Bound::Unbounded,
+                    ))
+                    .take_while(|(value, _)| value.starts_with(prefix))
+                    .filter(|(value, _)| mode.matches(pattern, value))
+                    .flat_map(|(_, ent_indices)| ent_indices.iter().copied())
+                    .collect()
+            }
+        };
+
+        found.sort_unstable();
+        found
+    }
+}
@@ -0,0 +1,73 @@
+use std::ffi::CString;
+
+use quake_util::qmap::{Entity, QuakeMap};
+
+use crate::index::EntityIndex;
+use crate::pattern::MatchMode;
+
+fn test_map() -> QuakeMap {
+    let classnames = [
+        "worldspawn",
+        "func_button",
+        "func_door",
+        "light",
+        "func_button",
+        "info_player_start",
+    ];
+
+    let entities = classnames
+        .iter()
+        .map(|&classname| {
+            let mut entity = Entity::new();
+
+            entity.edict.insert(
+                c"classname".to_owned(),
+                CString::new(classname).unwrap(),
+            );
+
+            entity
+        })
+        .collect();
+
+    QuakeMap { entities }
+}
+
+#[test]
+fn find_exact() {
+    let index = EntityIndex::new(&test_map());
+
+    assert_eq!(
+        index.find(c"classname", b"func_button", MatchMode::Exact),
+        vec![1, 4]
+    );
+
+    assert!(index
+        .find(c"classname", b"func_", MatchMode::Exact)
+        .is_empty());
+    assert!(index.find(c"target", b"light", MatchMode::Exact).is_empty());
+}
+
+#[test]
+fn find_prefix() {
+    let index = EntityIndex::new(&test_map());
+
+    assert_eq!(
+        index.find(c"classname", b"func_", MatchMode::Prefix),
+        vec![1, 2, 4]
+    );
+}
+
+#[test]
+fn find_glob() {
+    let index = EntityIndex::new(&test_map());
+
+    assert_eq!(
+        index.find(c"classname", b"*_*", MatchMode::Glob),
+        vec![1, 2, 4, 5]
+    );
+
+    assert_eq!(
+        index.find(c"classname", b"func_d*", MatchMode::Glob),
+        vec![2]
+    );
+}
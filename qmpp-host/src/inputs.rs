@@ -0,0 +1,124 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::pattern::glob_match;
+
+const MAP_EXTENSION: &str = "map";
+const JSON_EXTENSION: &str = "json";
+const ENT_EXTENSION: &str = "ent";
+const BSP_EXTENSION: &str = "bsp";
+
+/// Format a map is read or written in, going by its file extension
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapFormat {
+    /// The `.map` text format
+    Map,
+    /// The JSON form of `json_map`
+    Json,
+    /// Entities alone, in the `.map` syntax
+    Ent,
+    /// The entity lump of a compiled BSP
+    Bsp,
+}
+
+impl MapFormat {
+    pub fn of(path: &Path) -> Self {
+        let is = |extension: &str| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        };
+
+        if is(JSON_EXTENSION) {
+            MapFormat::Json
+        } else if is(ENT_EXTENSION) {
+            MapFormat::Ent
+        } else if is(BSP_EXTENSION) {
+            MapFormat::Bsp
+        } else {
+            MapFormat::Map
+        }
+    }
+}
+
+/// Expand the map paths given by the user into the maps to process
+///
+/// A directory stands for the .map files directly inside it, and a path
+/// whose file name contains `*` or `?` for the files in its directory that
+/// match, which saves quoting trouble on shells that do not expand globs.
+/// Maps found either way are sorted by path.
+pub fn expand_map_paths(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut map_paths = Vec::new();
+
+    for path in paths {
+        let pattern = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .filter(|name| name.contains(['*', '?']));
+
+        if let Some(pattern) = pattern {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+            let found = list_files(dir, |name| {
+                glob_match(pattern.as_bytes(), name.as_bytes())
+            })?;
+
+            if found.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No maps match {}",
+                    path.display()
+                ));
+            }
+
+            map_paths.extend(found);
+        } else if path.is_dir() {
+            map_paths.extend(list_files(path, |name| {
+                Path::new(name)
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(MAP_EXTENSION))
+            })?);
+        } else {
+            map_paths.push(path.clone());
+        }
+    }
+
+    Ok(map_paths)
+}
+
+/// Where the output for a map goes in an output directory
+pub fn output_path_in(dir: &Path, map_path: &Path) -> PathBuf {
+    dir.join(map_path.file_name().unwrap_or(map_path.as_os_str()))
+}
+
+fn list_files(
+    dir: &Path,
+    mut keep: impl FnMut(&str) -> bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let read_dir = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+
+    let entries = read_dir.map_err(|e| {
+        anyhow::anyhow!("Failed to read {}: {}", dir.display(), e)
+    })?;
+
+    let mut paths = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+
+        if keep(name) && entry.path().is_file() {
+            paths.push(dir.join(name));
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
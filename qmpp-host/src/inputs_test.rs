@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::inputs::{expand_map_paths, output_path_in, MapFormat};
+
+#[test]
+fn directories_and_patterns_expand_to_maps() {
+    let dir = std::env::temp_dir()
+        .join(format!("qmpp-inputs-test-{}", std::process::id()));
+
+    fs::create_dir_all(dir.join("sub.map")).unwrap();
+
+    for name in ["e1m2.map", "e1m1.MAP", "e2m1.map", "notes.txt"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+
+    let all = expand_map_paths(std::slice::from_ref(&dir)).unwrap();
+
+    assert_eq!(
+        all,
+        vec![
+            dir.join("e1m1.MAP"),
+            dir.join("e1m2.map"),
+            dir.join("e2m1.map")
+        ]
+    );
+
+    let episode =
+        expand_map_paths(&[dir.join("e1m?.map"), dir.join("notes.txt")])
+            .unwrap();
+
+    assert_eq!(episode, vec![dir.join("e1m2.map"), dir.join("notes.txt")]);
+    assert!(expand_map_paths(&[dir.join("e3*")]).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn outputs_keep_the_map_name() {
+    assert_eq!(
+        output_path_in(Path::new("build"), Path::new("maps/e1m1.map")),
+        PathBuf::from("build/e1m1.map")
+    );
+}
+
+#[test]
+fn formats_go_by_extension() {
+    assert_eq!(MapFormat::of(Path::new("maps/e1m1.json")), MapFormat::Json);
+    assert_eq!(MapFormat::of(Path::new("E1M1.JSON")), MapFormat::Json);
+    assert_eq!(MapFormat::of(Path::new("e1m1.ent")), MapFormat::Ent);
+    assert_eq!(MapFormat::of(Path::new("e1m1.bsp")), MapFormat::Bsp);
+    assert_eq!(MapFormat::of(Path::new("e1m1.map")), MapFormat::Map);
+    assert_eq!(MapFormat::of(Path::new("e1m1")), MapFormat::Map);
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+
+use quake_util::qmap::{Alignment, Edict, Entity, QuakeMap, Surface, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::extras::{EntityExtras, MapExtras, SurfaceFlags};
+use crate::map_data::MapData;
+
+const FORMAT: &str = "qmpp-map";
+const VERSION: u32 = 1;
+
+/// A map as JSON, for scripts that inspect or generate maps
+///
+/// A map is an object naming the schema and its version, followed by the
+/// entities in map order:
+///
+/// ```json
+/// {
+///   "format": "qmpp-map",
+///   "version": 1,
+///   "entities": [
+///     {
+///       "edict": { "classname": "worldspawn", "wad": "gfx.wad" },
+///       "brushes": [
+///         [
+///           {
+///             "points": [[0, 0, 0], [0, 1, 0], [0, 0, 1]],
+///             "texture": "base",
+///             "offset": [0, 0],
+///             "rotation": 0,
+///             "scale": [1, 1]
+///           }
+///         ]
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Each brush is an array of surfaces, each given by the three points of
+/// its half-space as in the `.map` format.  Surfaces with Valve220
+/// alignment also have `"axes"`, the U and V texture axes, and surfaces of
+/// Quake 2 maps have `"flags"`, an object of `contents`, `flags` and
+/// `value`.  Members that are left out take the values shown above, apart
+/// from `points`.  Quake 3 patches and `brushDef` texture matrices are not
+/// included.
+///
+/// Keys, values and texture names are the bytes of the map read as Latin-1,
+/// so Quake's own characters above 127 come through unchanged; characters
+/// past U+00FF are rejected when reading.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonMap {
+    format: String,
+    version: u32,
+    entities: Vec<JsonEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEntity {
+    #[serde(default)]
+    edict: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    brushes: Vec<Vec<JsonSurface>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonSurface {
+    points: [Vec3; 3],
+    #[serde(default)]
+    texture: String,
+    #[serde(default)]
+    offset: [f64; 2],
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "unit_scale")]
+    scale: [f64; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    axes: Option<[Vec3; 2]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<JsonFlags>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonFlags {
+    #[serde(default)]
+    contents: i32,
+    #[serde(default)]
+    flags: i32,
+    #[serde(default)]
+    value: i32,
+}
+
+fn unit_scale() -> [f64; 2] {
+    [1.0, 1.0]
+}
+
+/// Parse a map written as JSON, `name` being used in error messages
+pub fn read_json_map(name: &str, text: &[u8]) -> anyhow::Result<MapData> {
+    let json: JsonMap = serde_json::from_slice(text)
+        .map_err(|e| anyhow::anyhow!("{}: {}", name, e))?;
+
+    if json.format != FORMAT || json.version != VERSION {
+        return Err(anyhow::anyhow!(
+            "{}: Expected format \"{}\" version {} but got \"{}\" version {}",
+            name,
+            FORMAT,
+            VERSION,
+            json.format,
+            json.version
+        ));
+    }
+
+    let mut map = QuakeMap::new();
+    let mut extras = MapExtras::default();
+    let mut has_flags = false;
+
+    for (ent_idx, json_entity) in json.entities.into_iter().enumerate() {
+        let context = |e: anyhow::Error| {
+            anyhow::anyhow!("{}: Entity {}: {}", name, ent_idx, e)
+        };
+
+        let mut edict = Edict::new();
+
+        for (key, value) in json_entity.edict {
+            edict.insert(
+                from_latin1(&key).map_err(context)?,
+                from_latin1(&value).map_err(context)?,
+            );
+        }
+
+        let mut brushes = Vec::new();
+        let mut ent_extras = EntityExtras::default();
+
+        for json_brush in json_entity.brushes {
+            let mut brush = Vec::new();
+            let mut brush_flags = Vec::new();
+
+            for json_surface in json_brush {
+                brush.push(Surface {
+                    half_space: json_surface.points,
+                    texture: from_latin1(&json_surface.texture)
+                        .map_err(context)?,
+                    alignment: Alignment {
+                        offset: json_surface.offset,
+                        rotation: json_surface.rotation,
+                        scale: json_surface.scale,
+                        axes: json_surface.axes,
+                    },
+                });
+
+                brush_flags.push(json_surface.flags.map(|flags| {
+                    has_flags = true;
+
+                    SurfaceFlags {
+                        contents: flags.contents,
+                        flags: flags.flags,
+                        value: flags.value,
+                    }
+                }));
+            }
+
+            brushes.push(brush);
+            ent_extras.surface_flags.push(brush_flags);
+            ent_extras.texture_matrices.push(None);
+        }
+
+        map.entities.push(Entity { edict, brushes });
+        extras.entities.push(ent_extras);
+    }
+
+    if !has_flags {
+        extras = MapExtras::default();
+    }
+
+    Ok(MapData::new(map).with_extras(extras))
+}
+
+/// Write the map as JSON, with keys sorted
+pub fn write_json_map(
+    writer: &mut impl Write,
+    map: &MapData,
+) -> anyhow::Result<()> {
+    let mut entities = Vec::new();
+
+    for (ent_idx, entity) in map.entities.iter().enumerate() {
+        let edict = entity
+            .edict
+            .iter()
+            .map(|(key, value)| (to_latin1(key), to_latin1(value)))
+            .collect();
+
+        let mut brushes = Vec::new();
+
+        for (brush_idx, brush) in entity.brushes.iter().enumerate() {
+            let surfaces = brush
+                .iter()
+                .enumerate()
+                .map(|(surface_idx, surface)| {
+                    let flags = map.extras().surface_flags(
+                        ent_idx,
+                        brush_idx,
+                        surface_idx,
+                    );
+
+                    json_surface(surface, flags)
+                })
+                .collect();
+
+            brushes.push(surfaces);
+        }
+
+        entities.push(JsonEntity { edict, brushes });
+    }
+
+    let json = JsonMap {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        entities,
+    };
+
+    serde_json::to_writer_pretty(&mut *writer, &json)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn json_surface(surface: &Surface, flags: Option<SurfaceFlags>) -> JsonSurface {
+    JsonSurface {
+        points: surface.half_space,
+        texture: to_latin1(&surface.texture),
+        offset: surface.alignment.offset,
+        rotation: surface.alignment.rotation,
+        scale: surface.alignment.scale,
+        axes: surface.alignment.axes,
+        flags: flags.map(|flags| JsonFlags {
+            contents: flags.contents,
+            flags: flags.flags,
+            value: flags.value,
+        }),
+    }
+}
+
+fn to_latin1(text: &CStr) -> String {
+    text.to_bytes()
+        .iter()
+        .map(|&byte| char::from(byte))
+        .collect()
+}
+
+fn from_latin1(text: &str) -> anyhow::Result<CString> {
+    let bytes = text
+        .chars()
+        .map(|ch| {
+            u8::try_from(ch).map_err(|_| {
+                anyhow::anyhow!("Character {:?} is not in Latin-1", ch)
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    CString::new(bytes)
+        .map_err(|_| anyhow::anyhow!("Text {:?} contains a nul", text))
+}
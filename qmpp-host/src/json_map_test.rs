@@ -0,0 +1,126 @@
+use serde_json::Value;
+
+use crate::json_map::{read_json_map, write_json_map};
+use crate::map_data::MapData;
+use crate::reader::read_map;
+use crate::writer::write_map;
+
+const QUAKE2_MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"message\" \"The \u{e8}lder World\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) e1u1/sky1 8 4 90 2 0.5 1 4 200
+}
+{
+( 32 -16 -16 ) ( 32 -15 -16 ) ( 32 -16 -15 ) e1u1/water4 [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 32 -16 -16 ) ( 32 -16 -15 ) ( 33 -16 -16 ) e1u1/water4 [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 32 -16 -16 ) ( 33 -16 -16 ) ( 32 -15 -16 ) e1u1/water4 [ -1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 16 16 ) ( 64 17 16 ) ( 65 16 16 ) e1u1/water4 [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 16 16 ) ( 65 16 16 ) ( 64 16 17 ) e1u1/water4 [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 16 16 ) ( 64 16 17 ) ( 64 17 16 ) e1u1/water4 [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+}
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+";
+
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|ch| u8::try_from(ch).unwrap()).collect()
+}
+
+fn json(map: &MapData) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_json_map(&mut out, map).unwrap();
+    out
+}
+
+fn reformatted(map: &MapData) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_map(&mut out, map, None, None).unwrap();
+    out
+}
+
+#[test]
+fn maps_survive_a_round_trip_through_json() {
+    let map = read_map("q2.map".into(), latin1(QUAKE2_MAP)).unwrap();
+    let from_json = read_json_map("q2.json", &json(&map)).unwrap();
+
+    assert_eq!(reformatted(&from_json), reformatted(&map));
+    assert_eq!(json(&from_json), json(&map));
+}
+
+#[test]
+fn json_maps_follow_the_schema() {
+    let map = read_map("q2.map".into(), latin1(QUAKE2_MAP)).unwrap();
+    let value: Value = serde_json::from_slice(&json(&map)).unwrap();
+
+    assert_eq!(value["format"], "qmpp-map");
+    assert_eq!(value["version"], 1);
+
+    let worldspawn = &value["entities"][0];
+    assert_eq!(worldspawn["edict"]["message"], "The \u{e8}lder World");
+
+    let sky = &worldspawn["brushes"][0][5];
+    assert_eq!(sky["texture"], "e1u1/sky1");
+    assert_eq!(sky["offset"], serde_json::json!([8.0, 4.0]));
+    assert_eq!(sky["rotation"], 90.0);
+    assert_eq!(sky["scale"], serde_json::json!([2.0, 0.5]));
+    assert_eq!(sky["flags"]["value"], 200);
+    assert!(sky.get("axes").is_none());
+
+    let water = &worldspawn["brushes"][1][0];
+    assert_eq!(
+        water["axes"],
+        serde_json::json!([[0.0, 1.0, 0.0], [0.0, 0.0, -1.0]])
+    );
+    assert!(water.get("flags").is_none());
+
+    let light = &value["entities"][1];
+    assert!(light.get("brushes").is_none());
+}
+
+#[test]
+fn left_out_members_take_defaults() {
+    let text = r#"{
+        "format": "qmpp-map",
+        "version": 1,
+        "entities": [
+            {
+                "edict": { "classname": "worldspawn" },
+                "brushes": [[{ "points": [[0, 0, 0], [0, 1, 0], [0, 0, 1]] }]]
+            },
+            {}
+        ]
+    }"#;
+
+    let map = read_json_map("small.json", text.as_bytes()).unwrap();
+    let surface = &map.entities[0].brushes[0][0];
+
+    assert_eq!(surface.alignment.scale, [1.0, 1.0]);
+    assert_eq!(surface.alignment.axes, None);
+    assert!(map.entities[1].edict.is_empty());
+}
+
+#[test]
+fn unknown_versions_and_characters_are_rejected() {
+    let text = r#"{ "format": "qmpp-map", "version": 2, "entities": [] }"#;
+    let error = read_json_map("new.json", text.as_bytes()).err().unwrap();
+    assert!(error.to_string().contains("version 2"));
+
+    let text = r#"{
+        "format": "qmpp-map",
+        "version": 1,
+        "entities": [{ "edict": { "message": "☃" } }]
+    }"#;
+
+    let error = read_json_map("snow.json", text.as_bytes()).err().unwrap();
+    assert!(error.to_string().starts_with("snow.json: Entity 0: "));
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use quake_util::qmap::QuakeMap;
+
+/// Which key an entity uses to refer to another entity's `targetname`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LinkKind {
+    Target,
+    Killtarget,
+}
+
+impl LinkKind {
+    const ALL: [LinkKind; 2] = [LinkKind::Target, LinkKind::Killtarget];
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            LinkKind::Target => c"target",
+            LinkKind::Killtarget => c"killtarget",
+        }
+    }
+
+    /// Numeric code used when sending links to plugins
+    pub fn code(self) -> u32 {
+        match self {
+            LinkKind::Target => 0,
+            LinkKind::Killtarget => 1,
+        }
+    }
+}
+
+/// One end of a link, as seen from the entity at the other end
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub ent_idx: usize,
+}
+
+/// Links between entities formed by `target` and `killtarget` keys
+/// naming another entity's `targetname`
+pub struct LinkGraph {
+    outgoing: Vec<Vec<Link>>,
+    incoming: Vec<Vec<Link>>,
+}
+
+impl LinkGraph {
+    pub fn new(map: &QuakeMap) -> Self {
+        let mut named = HashMap::<&CStr, Vec<usize>>::new();
+
+        for (ent_idx, entity) in map.entities.iter().enumerate() {
+            if let Some(name) = entity.edict.get(c"targetname") {
+                if !name.is_empty() {
+                    named.entry(name).or_default().push(ent_idx);
+                }
+            }
+        }
+
+        let mut outgoing = vec![Vec::new(); map.entities.len()];
+        let mut incoming = vec![Vec::new(); map.entities.len()];
+
+        for (from_idx, entity) in map.entities.iter().enumerate() {
+            for kind in LinkKind::ALL {
+                let targets = entity
+                    .edict
+                    .get(kind.key())
+                    .and_then(|name| named.get(name.as_c_str()));
+
+                for &to_idx in targets.into_iter().flatten() {
+                    outgoing[from_idx].push(Link {
+                        kind,
+                        ent_idx: to_idx,
+                    });
+
+                    incoming[to_idx].push(Link {
+                        kind,
+                        ent_idx: from_idx,
+                    });
+                }
+            }
+        }
+
+        Self { outgoing, incoming }
+    }
+
+    /// Links from the entity to the entities it targets
+    pub fn outgoing(&self, ent_idx: usize) -> &[Link] {
+        self.outgoing.get(ent_idx).map_or(&[], Vec::as_slice)
+    }
+
+    /// Links to the entity from the entities targeting it
+    pub fn incoming(&self, ent_idx: usize) -> &[Link] {
+        self.incoming.get(ent_idx).map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,91 @@
+use std::ffi::CString;
+
+use quake_util::qmap::{Entity, QuakeMap};
+
+use crate::links::{Link, LinkGraph, LinkKind};
+
+fn entity(keyvalues: &[(&str, &str)]) -> Entity {
+    let mut entity = Entity::new();
+
+    for &(key, value) in keyvalues {
+        entity
+            .edict
+            .insert(CString::new(key).unwrap(), CString::new(value).unwrap());
+    }
+
+    entity
+}
+
+fn test_map() -> QuakeMap {
+    QuakeMap {
+        entities: vec![
+            entity(&[("classname", "worldspawn")]),
+            entity(&[("classname", "func_button"), ("target", "door")]),
+            entity(&[("classname", "func_door"), ("targetname", "door")]),
+            entity(&[("classname", "func_door"), ("targetname", "door")]),
+            entity(&[
+                ("classname", "trigger_once"),
+                ("target", "nothing"),
+                ("killtarget", "door"),
+            ]),
+        ],
+    }
+}
+
+#[test]
+fn outgoing_links() {
+    let graph = LinkGraph::new(&test_map());
+
+    assert_eq!(
+        graph.outgoing(1),
+        &[
+            Link {
+                kind: LinkKind::Target,
+                ent_idx: 2
+            },
+            Link {
+                kind: LinkKind::Target,
+                ent_idx: 3
+            },
+        ]
+    );
+
+    // dangling targets produce no links
+    assert_eq!(
+        graph.outgoing(4),
+        &[
+            Link {
+                kind: LinkKind::Killtarget,
+                ent_idx: 2
+            },
+            Link {
+                kind: LinkKind::Killtarget,
+                ent_idx: 3
+            },
+        ]
+    );
+
+    assert!(graph.outgoing(0).is_empty());
+    assert!(graph.outgoing(99).is_empty());
+}
+
+#[test]
+fn incoming_links() {
+    let graph = LinkGraph::new(&test_map());
+
+    assert_eq!(
+        graph.incoming(3),
+        &[
+            Link {
+                kind: LinkKind::Target,
+                ent_idx: 1
+            },
+            Link {
+                kind: LinkKind::Killtarget,
+                ent_idx: 4
+            },
+        ]
+    );
+
+    assert!(graph.incoming(1).is_empty());
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli::LogOptions;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogLevel::Trace => write!(f, "TRACE"),
+            LogLevel::Debug => write!(f, "DEBUG"),
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(level: &str) -> anyhow::Result<Self> {
+        match level.to_ascii_lowercase().as_str() {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(anyhow::anyhow!("Invalid log level \"{}\"", level)),
+        }
+    }
+}
+
+/// Decides which plugin log messages are kept and where they are written
+///
+/// Messages go to stdout, or stderr for warnings and errors, unless a log
+/// file is given, in which case every message goes to the file.
+pub struct Logger {
+    level: LogLevel,
+    plugin_levels: Vec<(String, LogLevel)>,
+    timestamps: bool,
+    file: Option<Arc<Mutex<LineWriter<File>>>>,
+    /// Lines held back until `release`, if this logger holds them
+    held: Option<Mutex<Vec<(LogLevel, String)>>>,
+}
+
+impl Logger {
+    pub fn new(options: &LogOptions) -> anyhow::Result<Self> {
+        let file = match &options.file_path {
+            Some(path) => {
+                Some(Arc::new(Mutex::new(LineWriter::new(File::create(path)?))))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            level: options.level,
+            plugin_levels: options.plugin_levels.clone(),
+            timestamps: options.timestamps,
+            file,
+            held: None,
+        })
+    }
+
+    /// Logger writing to the same place with the same levels, but holding
+    /// every line back until `release`
+    pub fn holding(&self) -> Self {
+        Self {
+            level: self.level,
+            plugin_levels: self.plugin_levels.clone(),
+            timestamps: self.timestamps,
+            file: self.file.clone(),
+            held: Some(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Whether messages at `level` from the plugin with the given file stem
+    /// are kept
+    ///
+    /// A level given for the plugin overrides the general one, and the last
+    /// such level wins.
+    pub fn enabled(&self, file_stem: &str, level: LogLevel) -> bool {
+        let min_level = self
+            .plugin_levels
+            .iter()
+            .rev()
+            .find(|(name, _)| name == file_stem)
+            .map_or(self.level, |&(_, level)| level);
+
+        level >= min_level
+    }
+
+    /// Write a message that has already been checked with `enabled`
+    pub fn write(&self, plugin_name: &str, level: LogLevel, mesg: &str) {
+        let line = if self.timestamps {
+            format!(
+                "{}\t{}\t{}\t{}",
+                format_time(SystemTime::now()),
+                plugin_name,
+                level,
+                mesg
+            )
+        } else {
+            format!("{}\t{}\t{}", plugin_name, level, mesg)
+        };
+
+        match &self.held {
+            Some(held) => held.lock().unwrap().push((level, line)),
+            None => self.emit(&[(level, line)]),
+        }
+    }
+
+    /// Write the lines held so far together, so that no other line comes
+    /// between them
+    pub fn release(&self) {
+        if let Some(held) = &self.held {
+            let lines = std::mem::take(&mut *held.lock().unwrap());
+            self.emit(&lines);
+        }
+    }
+
+    fn emit(&self, lines: &[(LogLevel, String)]) {
+        match &self.file {
+            Some(file) => {
+                let mut file = file.lock().unwrap();
+
+                for (_, line) in lines {
+                    if let Err(e) = writeln!(file, "{}", line) {
+                        eprintln!("Could not write to log file: {}", e);
+                    }
+                }
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                let mut stderr = io::stderr().lock();
+
+                for (level, line) in lines {
+                    if *level >= LogLevel::Warn {
+                        writeln!(stderr, "{}", line).unwrap();
+                    } else {
+                        writeln!(stdout, "{}", line).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Time of day in UTC as `HH:MM:SS.mmm`
+pub fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() % 86400;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
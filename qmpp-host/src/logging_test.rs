@@ -0,0 +1,70 @@
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::cli::LogOptions;
+use crate::logging::{format_time, LogLevel, Logger};
+
+#[test]
+fn level_names() {
+    assert_eq!("warn".parse::<LogLevel>().unwrap(), LogLevel::Warn);
+    assert_eq!("TRACE".parse::<LogLevel>().unwrap(), LogLevel::Trace);
+    assert!("verbose".parse::<LogLevel>().is_err());
+    assert_eq!(LogLevel::Debug.to_string(), "DEBUG");
+}
+
+#[test]
+fn plugin_levels_override() {
+    let logger = Logger::new(&LogOptions {
+        level: LogLevel::Info,
+        plugin_levels: vec![
+            ("hello".into(), LogLevel::Debug),
+            ("hello".into(), LogLevel::Error),
+        ],
+        timestamps: false,
+        file_path: None,
+    })
+    .unwrap();
+
+    assert!(logger.enabled("other", LogLevel::Info));
+    assert!(!logger.enabled("other", LogLevel::Debug));
+    assert!(logger.enabled("hello", LogLevel::Error));
+    assert!(!logger.enabled("hello", LogLevel::Warn));
+}
+
+#[test]
+fn held_lines_are_written_together() {
+    let log_path = std::env::temp_dir()
+        .join(format!("qmpp-logging-test-{}.log", std::process::id()));
+
+    let logger = Logger::new(&LogOptions {
+        level: LogLevel::Info,
+        plugin_levels: Vec::new(),
+        timestamps: false,
+        file_path: Some(log_path.clone()),
+    })
+    .unwrap();
+
+    let first = logger.holding();
+    let second = logger.holding();
+
+    first.write("a", LogLevel::Info, "first map");
+    second.write("b", LogLevel::Info, "second map");
+    first.write("a", LogLevel::Warn, "first map again");
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), "");
+
+    second.release();
+    first.release();
+
+    assert_eq!(
+        fs::read_to_string(&log_path).unwrap(),
+        "b\tINFO\tsecond map\na\tINFO\tfirst map\na\tWARN\tfirst map again\n"
+    );
+
+    fs::remove_file(&log_path).unwrap();
+}
+
+#[test]
+fn time_of_day() {
+    let time = UNIX_EPOCH + Duration::from_millis(86400 * 1000 * 3 + 45296789);
+    assert_eq!(format_time(time), "12:34:56.789");
+}
@@ -1,27 +1,606 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::sync::Arc;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use quake_util::qmap;
-
-use wasmtime::{Engine, Module};
+use wasmtime::{Engine, Linker, Module};
 
+mod alignment;
+mod bsp;
+mod cache;
+mod cli;
+mod config;
+mod diagnostics;
+mod diff;
+mod extras;
+mod geometry;
+mod index;
+mod inputs;
+mod json_map;
+mod links;
+mod logging;
+mod map_data;
+mod patch;
+mod pattern;
+mod pipeline;
 mod plugin;
-use plugin::{init, process};
+mod reader;
+mod report;
+mod source;
+mod spatial;
+mod watch;
+mod writer;
+use alignment::{convert_map, same_alignment, AlignmentStyle};
+use bsp::{read_bsp, write_bsp};
+use cache::{load_module, ModuleCache};
+use cli::{parse_args, Command, Options, USAGE};
+use config::CONFIG_FILE;
+use diagnostics::{Diagnostic, Severity};
+use diff::describe;
+use inputs::{expand_map_paths, output_path_in, MapFormat};
+use json_map::{read_json_map, write_json_map};
+use logging::Logger;
+use map_data::MapData;
+use patch::QuakeMapPatcher;
+use pipeline::Processed;
+use plugin::{linker, Phase, Plugin, PluginEnv, IMPORTS};
+use reader::{read_ent, read_map};
+use report::{write_reports, RunReport};
+use watch::Watcher;
+use writer::{write_ent, write_map};
+
+#[cfg(test)]
+mod alignment_test;
+#[cfg(test)]
+mod bsp_test;
+#[cfg(test)]
+mod cache_test;
+#[cfg(test)]
+mod cli_test;
+#[cfg(test)]
+mod config_test;
+#[cfg(test)]
+mod diagnostics_test;
+#[cfg(test)]
+mod diff_test;
+#[cfg(test)]
+mod geometry_test;
+#[cfg(test)]
+mod index_test;
+#[cfg(test)]
+mod inputs_test;
+#[cfg(test)]
+mod json_map_test;
+#[cfg(test)]
+mod links_test;
+#[cfg(test)]
+mod logging_test;
+#[cfg(test)]
+mod patch_test;
+#[cfg(test)]
+mod pattern_test;
+#[cfg(test)]
+mod pipeline_test;
+#[cfg(test)]
+mod reader_test;
+#[cfg(test)]
+mod source_test;
+#[cfg(test)]
+mod spatial_test;
+#[cfg(test)]
+mod watch_test;
+#[cfg(test)]
+mod writer_test;
 
 fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => *options,
+        Ok(Command::ListImports) => {
+            list_imports();
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(options) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+fn run(options: Options) -> anyhow::Result<()> {
     let engine = Engine::default();
-    let module = Module::from_file(
-        &engine,
-        "target/wasm32-unknown-unknown/release/hello.wasm",
-    )
-    .unwrap();
-
-    let reader = BufReader::new(
-        File::open("qmpp-host/test-res/q25_limits_4lt.map").unwrap(),
+    let cache = ModuleCache::new(options.cache.dir.clone(), &engine);
+
+    if options.cache.clear {
+        cache.clear()?;
+    }
+
+    let cache = Some(&cache).filter(|_| options.cache.enabled);
+    let linker = linker(&engine)?;
+    let mut logger = Arc::new(Logger::new(&options.log)?);
+    let mut modules = load_modules(&engine, &options, cache)?;
+
+    if !options.watch {
+        let map_paths = expand_inputs(&options)?;
+        return run_maps(&options, &map_paths, &linker, &modules, &logger);
+    }
+
+    let mut options = options;
+    let mut watcher = Watcher::new(watched_paths(&options));
+
+    loop {
+        let ran = expand_inputs(&options).and_then(|map_paths| {
+            run_maps(&options, &map_paths, &linker, &modules, &logger)
+        });
+
+        if let Err(e) = ran {
+            eprintln!("{}", e);
+        }
+
+        eprintln!("Watching for changes...");
+
+        // only plugins whose wasm changed are compiled again, unless the
+        // configuration changed, and a plugin or configuration that fails
+        // to load holds off the next run until it is fixed
+        loop {
+            let changed = watcher.wait(|| watched_paths(&options));
+
+            let reloaded = if changed.contains(&config_file(&options)) {
+                reload_options().and_then(|reloaded| {
+                    logger = Arc::new(Logger::new(&reloaded.log)?);
+                    modules = load_modules(&engine, &reloaded, cache)?;
+                    options = reloaded;
+                    Ok(())
+                })
+            } else {
+                options.plugins.iter().zip(&mut modules).try_for_each(
+                    |(spec, module)| {
+                        if changed.contains(&spec.path) {
+                            *module = load_module(&engine, &spec.path, cache)?;
+                        }
+
+                        Ok(())
+                    },
+                )
+            };
+
+            match reloaded {
+                Ok(()) => break,
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
+
+/// The maps to process, as the map paths given by the user expand to now
+fn expand_inputs(options: &Options) -> anyhow::Result<Vec<PathBuf>> {
+    let map_paths = expand_map_paths(&options.map_paths)?;
+
+    if map_paths.len() > 1 && options.output_path.is_some() {
+        return Err(anyhow::anyhow!(
+            "Use --output-dir to write the output of several maps"
+        ));
+    }
+
+    Ok(map_paths)
+}
+
+fn load_modules(
+    engine: &Engine,
+    options: &Options,
+    cache: Option<&ModuleCache>,
+) -> anyhow::Result<Vec<Module>> {
+    options
+        .plugins
+        .iter()
+        .map(|spec| load_module(engine, &spec.path, cache))
+        .collect()
+}
+
+/// Configuration file that is read, or would be if it existed
+fn config_file(options: &Options) -> PathBuf {
+    options
+        .config_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+}
+
+/// Files whose changes start another run in watch mode
+///
+/// Maps are found again each time, so maps added to a watched directory
+/// are picked up, and maps that cannot be found now are left for a later
+/// poll.
+fn watched_paths(options: &Options) -> Vec<PathBuf> {
+    let mut paths = expand_map_paths(&options.map_paths).unwrap_or_default();
+    paths.extend(options.plugins.iter().map(|spec| spec.path.clone()));
+    paths.push(config_file(options));
+    paths
+}
+
+/// Parse the command line again, reading the configuration anew
+fn reload_options() -> anyhow::Result<Options> {
+    match parse_args(std::env::args().skip(1))? {
+        Command::Run(options) => Ok(*options),
+        // the same arguments already asked for a run once
+        _ => unreachable!(),
+    }
+}
+
+/// What happened when the plugins ran over one map
+struct MapRun {
+    map_path: PathBuf,
+    output_path: Option<PathBuf>,
+    plugins: Vec<Plugin>,
+    diagnostics: Vec<Diagnostic>,
+    outcome: anyhow::Result<()>,
+    elapsed: Duration,
+}
+
+/// Run the plugins over every map, writing the report and diagnostics if
+/// asked for
+///
+/// Maps are processed on up to `jobs` threads, and the threads left over
+/// are shared out between the read-only plugins working on each map.  With
+/// several maps, failures are summarized once all of them are done.
+fn run_maps(
+    options: &Options,
+    map_paths: &[PathBuf],
+    linker: &Linker<PluginEnv>,
+    modules: &[Module],
+    logger: &Arc<Logger>,
+) -> anyhow::Result<()> {
+    let map_jobs = options.jobs.min(map_paths.len()).max(1);
+    let plugin_jobs = (options.jobs / map_jobs).max(1);
+
+    let next = AtomicUsize::new(0);
+    let slots = map_paths
+        .iter()
+        .map(|_| Mutex::new(None))
+        .collect::<Vec<_>>();
+
+    thread::scope(|scope| {
+        for _ in 0..map_jobs {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+
+                let Some(map_path) = map_paths.get(idx) else {
+                    break;
+                };
+
+                // with maps processed side by side, each map's log is held
+                // until the map is done so that their lines don't interleave
+                let map_logger = match map_jobs {
+                    1 => logger.clone(),
+                    _ => Arc::new(logger.holding()),
+                };
+
+                let run = run_map(
+                    options,
+                    map_path,
+                    linker,
+                    modules,
+                    &map_logger,
+                    plugin_jobs,
+                );
+
+                map_logger.release();
+
+                *slots[idx].lock().unwrap() = Some(run);
+            });
+        }
+    });
+
+    let mut runs = slots
+        .into_iter()
+        .map(|slot| slot.into_inner().unwrap().unwrap())
+        .collect::<Vec<_>>();
+
+    if let Some(report_path) = &options.report_path {
+        let reports = runs
+            .iter()
+            .map(|run| {
+                RunReport::new(
+                    &run.map_path,
+                    run.output_path.as_deref(),
+                    &run.outcome,
+                    run.elapsed,
+                    run.plugins
+                        .iter()
+                        .zip(options.plugins.iter().map(|spec| &spec.path)),
+                    &run.diagnostics,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        write_reports(report_path, &reports)?;
+    }
+
+    if let Some(json_path) = &options.diagnostics.json_path {
+        let diagnostics = runs
+            .iter()
+            .flat_map(|run| &run.diagnostics)
+            .collect::<Vec<_>>();
+
+        diagnostics::write_json(json_path, &diagnostics)?;
+    }
+
+    if runs.len() == 1 {
+        return runs.pop().unwrap().outcome;
+    }
+
+    let failures = runs
+        .iter()
+        .filter_map(|run| Some((&run.map_path, run.outcome.as_ref().err()?)))
+        .collect::<Vec<_>>();
+
+    for (map_path, e) in &failures {
+        eprintln!("{}: {}", map_path.display(), e);
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "{} of {} maps failed",
+            failures.len(),
+            runs.len()
+        ))
+    }
+}
+
+/// Run a fresh instance of every plugin over one map
+fn run_map(
+    options: &Options,
+    map_path: &Path,
+    linker: &Linker<PluginEnv>,
+    modules: &[Module],
+    logger: &Arc<Logger>,
+    jobs: usize,
+) -> MapRun {
+    let started = Instant::now();
+
+    let output_path = match &options.output_dir {
+        Some(dir) => Some(output_path_in(dir, map_path)),
+        None => options.output_path.clone(),
+    };
+
+    let mut plugins = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    let outcome = modules
+        .iter()
+        .zip(&options.plugins)
+        .map(|(module, spec)| {
+            Plugin::new(
+                linker,
+                module,
+                &spec.file_stem(),
+                spec.options.clone(),
+                logger.clone(),
+            )
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .and_then(|instances| {
+            plugins = instances;
+
+            let text = fs::read(map_path).map_err(|e| {
+                anyhow::anyhow!("Failed to read {}: {}", map_path.display(), e)
+            })?;
+
+            let name = map_path.display().to_string();
+
+            let map = match MapFormat::of(map_path) {
+                MapFormat::Map => read_map(name, text)?,
+                MapFormat::Json => read_json_map(&name, &text)?,
+                MapFormat::Ent => read_ent(name, text)?,
+                MapFormat::Bsp => read_bsp(name, text)?,
+            };
+
+            let map = Arc::new(map);
+
+            process_map(
+                options,
+                &mut plugins,
+                &map,
+                output_path.as_deref(),
+                jobs,
+                &mut diagnostics,
+            )
+        });
+
+    MapRun {
+        map_path: map_path.to_path_buf(),
+        output_path,
+        plugins,
+        diagnostics,
+        outcome,
+        elapsed: started.elapsed(),
+    }
+}
+
+/// Run the plugins over the map and write the result, collecting the
+/// diagnostics they report
+fn process_map(
+    options: &Options,
+    plugins: &mut [Plugin],
+    map: &Arc<MapData>,
+    output_path: Option<&Path>,
+    jobs: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let initialized = plugins.iter_mut().try_for_each(Plugin::init);
+
+    let mut processed = initialized.and_then(|()| {
+        pipeline::process(plugins, map.clone(), jobs, options.dry_run)
+    });
+
+    diagnostics.extend(plugins.iter_mut().flat_map(Plugin::take_diagnostics));
+
+    if let (Ok(processed), Some(style)) = (&mut processed, options.alignment) {
+        realign(processed, style, options.dry_run, diagnostics);
+    }
+
+    let reported = diagnostics::report(diagnostics, &options.diagnostics);
+    let processed = processed?;
+    reported?;
+
+    if options.dry_run {
+        print!("{}", processed.diff);
+        return Ok(());
+    }
+
+    if let Some(output_path) = output_path {
+        let format = MapFormat::of(output_path);
+
+        if format == MapFormat::Bsp && map.bsp().is_none() {
+            return Err(anyhow::anyhow!(
+                "Only a map read from a BSP can be written to {}",
+                output_path.display()
+            ));
+        }
+
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        let original = Some(map.as_ref()).filter(|_| !options.reformat);
+
+        match format {
+            MapFormat::Map => write_map(
+                &mut writer,
+                &processed.map,
+                original,
+                Some(&processed.origins),
+            )?,
+            MapFormat::Json => write_json_map(&mut writer, &processed.map)?,
+            MapFormat::Ent => write_ent(
+                &mut writer,
+                &processed.map,
+                original,
+                Some(&processed.origins),
+            )?,
+            MapFormat::Bsp => write_bsp(
+                &mut writer,
+                map.bsp().unwrap(),
+                &processed.map,
+                original,
+                Some(&processed.origins),
+            )?,
+        }
+
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Convert the texture alignment of the processed map, warning about each
+/// surface that could not be converted exactly and each brush left as it
+/// was
+///
+/// If `diff` is set, the conversion is described after the plugins'
+/// changes.
+fn realign(
+    processed: &mut Processed,
+    style: AlignmentStyle,
+    diff: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let converted = convert_map(&processed.map, style);
+
+    let warning = |code: &str, mesg: String, part| {
+        Diagnostic::new(
+            "qmpp-host".to_string(),
+            Severity::Warning,
+            code.to_string(),
+            mesg,
+            &processed.map,
+            Some(part),
+        )
+    };
+
+    // parts are located in the map as it was before converting
+    diagnostics.extend(converted.lossy.into_iter().map(|part| {
+        warning(
+            "lossy-alignment",
+            format!("Texture alignment cannot be converted to {}", style),
+            part,
+        )
+    }));
+
+    diagnostics.extend(converted.unconverted.into_iter().map(|part| {
+        warning(
+            "unconverted-alignment",
+            format!(
+                "Could not convert texture alignment to {} since a surface \
+                 is not a plane; the brush is left unchanged",
+                style
+            ),
+            part,
+        )
+    }));
+
+    // the conversion is applied as a patch so that it is described like a
+    // plugin's changes; it keeps every entity and brush where it was
+    let mut patcher = QuakeMapPatcher::new(processed.map.entities.len());
+
+    for (ent_idx, entity) in converted.map.entities.iter().enumerate() {
+        for (brush_idx, brush) in entity.brushes.iter().enumerate() {
+            for (surface_idx, surface) in brush.iter().enumerate() {
+                let old = &processed.map.entities[ent_idx].brushes[brush_idx]
+                    [surface_idx];
+
+                if !same_alignment(&old.alignment, &surface.alignment) {
+                    patcher.set_alignment(
+                        ent_idx,
+                        brush_idx,
+                        surface_idx,
+                        surface.alignment,
+                    );
+                }
+            }
+        }
+    }
+
+    let patched = patcher.patch(&processed.map);
+
+    if diff {
+        processed.diff.push_str(&describe(
+            "qmpp-host",
+            &patched.changes,
+            &processed.map,
+        ));
+    }
+
+    let extras = processed.map.extras().patched(&patched);
+    processed.map = Arc::new(
+        MapData::patched(patched.map, &processed.map, &patched.origins)
+            .with_extras(extras),
     );
-    let map = qmap::parse(reader).unwrap();
+}
+
+fn list_imports() {
+    for import in IMPORTS {
+        let phases = [Phase::Init, Phase::Process]
+            .into_iter()
+            .filter(|&phase| import.available_in(phase))
+            .map(|phase| phase.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!("{}  [{}]", import.signature(), phases);
 
-    init(&engine, &module);
-    process(&engine, &module, Arc::new(map));
+        for line in import.doc {
+            println!("    {}", line.trim());
+        }
+    }
 }
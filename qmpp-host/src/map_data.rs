@@ -0,0 +1,230 @@
+use std::ops::{Deref, RangeInclusive};
+use std::sync::{Arc, OnceLock};
+
+use quake_util::qmap::QuakeMap;
+
+use crate::bsp::BspFile;
+use crate::extras::MapExtras;
+use crate::geometry::MapGeometry;
+use crate::index::EntityIndex;
+use crate::links::LinkGraph;
+use crate::patch::EntityOrigin;
+use crate::source::MapSource;
+use crate::spatial::SpatialIndex;
+
+/// An entity, one of its brushes, or one of a brush's surfaces
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapPart {
+    Entity(usize),
+    Brush(usize, usize),
+    Surface(usize, usize, usize),
+}
+
+impl MapPart {
+    pub fn ent_idx(self) -> usize {
+        match self {
+            MapPart::Entity(ent_idx)
+            | MapPart::Brush(ent_idx, _)
+            | MapPart::Surface(ent_idx, _, _) => ent_idx,
+        }
+    }
+}
+
+/// A parsed map along with lookup structures derived from it
+///
+/// Lookup structures are built the first time they are needed and then
+/// shared by every plugin processing the map.
+pub struct MapData {
+    map: QuakeMap,
+    /// Text the map, or the map it was patched from, was read from
+    source: Option<Arc<MapSource>>,
+    /// Where each entity came from in the map `source` holds, if the map
+    /// was patched since it was read
+    origins: Option<Vec<Option<EntityOrigin>>>,
+    extras: MapExtras,
+    bsp: Option<BspFile>,
+    entity_index: OnceLock<EntityIndex>,
+    link_graph: OnceLock<LinkGraph>,
+    geometry: OnceLock<MapGeometry>,
+    spatial_index: OnceLock<SpatialIndex>,
+}
+
+impl MapData {
+    pub fn new(map: QuakeMap) -> Self {
+        Self::build(map, None)
+    }
+
+    /// Map along with the text it was parsed from
+    pub fn with_source(map: QuakeMap, source: MapSource) -> Self {
+        Self::build(map, Some(Arc::new(source)))
+    }
+
+    /// Map patched from `input`, sharing its source so that parts of the
+    /// map can still be found in the text
+    ///
+    /// `origins` gives where each entity came from in `input`, or `None` for
+    /// entities added since.
+    pub fn patched(
+        map: QuakeMap,
+        input: &MapData,
+        origins: &[Option<EntityOrigin>],
+    ) -> Self {
+        let origins = match &input.origins {
+            Some(input_origins) => origins
+                .iter()
+                .map(|origin| {
+                    let origin = origin.as_ref()?;
+                    let input_origin =
+                        input_origins.get(origin.ent_idx)?.as_ref()?;
+                    Some(input_origin.then(origin))
+                })
+                .collect(),
+            None => origins.to_vec(),
+        };
+
+        let mut patched = Self::build(map, input.source.clone());
+        patched.origins = Some(origins);
+        patched
+    }
+
+    fn build(map: QuakeMap, source: Option<Arc<MapSource>>) -> Self {
+        Self {
+            map,
+            source,
+            origins: None,
+            extras: MapExtras::default(),
+            bsp: None,
+            entity_index: OnceLock::new(),
+            link_graph: OnceLock::new(),
+            geometry: OnceLock::new(),
+            spatial_index: OnceLock::new(),
+        }
+    }
+
+    /// Map along with the parts of it the map parser had no place for
+    pub fn with_extras(mut self, extras: MapExtras) -> Self {
+        self.extras = extras;
+        self
+    }
+
+    /// Map of the entities of a compiled BSP, along with the rest of it
+    pub fn with_bsp(mut self, bsp: BspFile) -> Self {
+        self.bsp = Some(bsp);
+        self
+    }
+
+    /// Text the map was read from, or `None` if it was patched since
+    pub fn source(&self) -> Option<&MapSource> {
+        self.source.as_deref().filter(|_| self.origins.is_none())
+    }
+
+    /// Name of the file the map, or the map it was patched from, was read
+    /// from
+    pub fn source_name(&self) -> Option<&str> {
+        self.source.as_deref().map(MapSource::name)
+    }
+
+    /// Lines of the file holding the part, as `MapSource::lines`, following
+    /// the part back through any patches
+    pub fn source_lines(&self, part: MapPart) -> Option<RangeInclusive<usize>> {
+        let source = self.source.as_deref()?;
+
+        let Some(origins) = &self.origins else {
+            return source.lines(part);
+        };
+
+        let origin = origins.get(part.ent_idx())?.as_ref()?;
+        let brush = |brush_idx: usize| *origin.brushes.get(brush_idx)?;
+
+        source.lines(match part {
+            MapPart::Entity(_) => MapPart::Entity(origin.ent_idx),
+            MapPart::Brush(_, brush_idx) => {
+                MapPart::Brush(origin.ent_idx, brush(brush_idx)?)
+            }
+            MapPart::Surface(_, brush_idx, surface_idx) => {
+                MapPart::Surface(origin.ent_idx, brush(brush_idx)?, surface_idx)
+            }
+        })
+    }
+
+    pub fn extras(&self) -> &MapExtras {
+        &self.extras
+    }
+
+    pub fn bsp(&self) -> Option<&BspFile> {
+        self.bsp.as_ref()
+    }
+
+    /// Whether the entity has brushes or Quake 3 patches
+    pub fn has_brushes(&self, ent_idx: usize) -> bool {
+        !self.entities[ent_idx].brushes.is_empty()
+            || !self.extras.patches(ent_idx).is_empty()
+    }
+
+    /// Description of the part for messages, such as
+    /// `e1m1.map:1234: func_door brush 3 surface 2`
+    ///
+    /// The file and line are left out if the map was not read from a file.
+    pub fn location(&self, part: MapPart) -> String {
+        let ent_idx = part.ent_idx();
+
+        let entity = self
+            .entities
+            .get(ent_idx)
+            .and_then(|entity| entity.edict.get(c"classname"))
+            .map_or_else(
+                || format!("entity {}", ent_idx),
+                |classname| classname.to_string_lossy().into_owned(),
+            );
+
+        let what = match part {
+            MapPart::Entity(_) => entity,
+            MapPart::Brush(_, brush_idx) => {
+                format!("{} brush {}", entity, brush_idx)
+            }
+            MapPart::Surface(_, brush_idx, surface_idx) => {
+                format!(
+                    "{} brush {} surface {}",
+                    entity, brush_idx, surface_idx
+                )
+            }
+        };
+
+        let lines = self
+            .source_name()
+            .and_then(|name| Some((name, self.source_lines(part)?)));
+
+        match lines {
+            Some((name, lines)) => {
+                format!("{}:{}: {}", name, lines.start(), what)
+            }
+            None => what,
+        }
+    }
+
+    pub fn entity_index(&self) -> &EntityIndex {
+        self.entity_index
+            .get_or_init(|| EntityIndex::new(&self.map))
+    }
+
+    pub fn link_graph(&self) -> &LinkGraph {
+        self.link_graph.get_or_init(|| LinkGraph::new(&self.map))
+    }
+
+    pub fn geometry(&self) -> &MapGeometry {
+        self.geometry.get_or_init(|| MapGeometry::new(&self.map))
+    }
+
+    pub fn spatial_index(&self) -> &SpatialIndex {
+        self.spatial_index
+            .get_or_init(|| SpatialIndex::new(&self.map, self.geometry()))
+    }
+}
+
+impl Deref for MapData {
+    type Target = QuakeMap;
+
+    fn deref(&self) -> &QuakeMap {
+        &self.map
+    }
+}
@@ -1,57 +1,552 @@
-use quake_util::qmap::{
-    QuakeMap,
-    Edict
-};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::CString;
+use std::ops::AddAssign;
 
+use quake_util::qmap::{Alignment, Brush, Edict, Entity, QuakeMap};
+
+use serde::Serialize;
+
+use crate::alignment::same_alignment;
+
+#[derive(Default)]
 pub enum Patch<T> {
+    #[default]
     Leave,
     Delete,
     Modify(T),
 }
 
-impl<T> Default for Patch<T> {
-    fn default() -> Self {
-        Self::Leave
-    }
-}
-
+/// Changes to the keys of one entity
+#[derive(Default)]
 pub struct EdictPatch {
-    map: HashMap<CString, Patch<CString>>
+    map: BTreeMap<CString, Patch<CString>>,
 }
 
 impl EdictPatch {
-    fn get(&self, key: &'a CStr) -> Patch<&'a CStr> {
-        &self.map.get(key.into()).unwrap_or_default()
+    pub fn set(&mut self, key: CString, value: CString) {
+        self.map.insert(key, Patch::Modify(value));
+    }
+
+    pub fn delete(&mut self, key: CString) {
+        self.map.insert(key, Patch::Delete);
     }
 
-    fn set(&mut self, key: &'a CStr, value: CString) {
-        self.map.set(key.into(), value);
+    /// Apply the changes, returning those that made a difference in key
+    /// order
+    fn apply(&self, edict: &mut Edict) -> Vec<KeyChange> {
+        let mut changes = Vec::new();
+
+        for (key, patch) in &self.map {
+            let (old, new) = match patch {
+                Patch::Leave => continue,
+                Patch::Delete => (edict.remove(key), None),
+                Patch::Modify(value) => {
+                    (edict.insert(key.clone(), value.clone()), Some(value))
+                }
+            };
+
+            if old.as_ref() != new {
+                changes.push(KeyChange {
+                    key: key.clone(),
+                    old,
+                    new: new.cloned(),
+                });
+            }
+        }
+
+        changes
     }
 }
 
+/// Changes to one entity of the map being patched
+#[derive(Default)]
+pub struct EntityPatcher {
+    edict_patch: EdictPatch,
+    deleted_brushes: BTreeSet<usize>,
+    /// New textures by brush and surface index
+    textures: BTreeMap<(usize, usize), CString>,
+    /// New texture alignments by brush and surface index
+    alignments: BTreeMap<(usize, usize), Alignment>,
+    /// Brushes to add after the entity's own
+    added_brushes: Vec<Brush>,
+}
+
+impl EntityPatcher {
+    /// Patched copy of the entity along with what changed, which is also
+    /// counted in `result`
+    fn apply(
+        &self,
+        ent_idx: usize,
+        entity: &Entity,
+        result: &mut PatchResult,
+    ) -> (Entity, Option<EntityChange>) {
+        let mut patched = entity.clone();
+        let keys = self.edict_patch.apply(&mut patched.edict);
+        let mut textures = Vec::new();
+        let mut alignments = Vec::new();
+        let mut changed_surfaces = BTreeSet::new();
+
+        for (&(brush_idx, surface_idx), texture) in &self.textures {
+            if self.deleted_brushes.contains(&brush_idx) {
+                continue;
+            }
+
+            let Some(surface) = patched
+                .brushes
+                .get_mut(brush_idx)
+                .and_then(|brush| brush.get_mut(surface_idx))
+            else {
+                continue;
+            };
+
+            if surface.texture != *texture {
+                let old =
+                    std::mem::replace(&mut surface.texture, texture.clone());
+
+                textures.push(TextureChange {
+                    brush_idx,
+                    surface_idx,
+                    old,
+                    new: texture.clone(),
+                });
+
+                changed_surfaces.insert((brush_idx, surface_idx));
+            }
+        }
+
+        for (&(brush_idx, surface_idx), alignment) in &self.alignments {
+            if self.deleted_brushes.contains(&brush_idx) {
+                continue;
+            }
+
+            let Some(surface) = patched
+                .brushes
+                .get_mut(brush_idx)
+                .and_then(|brush| brush.get_mut(surface_idx))
+            else {
+                continue;
+            };
+
+            if !same_alignment(&surface.alignment, alignment) {
+                let old = std::mem::replace(&mut surface.alignment, *alignment);
+
+                alignments.push(AlignmentChange {
+                    brush_idx,
+                    surface_idx,
+                    old,
+                    new: *alignment,
+                });
+
+                changed_surfaces.insert((brush_idx, surface_idx));
+            }
+        }
+
+        let changed_brushes = changed_surfaces
+            .iter()
+            .map(|&(brush_idx, _)| brush_idx)
+            .collect::<BTreeSet<_>>();
+
+        result.surfaces_modified += changed_surfaces.len();
+        result.brushes_modified += changed_brushes.len();
+
+        let removed_brushes = self
+            .deleted_brushes
+            .iter()
+            .copied()
+            .filter(|&brush_idx| brush_idx < entity.brushes.len())
+            .collect::<Vec<_>>();
+
+        for &brush_idx in removed_brushes.iter().rev() {
+            let brush = patched.brushes.remove(brush_idx);
+            result.brushes_removed += 1;
+            result.surfaces_removed += brush.len();
+        }
+
+        let added_brushes = (patched.brushes.len()..)
+            .take(self.added_brushes.len())
+            .collect::<Vec<_>>();
+
+        for brush in &self.added_brushes {
+            patched.brushes.push(brush.clone());
+            result.brushes_added += 1;
+            result.surfaces_added += brush.len();
+        }
+
+        if keys.is_empty()
+            && textures.is_empty()
+            && alignments.is_empty()
+            && removed_brushes.is_empty()
+            && added_brushes.is_empty()
+        {
+            return (patched, None);
+        }
+
+        result.entities_modified += 1;
+
+        let change = EntityChange::Modified {
+            ent_idx,
+            keys,
+            removed_brushes,
+            added_brushes,
+            textures,
+            alignments,
+        };
+
+        (patched, Some(change))
+    }
+}
+
+/// Changes a plugin makes to a map
+///
+/// Entities are identified by their index in the map being patched, and
+/// entities added by the plugin follow on from the last of those.  Changes
+/// are only applied once the plugin is done, so the plugin keeps reading
+/// the map as it was.
 pub struct QuakeMapPatcher {
-    entity_patches: Vec<EntityPatcher>,
-    qmap: QuakeMap,
+    entity_patches: Vec<Patch<EntityPatcher>>,
+    /// Entities added by the plugin, or `None` once deleted again
+    added: Vec<Option<Entity>>,
 }
 
 impl QuakeMapPatcher {
-    pub fn new(qmap: QuakeMap) -> Self {
+    pub fn new(entity_count: usize) -> Self {
         Self {
-            entity_patches: qmap.entities
+            entity_patches: (0..entity_count).map(|_| Patch::Leave).collect(),
+            added: Vec::new(),
+        }
+    }
+
+    /// Whether no changes have been made
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self
+                .entity_patches
                 .iter()
-                .map(|ent| Patch::<EntityPatcher>::Leave)
-                .collect(),
-            qmap,
+                .all(|patch| matches!(patch, Patch::Leave))
+    }
+
+    /// Add an entity without any keys, returning its index
+    pub fn add_entity(&mut self) -> usize {
+        self.added.push(Some(Entity {
+            edict: Edict::new(),
+            brushes: Vec::new(),
+        }));
+
+        self.entity_patches.len() + self.added.len() - 1
+    }
+
+    /// Delete an entity, returning false if there is no such entity
+    pub fn delete_entity(&mut self, ent_idx: usize) -> bool {
+        match self.entity_patches.get_mut(ent_idx) {
+            Some(Patch::Delete) => false,
+            Some(patch) => {
+                *patch = Patch::Delete;
+                true
+            }
+            None => self
+                .added
+                .get_mut(ent_idx - self.entity_patches.len())
+                .is_some_and(|entity| entity.take().is_some()),
         }
     }
 
-    pub fn patch(self, qmap: &mut QuakeMap) -> PatchResult {
-        self.entity_patches
+    /// Set a key of an entity, returning false if there is no such entity
+    pub fn set_keyvalue(
+        &mut self,
+        ent_idx: usize,
+        key: CString,
+        value: CString,
+    ) -> bool {
+        if let Some(entity) = self.added_entity(ent_idx) {
+            entity.edict.insert(key, value);
+            return true;
+        }
+
+        self.entity(ent_idx)
+            .map(|patcher| patcher.edict_patch.set(key, value))
+            .is_some()
+    }
+
+    /// Delete a key of an entity, returning false if there is no such
+    /// entity
+    pub fn delete_keyvalue(&mut self, ent_idx: usize, key: CString) -> bool {
+        if let Some(entity) = self.added_entity(ent_idx) {
+            entity.edict.remove(&key);
+            return true;
+        }
+
+        self.entity(ent_idx)
+            .map(|patcher| patcher.edict_patch.delete(key))
+            .is_some()
+    }
+
+    /// Add a brush to an entity, after the brushes it already has, returning
+    /// false if there is no such entity
+    pub fn add_brush(&mut self, ent_idx: usize, brush: Brush) -> bool {
+        if let Some(entity) = self.added_entity(ent_idx) {
+            entity.brushes.push(brush);
+            return true;
+        }
+
+        self.entity(ent_idx)
+            .map(|patcher| patcher.added_brushes.push(brush))
+            .is_some()
+    }
+
+    /// Delete a brush of an entity from the map being patched, returning
+    /// false if the entity has been deleted
+    pub fn delete_brush(&mut self, ent_idx: usize, brush_idx: usize) -> bool {
+        self.entity(ent_idx)
+            .map(|patcher| patcher.deleted_brushes.insert(brush_idx))
+            .is_some()
+    }
+
+    /// Set the texture of a surface of an entity from the map being
+    /// patched, returning false if the entity has been deleted
+    pub fn set_texture(
+        &mut self,
+        ent_idx: usize,
+        brush_idx: usize,
+        surface_idx: usize,
+        texture: CString,
+    ) -> bool {
+        self.entity(ent_idx)
+            .map(|patcher| {
+                patcher.textures.insert((brush_idx, surface_idx), texture)
+            })
+            .is_some()
+    }
+
+    /// Set the texture alignment of a surface of an entity from the map
+    /// being patched, returning false if the entity has been deleted
+    pub fn set_alignment(
+        &mut self,
+        ent_idx: usize,
+        brush_idx: usize,
+        surface_idx: usize,
+        alignment: Alignment,
+    ) -> bool {
+        self.entity(ent_idx)
+            .map(|patcher| {
+                patcher
+                    .alignments
+                    .insert((brush_idx, surface_idx), alignment)
+            })
+            .is_some()
+    }
+
+    /// Apply the changes to the map they were made against
+    pub fn patch(&self, map: &QuakeMap) -> PatchedMap {
+        let mut result = PatchResult::default();
+        let mut patched = QuakeMap::new();
+        let mut origins = Vec::new();
+        let mut changes = Vec::new();
+
+        for (ent_idx, entity) in map.entities.iter().enumerate() {
+            let patch = self.entity_patches.get(ent_idx);
+
+            let origin = match patch.unwrap_or(&Patch::Leave) {
+                Patch::Leave => {
+                    patched.entities.push(entity.clone());
+                    EntityOrigin::of(ent_idx, entity)
+                }
+                Patch::Delete => {
+                    result.entities_removed += 1;
+                    result.brushes_removed += entity.brushes.len();
+                    result.surfaces_removed +=
+                        entity.brushes.iter().map(Vec::len).sum::<usize>();
+                    changes.push(EntityChange::Removed { ent_idx });
+                    continue;
+                }
+                Patch::Modify(patcher) => {
+                    let (patched_entity, change) =
+                        patcher.apply(ent_idx, entity, &mut result);
+
+                    patched.entities.push(patched_entity);
+                    changes.extend(change);
+
+                    let kept = (0..entity.brushes.len())
+                        .filter(|idx| !patcher.deleted_brushes.contains(idx))
+                        .map(Some);
+                    let added = patcher.added_brushes.iter().map(|_| None);
+
+                    EntityOrigin {
+                        ent_idx,
+                        brushes: kept.chain(added).collect(),
+                    }
+                }
+            };
+
+            origins.push(Some(origin));
+        }
+
+        for entity in self.added.iter().flatten() {
+            let mut keys = entity
+                .edict
+                .iter()
+                .map(|(key, value)| KeyChange {
+                    key: key.clone(),
+                    old: None,
+                    new: Some(value.clone()),
+                })
+                .collect::<Vec<_>>();
+
+            keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+            changes.push(EntityChange::Added {
+                ent_idx: patched.entities.len(),
+                keys,
+            });
+
+            result.entities_added += 1;
+            result.brushes_added += entity.brushes.len();
+            result.surfaces_added +=
+                entity.brushes.iter().map(Vec::len).sum::<usize>();
+            patched.entities.push(entity.clone());
+            origins.push(None);
+        }
+
+        PatchedMap {
+            map: patched,
+            origins,
+            result,
+            changes,
+        }
+    }
+
+    fn entity(&mut self, ent_idx: usize) -> Option<&mut EntityPatcher> {
+        let patch = self.entity_patches.get_mut(ent_idx)?;
+
+        if let Patch::Leave = patch {
+            *patch = Patch::Modify(EntityPatcher::default());
+        }
+
+        match patch {
+            Patch::Modify(patcher) => Some(patcher),
+            _ => None,
+        }
+    }
+
+    fn added_entity(&mut self, ent_idx: usize) -> Option<&mut Entity> {
+        let added_idx = ent_idx.checked_sub(self.entity_patches.len())?;
+        self.added.get_mut(added_idx)?.as_mut()
     }
 }
 
-pub struct EntityPatcher {
-    entity: Entity,
-    edict_patch: EdictPatch,
+/// Where an entity of a patched map came from
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EntityOrigin {
+    /// Index of the entity in the map before patching
+    pub ent_idx: usize,
+    /// For each of the entity's brushes, its index in that entity, or
+    /// `None` if it was added
+    pub brushes: Vec<Option<usize>>,
+}
+
+impl EntityOrigin {
+    /// Origin of an entity left as it was
+    pub fn of(ent_idx: usize, entity: &Entity) -> Self {
+        Self {
+            ent_idx,
+            brushes: (0..entity.brushes.len()).map(Some).collect(),
+        }
+    }
+
+    /// Origin of an entity that came from `later` in a map patched from the
+    /// one this entity is in
+    pub fn then(&self, later: &EntityOrigin) -> Self {
+        Self {
+            ent_idx: self.ent_idx,
+            brushes: later
+                .brushes
+                .iter()
+                .map(|&brush_idx| self.brushes[brush_idx?])
+                .collect(),
+        }
+    }
+}
+
+/// A map with a plugin's changes applied
+pub struct PatchedMap {
+    pub map: QuakeMap,
+    /// For each entity, where it came from in the map before patching, or
+    /// `None` if it was added
+    pub origins: Vec<Option<EntityOrigin>>,
+    pub result: PatchResult,
+    pub changes: Vec<EntityChange>,
+}
+
+/// What a patch did to one entity
+pub enum EntityChange {
+    /// The entity at `ent_idx` in the map before patching was changed
+    Modified {
+        ent_idx: usize,
+        keys: Vec<KeyChange>,
+        /// Indices of the brushes removed
+        removed_brushes: Vec<usize>,
+        /// Indices the brushes added have in the patched entity
+        added_brushes: Vec<usize>,
+        textures: Vec<TextureChange>,
+        alignments: Vec<AlignmentChange>,
+    },
+    /// The entity at `ent_idx` in the map before patching was removed
+    Removed { ent_idx: usize },
+    /// An entity was added at `ent_idx` in the patched map
+    Added {
+        ent_idx: usize,
+        keys: Vec<KeyChange>,
+    },
+}
+
+/// A key whose value was set or removed
+pub struct KeyChange {
+    pub key: CString,
+    /// `None` if the key was added
+    pub old: Option<CString>,
+    /// `None` if the key was removed
+    pub new: Option<CString>,
 }
 
+/// A surface whose texture was changed
+pub struct TextureChange {
+    pub brush_idx: usize,
+    pub surface_idx: usize,
+    pub old: CString,
+    pub new: CString,
+}
+
+/// A surface whose texture alignment was changed
+pub struct AlignmentChange {
+    pub brush_idx: usize,
+    pub surface_idx: usize,
+    pub old: Alignment,
+    pub new: Alignment,
+}
+
+/// How many parts of a map were added, removed or modified
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug, Serialize)]
+pub struct PatchResult {
+    pub entities_added: usize,
+    pub entities_removed: usize,
+    pub entities_modified: usize,
+    pub brushes_added: usize,
+    pub brushes_removed: usize,
+    pub brushes_modified: usize,
+    pub surfaces_added: usize,
+    pub surfaces_removed: usize,
+    pub surfaces_modified: usize,
+}
+
+impl AddAssign for PatchResult {
+    fn add_assign(&mut self, other: Self) {
+        self.entities_added += other.entities_added;
+        self.entities_removed += other.entities_removed;
+        self.entities_modified += other.entities_modified;
+        self.brushes_added += other.brushes_added;
+        self.brushes_removed += other.brushes_removed;
+        self.brushes_modified += other.brushes_modified;
+        self.surfaces_added += other.surfaces_added;
+        self.surfaces_removed += other.surfaces_removed;
+        self.surfaces_modified += other.surfaces_modified;
+    }
+}
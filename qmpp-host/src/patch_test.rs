@@ -0,0 +1,173 @@
+use std::ffi::CString;
+
+use quake_util::qmap::{self, QuakeMap};
+
+use crate::patch::{EntityOrigin, PatchResult, QuakeMapPatcher};
+
+const MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"gfx.wad\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+{
+( 32 -16 -16 ) ( 32 -15 -16 ) ( 32 -16 -15 ) base 0 0 0 1 1
+( 32 -16 -16 ) ( 32 -16 -15 ) ( 33 -16 -16 ) base 0 0 0 1 1
+( 32 -16 -16 ) ( 33 -16 -16 ) ( 32 -15 -16 ) base 0 0 0 1 1
+( 64 16 16 ) ( 64 17 16 ) ( 65 16 16 ) base 0 0 0 1 1
+( 64 16 16 ) ( 65 16 16 ) ( 64 16 17 ) base 0 0 0 1 1
+( 64 16 16 ) ( 64 16 17 ) ( 64 17 16 ) base 0 0 0 1 1
+}
+}
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+{
+\"classname\" \"info_null\"
+}
+";
+
+fn map() -> QuakeMap {
+    qmap::parse(MAP.as_bytes()).unwrap()
+}
+
+fn value(text: &str) -> CString {
+    CString::new(text).unwrap()
+}
+
+#[test]
+fn untouched_map_has_no_patch() {
+    let mut patcher = QuakeMapPatcher::new(3);
+    assert!(patcher.is_empty());
+
+    patcher.set_keyvalue(1, c"light".into(), value("300"));
+    assert!(!patcher.is_empty());
+}
+
+#[test]
+fn changes_are_applied_and_counted() {
+    let map = map();
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+
+    assert!(patcher.delete_keyvalue(0, c"wad".into()));
+    assert!(patcher.set_texture(0, 0, 2, c"sky1".into()));
+    assert!(patcher.set_texture(0, 0, 3, c"base".into()));
+    assert!(patcher.delete_brush(0, 1));
+    assert!(patcher.delete_entity(1));
+    assert!(!patcher.set_keyvalue(1, c"light".into(), value("300")));
+    assert!(!patcher.delete_entity(1));
+
+    let added = patcher.add_entity();
+    assert_eq!(added, 3);
+    assert!(patcher.set_keyvalue(added, c"classname".into(), value("light")));
+
+    let patched = patcher.patch(&map);
+
+    assert_eq!(
+        patched.origins,
+        [
+            Some(EntityOrigin {
+                ent_idx: 0,
+                brushes: vec![Some(0)],
+            }),
+            Some(EntityOrigin {
+                ent_idx: 2,
+                brushes: Vec::new(),
+            }),
+            None,
+        ]
+    );
+    assert_eq!(
+        patched.result,
+        PatchResult {
+            entities_added: 1,
+            entities_removed: 1,
+            entities_modified: 1,
+            brushes_removed: 1,
+            brushes_modified: 1,
+            surfaces_removed: 6,
+            surfaces_modified: 1,
+            ..PatchResult::default()
+        }
+    );
+
+    let worldspawn = &patched.map.entities[0];
+    assert_eq!(worldspawn.edict.get(c"wad"), None);
+    assert_eq!(worldspawn.brushes.len(), 1);
+    assert_eq!(worldspawn.brushes[0][2].texture.as_c_str(), c"sky1");
+
+    let added = &patched.map.entities[2];
+    assert_eq!(added.edict.get(c"classname"), Some(&value("light")));
+}
+
+#[test]
+fn added_brushes_are_counted() {
+    let map = map();
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+    let brush = &map.entities[0].brushes[1];
+
+    assert!(patcher.delete_brush(0, 0));
+    assert!(patcher.add_brush(0, brush.clone()));
+    assert!(patcher.delete_entity(1));
+    assert!(!patcher.add_brush(1, brush.clone()));
+
+    let added = patcher.add_entity();
+    assert!(patcher.add_brush(added, brush.clone()));
+
+    let patched = patcher.patch(&map);
+
+    assert_eq!(
+        patched.result,
+        PatchResult {
+            entities_added: 1,
+            entities_removed: 1,
+            entities_modified: 1,
+            brushes_added: 2,
+            brushes_removed: 1,
+            surfaces_added: 12,
+            surfaces_removed: 6,
+            ..PatchResult::default()
+        }
+    );
+
+    assert_eq!(
+        patched.origins[0],
+        Some(EntityOrigin {
+            ent_idx: 0,
+            brushes: vec![Some(1), None],
+        })
+    );
+
+    let worldspawn = &patched.map.entities[0];
+    assert_eq!(worldspawn.brushes.len(), 2);
+    assert_eq!(worldspawn.brushes[1].len(), brush.len());
+    assert_eq!(patched.map.entities[2].brushes.len(), 1);
+}
+
+#[test]
+fn alignment_changes_count_once_per_surface() {
+    let map = map();
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+    let mut alignment = map.entities[0].brushes[0][2].alignment;
+    alignment.rotation = 90.0;
+
+    assert!(patcher.set_texture(0, 0, 2, c"sky1".into()));
+    assert!(patcher.set_alignment(0, 0, 2, alignment));
+    assert!(patcher.set_alignment(0, 1, 0, alignment));
+
+    let patched = patcher.patch(&map);
+
+    assert_eq!(patched.result.brushes_modified, 2);
+    assert_eq!(patched.result.surfaces_modified, 2);
+    assert_eq!(
+        patched.map.entities[0].brushes[0][2].alignment.rotation,
+        90.0
+    );
+}
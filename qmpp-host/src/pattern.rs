@@ -0,0 +1,79 @@
+/// Match `text` against a glob `pattern`, where `*` matches any run of
+/// bytes and `?` matches any single byte
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p_idx, mut t_idx) = (0, 0);
+
+    // position of the last `*` seen and the text position it was tried at
+    let mut backtrack = None;
+
+    while t_idx < text.len() {
+        match pattern.get(p_idx) {
+            Some(b'*') => {
+                backtrack = Some((p_idx, t_idx));
+                p_idx += 1;
+            }
+            Some(&ch) if ch == b'?' || ch == text[t_idx] => {
+                p_idx += 1;
+                t_idx += 1;
+            }
+            _ => match backtrack {
+                Some((star_idx, star_t_idx)) => {
+                    backtrack = Some((star_idx, star_t_idx + 1));
+                    p_idx = star_idx + 1;
+                    t_idx = star_t_idx + 1;
+                }
+                None => {
+                    return false;
+                }
+            },
+        }
+    }
+
+    pattern[p_idx..].iter().all(|&ch| ch == b'*')
+}
+
+/// How a pattern is compared against text
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MatchMode {
+    Exact,
+    Prefix,
+    Glob,
+}
+
+impl MatchMode {
+    pub fn matches(self, pattern: &[u8], text: &[u8]) -> bool {
+        match self {
+            MatchMode::Exact => pattern == text,
+            MatchMode::Prefix => text.starts_with(pattern),
+            MatchMode::Glob => glob_match(pattern, text),
+        }
+    }
+
+    /// The part of `pattern` every matching text must start with
+    pub fn literal_prefix(self, pattern: &[u8]) -> &[u8] {
+        match self {
+            MatchMode::Exact | MatchMode::Prefix => pattern,
+            MatchMode::Glob => {
+                let end = pattern
+                    .iter()
+                    .position(|&ch| ch == b'*' || ch == b'?')
+                    .unwrap_or(pattern.len());
+
+                &pattern[..end]
+            }
+        }
+    }
+}
+
+impl TryFrom<i32> for MatchMode {
+    type Error = anyhow::Error;
+
+    fn try_from(mode: i32) -> anyhow::Result<Self> {
+        match mode {
+            0 => Ok(MatchMode::Exact),
+            1 => Ok(MatchMode::Prefix),
+            2 => Ok(MatchMode::Glob),
+            _ => Err(anyhow::anyhow!("Bad match mode {}", mode)),
+        }
+    }
+}
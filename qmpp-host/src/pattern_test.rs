@@ -0,0 +1,26 @@
+use crate::pattern::glob_match;
+
+#[test]
+fn glob_literal() {
+    assert!(glob_match(b"func_door", b"func_door"));
+    assert!(!glob_match(b"func_door", b"func_doors"));
+    assert!(!glob_match(b"func_door", b"func_doo"));
+}
+
+#[test]
+fn glob_wildcards() {
+    assert!(glob_match(b"func_*", b"func_button"));
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"info_player_*", b"info_player_start"));
+    assert!(glob_match(b"*_start", b"info_player_start"));
+    assert!(glob_match(b"light?", b"light1"));
+    assert!(!glob_match(b"light?", b"light"));
+    assert!(!glob_match(b"trigger_*", b"func_button"));
+}
+
+#[test]
+fn glob_backtracking() {
+    assert!(glob_match(b"*a*b", b"xaxxab"));
+    assert!(glob_match(b"a*a*a", b"aaaa"));
+    assert!(!glob_match(b"*a*b", b"xaxxa"));
+}
@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::diff::describe;
+use crate::map_data::MapData;
+use crate::patch::EntityOrigin;
+use crate::plugin::Plugin;
+
+/// The map left once every plugin has processed it
+pub struct Processed {
+    pub map: Arc<MapData>,
+    /// For each entity, where it came from in the input map, or `None` if a
+    /// plugin added it
+    pub origins: Vec<Option<EntityOrigin>>,
+    /// Description of every change plugins made, if asked for
+    pub diff: String,
+}
+
+/// Run the process hooks of every plugin in order
+///
+/// Plugins that modify the map run on their own, and their changes are
+/// applied before the next plugin runs.  Consecutive read-only plugins are
+/// run concurrently on up to `jobs` threads.  Their logs are held until the
+/// whole batch is done and then written in plugin order, and the first
+/// failure in plugin order is returned, so output does not depend on
+/// scheduling.
+///
+/// If `diff` is set, each plugin's changes are described as they are
+/// applied.
+pub fn process(
+    plugins: &mut [Plugin],
+    mut map: Arc<MapData>,
+    jobs: usize,
+    diff: bool,
+) -> anyhow::Result<Processed> {
+    let mut origins = map
+        .entities
+        .iter()
+        .enumerate()
+        .map(|(ent_idx, entity)| Some(EntityOrigin::of(ent_idx, entity)))
+        .collect::<Vec<_>>();
+    let mut description = String::new();
+    let mut remaining = plugins;
+
+    while !remaining.is_empty() {
+        let batch_len = remaining
+            .iter()
+            .take_while(|plugin| plugin.is_read_only())
+            .count()
+            .max(1);
+
+        let (batch, rest) = remaining.split_at_mut(batch_len);
+
+        if batch.len() == 1 || jobs <= 1 {
+            for plugin in batch.iter_mut() {
+                plugin.process(map.clone())?;
+
+                if let Some(patched) = plugin.patched(&map) {
+                    if diff {
+                        description.push_str(&describe(
+                            plugin.name(),
+                            &patched.changes,
+                            &map,
+                        ));
+                    }
+
+                    origins = patched
+                        .origins
+                        .iter()
+                        .map(|origin| {
+                            let origin = origin.as_ref()?;
+                            Some(origins[origin.ent_idx].as_ref()?.then(origin))
+                        })
+                        .collect();
+
+                    let extras = map.extras().patched(&patched);
+                    map = Arc::new(
+                        MapData::patched(patched.map, &map, &patched.origins)
+                            .with_extras(extras),
+                    );
+                }
+            }
+        } else {
+            process_concurrently(batch, &map, jobs)?;
+        }
+
+        remaining = rest;
+    }
+
+    Ok(Processed {
+        map,
+        origins,
+        diff: description,
+    })
+}
+
+fn process_concurrently(
+    batch: &mut [Plugin],
+    map: &Arc<MapData>,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    batch.iter_mut().for_each(Plugin::buffer_logs);
+
+    let next = AtomicUsize::new(0);
+    let slots = batch.iter_mut().map(Mutex::new).collect::<Vec<_>>();
+    let results = slots.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(slots.len()) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+
+                let Some(slot) = slots.get(idx) else {
+                    break;
+                };
+
+                let result = slot.lock().unwrap().process(map.clone());
+                *results[idx].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    let mut first_failure = None;
+
+    for (slot, result) in slots.into_iter().zip(results) {
+        slot.into_inner().unwrap().flush_logs();
+
+        if let Some(Err(e)) = result.into_inner().unwrap() {
+            first_failure.get_or_insert(e);
+        }
+    }
+
+    match first_failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
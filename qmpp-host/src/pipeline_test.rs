@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use quake_util::qmap;
+use wasmtime::{Engine, Module};
+
+use crate::cli::LogOptions;
+use crate::logging::{LogLevel, Logger};
+use crate::map_data::MapData;
+use crate::patch::EntityOrigin;
+use crate::pipeline::process;
+use crate::plugin::{linker, Plugin};
+
+/// Plugin that spins for `spin` iterations when processing a map and then
+/// traps if `fails` is set
+fn spinning_plugin(spin: u32, fails: bool) -> String {
+    let trap = if fails { "unreachable" } else { "" };
+
+    format!(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "QMPP_Hook_init"))
+            (func (export "QMPP_Hook_process")
+                (local $i i32)
+                (local.set $i (i32.const {spin}))
+                (block $done
+                    (loop $spin
+                        (br_if $done (i32.eqz (local.get $i)))
+                        (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+                        (br $spin)))
+                {trap}))"#
+    )
+}
+
+/// Plugin registered as `name` that logs "count1" or "count2" depending on
+/// how many entities it sees, spinning for `spin` iterations first and
+/// adding an entity afterwards if `writes` is set
+fn counting_plugin(name: char, spin: u32, writes: bool) -> String {
+    let (add_import, add_call) = if writes {
+        (
+            r#"(import "env" "QMPP_entity_add" (func $add (result i32)))"#,
+            "(drop (call $add))",
+        )
+    } else {
+        ("", "")
+    };
+
+    format!(
+        r#"(module
+            (import "env" "QMPP_register" (func $register (param i32 i32)))
+            (import "env" "QMPP_log_info" (func $log_info (param i32 i32)))
+            (import "env" "QMPP_ehandle_count" (func $count (result i32)))
+            {add_import}
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{name}count1count2")
+            (func (export "QMPP_Hook_init")
+                (call $register (i32.const 1) (i32.const 0)))
+            (func (export "QMPP_Hook_process")
+                (local $i i32)
+                (local.set $i (i32.const {spin}))
+                (block $done
+                    (loop $spin
+                        (br_if $done (i32.eqz (local.get $i)))
+                        (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+                        (br $spin)))
+                (call $log_info
+                    (i32.const 6)
+                    (i32.sub
+                        (i32.mul (call $count) (i32.const 6))
+                        (i32.const 5)))
+                {add_call}))"#
+    )
+}
+
+fn logger(path: Option<&Path>) -> Arc<Logger> {
+    let logger = Logger::new(&LogOptions {
+        level: LogLevel::Info,
+        plugin_levels: Vec::new(),
+        timestamps: false,
+        file_path: path.map(Path::to_path_buf),
+    })
+    .unwrap();
+
+    Arc::new(logger)
+}
+
+#[test]
+fn first_failure_in_plugin_order_is_returned() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+    let logger = logger(None);
+    let map = Arc::new(MapData::new(qmap::parse(&b"{\n}\n"[..]).unwrap()));
+
+    for jobs in [1, 4] {
+        // a takes the longest, so b fails first when run concurrently
+        let mut plugins =
+            [('a', 5_000_000, true), ('b', 0, true), ('c', 0, false)]
+                .into_iter()
+                .map(|(name, spin, fails)| {
+                    let wat = spinning_plugin(spin, fails);
+                    let module = Module::new(&engine, wat).unwrap();
+                    let mut plugin = Plugin::new(
+                        &linker,
+                        &module,
+                        &name.to_string(),
+                        BTreeMap::new(),
+                        logger.clone(),
+                    )
+                    .unwrap();
+
+                    plugin.init().unwrap();
+                    plugin
+                })
+                .collect::<Vec<_>>();
+
+        assert!(plugins.iter().all(Plugin::is_read_only));
+
+        let e = process(&mut plugins, map.clone(), jobs, false)
+            .err()
+            .unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("QMPP_Hook_process failed in plugin 'a'"));
+    }
+}
+
+#[test]
+fn concurrent_plugins_keep_plugin_order() {
+    let log_path = std::env::temp_dir()
+        .join(format!("qmpp-pipeline-test-{}.log", std::process::id()));
+
+    let logger = logger(Some(&log_path));
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+
+    // a takes the longest, so b finishes first; w has to wait for both and
+    // c and d have to wait for w
+    let mut plugins = [
+        ('a', 5_000_000, false),
+        ('b', 0, false),
+        ('w', 0, true),
+        ('c', 1_000_000, false),
+        ('d', 0, false),
+    ]
+    .into_iter()
+    .map(|(name, spin, writes)| {
+        let wat = counting_plugin(name, spin, writes);
+        let module = Module::new(&engine, wat).unwrap();
+        let mut plugin = Plugin::new(
+            &linker,
+            &module,
+            &format!("{}.wasm", name),
+            BTreeMap::new(),
+            logger.clone(),
+        )
+        .unwrap();
+
+        plugin.init().unwrap();
+        plugin
+    })
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        plugins.iter().map(Plugin::is_read_only).collect::<Vec<_>>(),
+        [true, true, false, true, true]
+    );
+
+    let map = Arc::new(MapData::new(qmap::parse(&b"{\n}\n"[..]).unwrap()));
+    let processed = process(&mut plugins, map, 4, false).unwrap();
+
+    assert_eq!(processed.map.entities.len(), 2);
+    assert_eq!(
+        processed.origins,
+        [
+            Some(EntityOrigin {
+                ent_idx: 0,
+                brushes: Vec::new(),
+            }),
+            None,
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(&log_path).unwrap(),
+        "a\tINFO\tcount1\n\
+         b\tINFO\tcount1\n\
+         w\tINFO\tcount1\n\
+         c\tINFO\tcount2\n\
+         d\tINFO\tcount2\n"
+    );
+
+    fs::remove_file(&log_path).unwrap();
+}
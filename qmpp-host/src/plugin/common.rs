@@ -1,99 +1,40 @@
 use std::ffi::CString;
+use std::fmt;
 
 use wasmtime::{Caller, Extern, Memory};
 
+use super::env::PluginEnv;
+use super::process::get_part;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::logging::LogLevel;
+
 macro_rules! stub_err {
-    (  $ctx:expr, $fun:expr ) => {
+    ( $phase:expr, $fun:expr ) => {
         Err(anyhow::anyhow!(
-            "\"{}\" not implemented for context \"{}\"",
+            "\"{}\" not available in phase \"{}\"",
             $fun,
-            $ctx
+            $phase
         ))
     };
 }
 
-macro_rules! stub_func {
-    (
-        $linker:expr,
-        $module:expr,
-        $ctx:expr,
-        $fun:expr,
-        ( $( $arg:ty ),* ),
-        $ret:ty $(,)?
-    ) => {
-        $linker.func_wrap(
-            $module,
-            $fun,
-            |$(_:$arg),*| -> anyhow::Result<$ret> {
-                stub_err!($ctx, $fun)
-            }
-        )
-    };
-    (
-        $linker:expr,
-        $module:expr,
-        $ctx:expr,
-        $fun:expr,
-        $arg:ty,
-        $ret:ty $(,)?
-    ) => {
-        $linker.func_wrap(
-            $module,
-            $fun,
-            |_:$arg| -> anyhow::Result<$ret> {
-                stub_err!($ctx, $fun)
-            }
-        )
-    };
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Phase {
+    Init,
+    Process,
 }
 
-/*
-macro_rules! stub_import {
-    (
-        $fun:expr,
-        $ctx:expr,
-        ( $( $arg:ty ),* ),
-        $ret:ty $(,)?
-    ) => {
-        {
-            |$(_:$arg),*| -> core::result::Result<
-                $ret,
-                $crate::plugin::common::ImportError
-            > {
-                stub_err!($fun, $ctx)
-            }
-        }
-    };
-    (
-        $fun:expr,
-        $ctx:expr,
-        $arg:ty,
-        $ret:ty $(,)?
-    ) => {
-        {
-            |_:$arg| -> core::result::Result<
-                $ret,
-                $crate::plugin::common::ImportError
-            > {
-                stub_err!($fun, $ctx)
-            }
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phase::Init => write!(f, "init"),
+            Phase::Process => write!(f, "process"),
         }
-    };
-}
-*/
-
-pub trait PluginEnv: Clone {
-    fn plugin_name(&self) -> &str;
-}
-
-#[derive(Copy, Clone)]
-enum LogLevel {
-    Info,
-    Error,
+    }
 }
 
 pub fn memory_from_caller(
-    caller: &mut Caller<'_, impl PluginEnv>,
+    caller: &mut Caller<'_, PluginEnv>,
 ) -> anyhow::Result<Memory> {
     if let Some(Extern::Memory(memory)) = caller.get_export("memory") {
         anyhow::Ok(memory)
@@ -103,7 +44,7 @@ pub fn memory_from_caller(
 }
 
 pub fn recv_c_string(
-    caller: &mut Caller<'_, impl PluginEnv>,
+    caller: &mut Caller<'_, PluginEnv>,
     ptr: i32,
 ) -> anyhow::Result<CString> {
     let memory = memory_from_caller(caller)?;
@@ -126,19 +67,22 @@ pub fn recv_c_string(
 }
 
 pub fn recv_bytes(
-    caller: &mut Caller<'_, impl PluginEnv>,
+    caller: &mut Caller<'_, PluginEnv>,
     len: i32,
     ptr: i32,
 ) -> anyhow::Result<Vec<u8>> {
     let memory = memory_from_caller(caller)?;
     let len = wasm_to_native_size(len);
     let start = wasm_to_native_size(ptr);
-    let end = start + len;
-    Ok(memory.data(caller)[start..end].into())
+    start
+        .checked_add(len)
+        .and_then(|end| memory.data(caller).get(start..end))
+        .map(Vec::from)
+        .ok_or_else(|| anyhow::anyhow!("Bytes out of bounds"))
 }
 
 pub fn send_bytes(
-    caller: &mut Caller<'_, impl PluginEnv>,
+    caller: &mut Caller<'_, PluginEnv>,
     ptr: i32,
     payload: &[u8],
 ) -> anyhow::Result<()> {
@@ -150,44 +94,179 @@ pub fn send_bytes(
     Ok(())
 }
 
+/// Log the message unless the plugin's log level filters it out, holding it
+/// back if the plugin's logs are being buffered
 fn log(
-    mut caller: Caller<'_, impl PluginEnv>,
+    mut caller: Caller<'_, PluginEnv>,
     mesg_len: i32,
     mesg_ptr: i32,
     level: LogLevel,
 ) {
-    let env = caller.data().clone();
+    let env = caller.data();
+
+    if !env.logger.enabled(&env.file_stem, level) {
+        return;
+    }
 
     match recv_bytes(&mut caller, mesg_len, mesg_ptr) {
         Result::Ok(bytes) => match String::from_utf8(bytes) {
-            Result::Ok(mesg) => match level {
-                LogLevel::Info => {
-                    println!("{}\tINFO\t{}", env.plugin_name(), mesg)
-                }
-                LogLevel::Error => {
-                    eprintln!("{}\tERROR\t{}", env.plugin_name(), mesg)
+            Result::Ok(mesg) => {
+                let env = caller.data();
+                let mut log_buffer = env.log_buffer.lock().unwrap();
+
+                match log_buffer.as_mut() {
+                    Some(buffer) => buffer.push((level, mesg)),
+                    None => env.logger.write(env.plugin_name(), level, &mesg),
                 }
-            },
+            }
             Result::Err(_) => eprintln!("Invalid UTF-8 in message"),
         },
         Result::Err(_) => eprintln!("Error while receiving bytes"),
     }
 }
 
+pub fn log_trace(
+    caller: Caller<'_, PluginEnv>,
+    mesg_len: i32,
+    mesg_ptr: i32,
+) -> anyhow::Result<()> {
+    log(caller, mesg_len, mesg_ptr, LogLevel::Trace);
+    Ok(())
+}
+
+pub fn log_debug(
+    caller: Caller<'_, PluginEnv>,
+    mesg_len: i32,
+    mesg_ptr: i32,
+) -> anyhow::Result<()> {
+    log(caller, mesg_len, mesg_ptr, LogLevel::Debug);
+    Ok(())
+}
+
 pub fn log_info(
-    caller: Caller<'_, impl PluginEnv>,
+    caller: Caller<'_, PluginEnv>,
     mesg_len: i32,
     mesg_ptr: i32,
-) {
-    log(caller, mesg_len, mesg_ptr, LogLevel::Info)
+) -> anyhow::Result<()> {
+    log(caller, mesg_len, mesg_ptr, LogLevel::Info);
+    Ok(())
+}
+
+pub fn log_warn(
+    caller: Caller<'_, PluginEnv>,
+    mesg_len: i32,
+    mesg_ptr: i32,
+) -> anyhow::Result<()> {
+    log(caller, mesg_len, mesg_ptr, LogLevel::Warn);
+    Ok(())
 }
 
 pub fn log_error(
-    caller: Caller<'_, impl PluginEnv>,
+    caller: Caller<'_, PluginEnv>,
     mesg_len: i32,
     mesg_ptr: i32,
-) {
-    log(caller, mesg_len, mesg_ptr, LogLevel::Error)
+) -> anyhow::Result<()> {
+    log(caller, mesg_len, mesg_ptr, LogLevel::Error);
+    Ok(())
+}
+
+/// Record a diagnostic about the part of the map named by the ehandle and
+/// indices, or about the map as a whole if the ehandle is negative
+#[allow(clippy::too_many_arguments)]
+pub fn report(
+    mut caller: Caller<'_, PluginEnv>,
+    severity: i32,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    code_ptr: i32,
+    mesg_len: i32,
+    mesg_ptr: i32,
+) -> anyhow::Result<()> {
+    let severity = Severity::try_from(severity)?;
+
+    let code = recv_c_string(&mut caller, code_ptr)
+        .map_err(|_| anyhow::anyhow!("Code pointer out of bounds"))?;
+
+    let mesg = recv_bytes(&mut caller, mesg_len, mesg_ptr)
+        .map_err(|_| anyhow::anyhow!("Message pointer out of bounds"))?;
+
+    let env = caller.data();
+
+    let part = if ehandle < 0 {
+        None
+    } else {
+        Some(get_part(&env.map, ehandle, brush_idx, surface_idx)?)
+    };
+
+    let diagnostic = Diagnostic::new(
+        env.plugin_name.clone(),
+        severity,
+        code.to_string_lossy().into_owned(),
+        String::from_utf8_lossy(&mesg).into_owned(),
+        &env.map,
+        part,
+    );
+
+    env.diagnostics.lock().unwrap().push(diagnostic);
+    Ok(())
+}
+
+/// Begin reading the value of a plugin option, writing its size (including
+/// null terminator) to `size_ptr`; returns 0 if the option was not given
+pub fn option_init_read(
+    mut caller: Caller<'_, PluginEnv>,
+    key_ptr: i32,
+    size_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut ort = env.option_read_transaction.lock().unwrap();
+
+    let key = recv_c_string(&mut caller, key_ptr)
+        .map_err(|_| anyhow::anyhow!("Key pointer out of bounds"))?;
+
+    let value = match env.options.get(&*key.to_string_lossy()) {
+        Some(value) => value,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let value_bytes = CString::new(value.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Option value contains a null byte"))?
+        .into_bytes_with_nul();
+
+    let size_bytes = native_to_wasm_size(value_bytes.len())?.to_le_bytes();
+
+    send_bytes(&mut caller, size_ptr, &size_bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to send size to plugin"))?;
+
+    ort.open(value_bytes)
+        .map_err(|_| anyhow::anyhow!("Option read transaction already open"))?;
+
+    Ok(1i32)
+}
+
+/// Finish reading the value of a plugin option
+pub fn option_read(
+    mut caller: Caller<'_, PluginEnv>,
+    val_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut ort = env.option_read_transaction.lock().unwrap();
+
+    let payload = ort
+        .close()
+        .map_err(|_| anyhow::anyhow!("Option read transaction is closed"))?;
+
+    if send_bytes(&mut caller, val_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send option with {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
 }
 
 pub fn wasm_to_native_size(wasm: i32) -> usize {
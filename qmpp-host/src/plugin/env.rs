@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use quake_util::qmap::QuakeMap;
+
+use crate::diagnostics::Diagnostic;
+use crate::logging::{LogLevel, Logger};
+use crate::map_data::MapData;
+use crate::patch::QuakeMapPatcher;
+
+use super::common::Phase;
+
+pub(super) type LogBuffer = Vec<(LogLevel, String)>;
+
+/// Store data shared by every import called from one plugin instance
+#[derive(Clone)]
+pub struct PluginEnv {
+    pub(super) plugin_name: String,
+    /// Name of the plugin's file without its extension, which stays the same
+    /// when the plugin registers a name of its own
+    pub(super) file_stem: String,
+    pub(super) plugin_version: Option<String>,
+    /// Settings given to the plugin by the configuration or command line
+    pub(super) options: Arc<BTreeMap<String, String>>,
+    pub(super) phase: Phase,
+    pub(super) map: Arc<MapData>,
+    pub(super) keyvalue_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) keys_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) texture_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) find_entities_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) links_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) face_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) vertices_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) entities_in_box_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) brushes_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) location_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) option_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
+    pub(super) logger: Arc<Logger>,
+    pub(super) log_buffer: Arc<Mutex<Option<LogBuffer>>>,
+    pub(super) classname_patterns: Arc<Mutex<Vec<Vec<u8>>>>,
+    pub(super) diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+    /// Changes made to the map during the current process phase
+    pub(super) patcher: Arc<Mutex<QuakeMapPatcher>>,
+    /// Number of calls made to each import
+    pub(super) host_calls: Arc<Mutex<BTreeMap<&'static str, u64>>>,
+}
+
+impl PluginEnv {
+    pub fn new(
+        plugin_name: String,
+        options: BTreeMap<String, String>,
+        logger: Arc<Logger>,
+    ) -> Self {
+        Self {
+            file_stem: plugin_name.clone(),
+            plugin_name,
+            plugin_version: None,
+            options: Arc::new(options),
+            phase: Phase::Init,
+            map: Arc::new(MapData::new(QuakeMap::new())),
+            keyvalue_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            keys_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            texture_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            find_entities_transaction: Arc::new(Mutex::new(Transaction::new())),
+            links_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            face_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            vertices_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            entities_in_box_transaction: Arc::new(Mutex::new(
+                Transaction::new(),
+            )),
+            brushes_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            location_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            option_read_transaction: Arc::new(Mutex::new(Transaction::new())),
+            logger,
+            log_buffer: Arc::new(Mutex::new(None)),
+            classname_patterns: Arc::new(Mutex::new(Vec::new())),
+            diagnostics: Arc::new(Mutex::new(Vec::new())),
+            patcher: Arc::new(Mutex::new(QuakeMapPatcher::new(0))),
+            host_calls: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn plugin_name(&self) -> &str {
+        &self.plugin_name
+    }
+}
+
+enum TransactionState<T> {
+    Closed,
+    Open(T),
+}
+
+pub(super) struct Transaction<T> {
+    state: TransactionState<T>,
+}
+
+impl<T> Transaction<T> {
+    pub fn new() -> Self {
+        Self {
+            state: TransactionState::Closed,
+        }
+    }
+
+    pub fn open(&mut self, payload: T) -> Result<(), ()> {
+        match self.state {
+            TransactionState::Closed => {
+                self.state = TransactionState::Open(payload);
+                Ok(())
+            }
+            TransactionState::Open(_) => Err(()),
+        }
+    }
+
+    pub fn close(&mut self) -> Result<T, ()> {
+        match std::mem::replace(&mut self.state, TransactionState::Closed) {
+            TransactionState::Closed => Err(()),
+            TransactionState::Open(payload) => {
+                self.state = TransactionState::Closed;
+                Ok(payload)
+            }
+        }
+    }
+}
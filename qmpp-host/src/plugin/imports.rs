@@ -0,0 +1,648 @@
+use wasmtime::{Caller, Engine, Linker};
+
+use super::common::{
+    log_debug, log_error, log_info, log_trace, log_warn, option_init_read,
+    option_read, report, Phase,
+};
+use super::env::PluginEnv;
+use super::init::{hook_filter_classname, register, register_version};
+use super::process::{
+    bhandle_count, brush_bounds_read, brush_contains_point, brush_exists,
+    brush_flags, brushes_at_point_init, brushes_in_box_init, brushes_read,
+    ehandle_count, entities_in_box_init, entities_in_box_read,
+    entity_bounds_read, entity_exists, face_init_read, face_read,
+    find_entities_init, find_entities_read, half_space_read, keys_init_read,
+    keys_read, keyvalue_init_read, keyvalue_read, links_in_init_read,
+    links_out_init_read, links_read, location_init_read, location_read,
+    nearest_brush, patch_points_read, patch_size_read, patch_texture_init_read,
+    phandle_count, shandle_count, source_lines_read, surface_exists,
+    surface_flags_read, surface_plane_read, texture_alignment_is_valve,
+    texture_alignment_read, texture_axes_read, texture_init_read, texture_read,
+    vertices_init_read, vertices_read,
+};
+use super::write::{
+    brush_add, brush_delete, entity_add, entity_delete, keyvalue_delete,
+    keyvalue_write, texture_alignment_convert, texture_write,
+};
+
+const IMPORT_MODULE: &str = "env";
+
+/// Create a linker providing every import to plugins run by `engine`
+pub fn linker(engine: &Engine) -> anyhow::Result<Linker<PluginEnv>> {
+    let mut linker = Linker::new(engine);
+    link(&mut linker)?;
+    Ok(linker)
+}
+
+/// Description of a function the host provides to plugins
+pub struct ImportInfo {
+    pub name: &'static str,
+    pub params: &'static [(&'static str, &'static str)],
+    pub result: &'static str,
+    pub doc: &'static [&'static str],
+    phases: &'static [Phase],
+    writes: bool,
+}
+
+impl ImportInfo {
+    pub fn available_in(&self, phase: Phase) -> bool {
+        self.phases.contains(&phase)
+    }
+
+    /// Whether calling the import can modify the map
+    pub fn writes(&self) -> bool {
+        self.writes
+    }
+
+    pub fn signature(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("{}({}) -> {}", self.name, params, self.result)
+    }
+}
+
+/// Declares every import along with the phases it may be called in.  Imports
+/// are linked in every phase, but trap when called outside of their phases.
+/// Imports that modify the map must be marked with `writes: true`.
+macro_rules! import_table {
+    (
+        $(
+            $( #[doc = $doc:literal] )*
+            $name:ident ( $( $arg:ident : $ty:ty ),* ) -> $ret:ty {
+                phases: [ $( $phase:ident ),* ],
+                handler: $handler:ident
+                $( , writes: $writes:literal )? $(,)?
+            }
+        )*
+    ) => {
+        pub const IMPORTS: &[ImportInfo] = &[
+            $(
+                ImportInfo {
+                    name: stringify!($name),
+                    params: &[ $( (stringify!($arg), stringify!($ty)) ),* ],
+                    result: stringify!($ret),
+                    doc: &[ $( $doc ),* ],
+                    phases: &[ $( Phase::$phase ),* ],
+                    writes: false $( || $writes )?,
+                },
+            )*
+        ];
+
+        fn link(linker: &mut Linker<PluginEnv>) -> anyhow::Result<()> {
+            $(
+                linker.func_wrap(
+                    IMPORT_MODULE,
+                    stringify!($name),
+                    |
+                        caller: Caller<'_, PluginEnv>,
+                        $( $arg: $ty ),*
+                    | -> anyhow::Result<$ret> {
+                        let phase = caller.data().phase;
+
+                        if ![ $( Phase::$phase ),* ].contains(&phase) {
+                            return stub_err!(phase, stringify!($name));
+                        }
+
+                        *caller
+                            .data()
+                            .host_calls
+                            .lock()
+                            .unwrap()
+                            .entry(stringify!($name))
+                            .or_default() += 1;
+
+                        $handler(caller, $( $arg ),*)
+                    },
+                )?;
+            )*
+
+            Ok(())
+        }
+    };
+}
+
+import_table! {
+    /// Register the plugin under the given name
+    QMPP_register(name_len: i32, name_ptr: i32) -> () {
+        phases: [Init],
+        handler: register,
+    }
+
+    /// Give the plugin's version, which is included in run reports
+    QMPP_register_version(version_len: i32, version_ptr: i32) -> () {
+        phases: [Init],
+        handler: register_version,
+    }
+
+    /// Only call entity and brush hooks for entities whose classname matches
+    /// the glob pattern; may be called more than once to allow several
+    QMPP_hook_filter_classname(pattern_len: i32, pattern_ptr: i32) -> () {
+        phases: [Init],
+        handler: hook_filter_classname,
+    }
+
+    /// Log a message tracing the plugin's progress in fine detail
+    QMPP_log_trace(mesg_len: i32, mesg_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: log_trace,
+    }
+
+    /// Log a message useful for debugging the plugin
+    QMPP_log_debug(mesg_len: i32, mesg_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: log_debug,
+    }
+
+    /// Log an informational message
+    QMPP_log_info(mesg_len: i32, mesg_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: log_info,
+    }
+
+    /// Log a warning message
+    QMPP_log_warn(mesg_len: i32, mesg_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: log_warn,
+    }
+
+    /// Log an error message
+    QMPP_log_error(mesg_len: i32, mesg_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: log_error,
+    }
+
+    /// Report a problem; `severity` is 0 for a note, 1 for a warning and 2
+    /// for an error, which fails the run unless downgraded.  The ehandle and
+    /// indices name the part of the map the problem is with as for
+    /// `QMPP_source_lines_read`, and a negative ehandle means the whole map.
+    /// `code_ptr` points to a null-terminated code identifying the problem
+    QMPP_report(
+        severity: i32,
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        code_ptr: i32,
+        mesg_len: i32,
+        mesg_ptr: i32
+    ) -> () {
+        phases: [Init, Process],
+        handler: report,
+    }
+
+    /// Begin reading the value of an option given to the plugin in the
+    /// configuration or on the command line, writing its size (including
+    /// null terminator) to `size_ptr`; returns 0 if the option was not given
+    QMPP_option_init_read(key_ptr: i32, size_ptr: i32) -> i32 {
+        phases: [Init, Process],
+        handler: option_init_read,
+    }
+
+    /// Finish reading an option
+    QMPP_option_read(val_ptr: i32) -> () {
+        phases: [Init, Process],
+        handler: option_read,
+    }
+
+    /// Number of entities in the map
+    QMPP_ehandle_count() -> i32 {
+        phases: [Process],
+        handler: ehandle_count,
+    }
+
+    /// Number of brushes in an entity
+    QMPP_bhandle_count(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: bhandle_count,
+    }
+
+    /// Number of surfaces in a brush
+    QMPP_shandle_count(ehandle: i32, brush_idx: i32) -> i32 {
+        phases: [Process],
+        handler: shandle_count,
+    }
+
+    /// 1 if the entity exists, 0 otherwise
+    QMPP_entity_exists(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: entity_exists,
+    }
+
+    /// 1 if the brush exists, 0 otherwise
+    QMPP_brush_exists(ehandle: i32, brush_idx: i32) -> i32 {
+        phases: [Process],
+        handler: brush_exists,
+    }
+
+    /// 1 if the surface exists, 0 otherwise
+    QMPP_surface_exists(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: surface_exists,
+    }
+
+    /// Begin reading a value, writing its size (including null terminator)
+    /// to `size_ptr`; returns 0 if the key is absent
+    QMPP_keyvalue_init_read(
+        ehandle: i32,
+        key_ptr: i32,
+        size_ptr: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: keyvalue_init_read,
+    }
+
+    /// Finish reading a value
+    QMPP_keyvalue_read(val_ptr: i32) -> () {
+        phases: [Process],
+        handler: keyvalue_read,
+    }
+
+    /// Begin reading an entity's null-separated keys, returning their size
+    QMPP_keys_init_read(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: keys_init_read,
+    }
+
+    /// Finish reading keys
+    QMPP_keys_read(keys_ptr: i32) -> () {
+        phases: [Process],
+        handler: keys_read,
+    }
+
+    /// Begin a search for entities whose value for the key matches the
+    /// pattern, returning the number of matches; `mode` is 0 for exact, 1 for
+    /// prefix and 2 for glob matching
+    QMPP_find_entities_init(
+        key_ptr: i32,
+        pattern_ptr: i32,
+        mode: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: find_entities_init,
+    }
+
+    /// Finish a search, writing the matching ehandles in ascending order as
+    /// u32s
+    QMPP_find_entities_read(ehandles_ptr: i32) -> () {
+        phases: [Process],
+        handler: find_entities_read,
+    }
+
+    /// Begin reading the links from an entity to the entities its `target`
+    /// and `killtarget` keys name, returning the number of links
+    QMPP_links_out_init_read(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: links_out_init_read,
+    }
+
+    /// Begin reading the links to an entity from the entities naming it in
+    /// their `target` or `killtarget` keys, returning the number of links
+    QMPP_links_in_init_read(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: links_in_init_read,
+    }
+
+    /// Finish reading links, writing each as a u32 kind (0 for `target`, 1
+    /// for `killtarget`) followed by the u32 ehandle at the other end
+    QMPP_links_read(links_ptr: i32) -> () {
+        phases: [Process],
+        handler: links_read,
+    }
+
+    /// Begin reading a surface's texture name, returning its size
+    QMPP_texture_init_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: texture_init_read,
+    }
+
+    /// Finish reading a texture name
+    QMPP_texture_read(texture_ptr: i32) -> () {
+        phases: [Process],
+        handler: texture_read,
+    }
+
+    /// Read a surface's three half-space points as 9 f64s
+    QMPP_half_space_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> () {
+        phases: [Process],
+        handler: half_space_read,
+    }
+
+    /// Read the plane through a surface's half-space points as its outward
+    /// unit normal followed by its distance from the origin, 4 f64s; returns
+    /// 0 and writes nothing if the points are collinear
+    QMPP_surface_plane_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: surface_plane_read,
+    }
+
+    /// Read the contents flags, surface flags and value that Quake 2 maps
+    /// give a surface as 3 i32s; returns 0 and writes nothing if the surface
+    /// has none, as in Quake 1 maps
+    QMPP_surface_flags_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: surface_flags_read,
+    }
+
+    /// Number of Quake 3 curve patches in an entity
+    QMPP_phandle_count(ehandle: i32) -> i32 {
+        phases: [Process],
+        handler: phandle_count,
+    }
+
+    /// Read a patch's width and height in control points as 2 u32s
+    QMPP_patch_size_read(ehandle: i32, patch_idx: i32, ptr: i32) -> () {
+        phases: [Process],
+        handler: patch_size_read,
+    }
+
+    /// Begin reading a patch's texture name, returning its size; finish with
+    /// `QMPP_texture_read`
+    QMPP_patch_texture_init_read(ehandle: i32, patch_idx: i32) -> i32 {
+        phases: [Process],
+        handler: patch_texture_init_read,
+    }
+
+    /// Read a patch's control points as x, y, z, s and t, 5 f64s each, in
+    /// width rows of height points
+    QMPP_patch_points_read(ehandle: i32, patch_idx: i32, ptr: i32) -> () {
+        phases: [Process],
+        handler: patch_points_read,
+    }
+
+    /// Read a surface's offset, rotation and scale as 5 f64s
+    QMPP_texture_alignment_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> () {
+        phases: [Process],
+        handler: texture_alignment_read,
+    }
+
+    /// 1 if the surface uses Valve220 alignment, 0 otherwise
+    QMPP_texture_alignment_is_valve(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: texture_alignment_is_valve,
+    }
+
+    /// Read a Valve220 surface's U and V axes as 6 f64s
+    QMPP_texture_axes_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> () {
+        phases: [Process],
+        handler: texture_axes_read,
+    }
+
+    /// Problems with a brush's shape computed from its half-spaces: 1 if a
+    /// surface's points are collinear, 2 if a surface is clipped away by the
+    /// others, 4 if the brush has no volume and 8 if it is not closed
+    QMPP_brush_flags(ehandle: i32, brush_idx: i32) -> i32 {
+        phases: [Process],
+        handler: brush_flags,
+    }
+
+    /// Begin reading the polygon a surface forms on its brush, returning its
+    /// number of vertices, which is 0 if the surface contributes no face
+    QMPP_face_init_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: face_init_read,
+    }
+
+    /// Finish reading a face, writing its vertices in clockwise order seen
+    /// from outside the brush as 3 f64s each
+    QMPP_face_read(ptr: i32) -> () {
+        phases: [Process],
+        handler: face_read,
+    }
+
+    /// Begin reading a brush's distinct vertices, returning their number
+    QMPP_vertices_init_read(ehandle: i32, brush_idx: i32) -> i32 {
+        phases: [Process],
+        handler: vertices_init_read,
+    }
+
+    /// Finish reading vertices, writing 3 f64s for each
+    QMPP_vertices_read(ptr: i32) -> () {
+        phases: [Process],
+        handler: vertices_read,
+    }
+
+    /// Read a brush's minimum and maximum corners as 6 f64s; returns 0 and
+    /// writes nothing if the brush has no volume or is not closed
+    QMPP_brush_bounds_read(ehandle: i32, brush_idx: i32, ptr: i32) -> i32 {
+        phases: [Process],
+        handler: brush_bounds_read,
+    }
+
+    /// Read the minimum and maximum corners of all of an entity's brushes as
+    /// 6 f64s; returns 0 and writes nothing if it has no well-formed brushes
+    QMPP_entity_bounds_read(ehandle: i32, ptr: i32) -> i32 {
+        phases: [Process],
+        handler: entity_bounds_read,
+    }
+
+    /// Read the first and last line in the map file of an entity, or of one
+    /// of its brushes or surfaces, as 2 u32s counting from 1; a negative
+    /// `brush_idx` or `surface_idx` means the entity or brush as a whole.
+    /// Returns 0 and writes nothing if the map was not read from a file
+    QMPP_source_lines_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        ptr: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: source_lines_read,
+    }
+
+    /// Begin reading a description of where an entity, brush or surface is
+    /// for use in messages, such as `e1m1.map:1234: func_door brush 3
+    /// surface 2`, returning its size; indices are as for
+    /// `QMPP_source_lines_read`
+    QMPP_location_init_read(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: location_init_read,
+    }
+
+    /// Finish reading a location, which is not null-terminated
+    QMPP_location_read(location_ptr: i32) -> () {
+        phases: [Process],
+        handler: location_read,
+    }
+
+    /// Begin a search for entities within the box given as minimum and
+    /// maximum corners of 3 f64s each, returning the number found; point
+    /// entities are found by `origin` and brush entities by their brushes
+    QMPP_entities_in_box_init(bounds_ptr: i32) -> i32 {
+        phases: [Process],
+        handler: entities_in_box_init,
+    }
+
+    /// Finish a box search, writing the ehandles found in ascending order as
+    /// u32s
+    QMPP_entities_in_box_read(ehandles_ptr: i32) -> () {
+        phases: [Process],
+        handler: entities_in_box_read,
+    }
+
+    /// Begin a search for brushes whose bounds overlap the box given as
+    /// minimum and maximum corners of 3 f64s each, returning the number found
+    QMPP_brushes_in_box_init(bounds_ptr: i32) -> i32 {
+        phases: [Process],
+        handler: brushes_in_box_init,
+    }
+
+    /// Begin a search for brushes containing the point given as 3 f64s,
+    /// returning the number found
+    QMPP_brushes_at_point_init(point_ptr: i32) -> i32 {
+        phases: [Process],
+        handler: brushes_at_point_init,
+    }
+
+    /// Finish a brush search, writing each brush found as a u32 ehandle
+    /// followed by a u32 brush index, in ascending order
+    QMPP_brushes_read(brushes_ptr: i32) -> () {
+        phases: [Process],
+        handler: brushes_read,
+    }
+
+    /// 1 if the brush contains the point given as 3 f64s or has it on its
+    /// boundary, 0 otherwise
+    QMPP_brush_contains_point(
+        ehandle: i32,
+        brush_idx: i32,
+        point_ptr: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: brush_contains_point,
+    }
+
+    /// Find the brush nearest the point given as 3 f64s, writing its u32
+    /// ehandle and u32 brush index to `brush_ptr` and its distance as an f64
+    /// to `dist_ptr`; returns 0 and writes nothing if no brush is closed
+    QMPP_nearest_brush(point_ptr: i32, brush_ptr: i32, dist_ptr: i32) -> i32 {
+        phases: [Process],
+        handler: nearest_brush,
+    }
+
+    /// Set a key of an entity to the null-terminated value.  Changes to the
+    /// map take effect once the plugin's process phase ends, so until then
+    /// the plugin keeps reading the map as it was.  Neither the key nor the
+    /// value may contain a double quote or line break
+    QMPP_keyvalue_write(ehandle: i32, key_ptr: i32, val_ptr: i32) -> () {
+        phases: [Process],
+        handler: keyvalue_write,
+        writes: true,
+    }
+
+    /// Remove a key from an entity
+    QMPP_keyvalue_delete(ehandle: i32, key_ptr: i32) -> () {
+        phases: [Process],
+        handler: keyvalue_delete,
+        writes: true,
+    }
+
+    /// Add an entity without keys or brushes, returning an ehandle that may
+    /// be passed to `QMPP_keyvalue_write`, `QMPP_keyvalue_delete` and
+    /// `QMPP_entity_delete`
+    QMPP_entity_add() -> i32 {
+        phases: [Process],
+        handler: entity_add,
+        writes: true,
+    }
+
+    /// Remove an entity along with its brushes
+    QMPP_entity_delete(ehandle: i32) -> () {
+        phases: [Process],
+        handler: entity_delete,
+        writes: true,
+    }
+
+    /// Add a copy of a brush of the map to an entity, after the brushes it
+    /// already has, without the brush's Quake 2 surface flags.  Returns 1,
+    /// or 0 if the brush cannot be copied, as with Quake 3 `brushDef`
+    /// brushes
+    QMPP_brush_add(ehandle: i32, src_ehandle: i32, src_brush_idx: i32) -> i32 {
+        phases: [Process],
+        handler: brush_add,
+        writes: true,
+    }
+
+    /// Remove a brush from an entity
+    QMPP_brush_delete(ehandle: i32, brush_idx: i32) -> () {
+        phases: [Process],
+        handler: brush_delete,
+        writes: true,
+    }
+
+    /// Set the texture of a surface to the null-terminated name
+    QMPP_texture_write(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        texture_ptr: i32
+    ) -> () {
+        phases: [Process],
+        handler: texture_write,
+        writes: true,
+    }
+
+    /// Convert a surface's texture alignment to Valve220 if `valve` is
+    /// nonzero, or to standard otherwise, keeping the texture where it was
+    /// as far as the style allows.  Returns 1 if the texture is projected
+    /// exactly as before, 0 if only approximately, and -1 if the surface
+    /// cannot be converted, as with Quake 3 `brushDef` surfaces
+    QMPP_texture_alignment_convert(
+        ehandle: i32,
+        brush_idx: i32,
+        surface_idx: i32,
+        valve: i32
+    ) -> i32 {
+        phases: [Process],
+        handler: texture_alignment_convert,
+        writes: true,
+    }
+}
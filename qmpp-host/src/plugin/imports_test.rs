@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use wasmtime::{Engine, Module, Store};
+
+use super::common::Phase;
+use super::env::PluginEnv;
+use super::imports::{linker, ImportInfo, IMPORTS};
+use crate::cli::LogOptions;
+use crate::logging::{LogLevel, Logger};
+
+const PHASES: [Phase; 2] = [Phase::Init, Phase::Process];
+
+fn store(engine: &Engine) -> Store<PluginEnv> {
+    let logger = Logger::new(&LogOptions {
+        level: LogLevel::Info,
+        plugin_levels: Vec::new(),
+        timestamps: false,
+        file_path: None,
+    })
+    .unwrap();
+
+    let env = PluginEnv::new("test".into(), BTreeMap::new(), Arc::new(logger));
+    Store::new(engine, env)
+}
+
+fn params(info: &ImportInfo) -> Vec<&'static str> {
+    info.params.iter().map(|&(_, ty)| ty).collect()
+}
+
+fn results(info: &ImportInfo) -> Vec<&'static str> {
+    match info.result {
+        "()" => Vec::new(),
+        ty => vec![ty],
+    }
+}
+
+/// Module importing `info` with the types from the table and exporting a
+/// function that calls it with zeros
+fn caller_module(engine: &Engine, info: &ImportInfo) -> Module {
+    let args = params(info)
+        .iter()
+        .map(|ty| format!("({}.const 0)", ty))
+        .collect::<String>();
+
+    let drops = "(drop)".repeat(results(info).len());
+
+    let wat = format!(
+        r#"(module
+            (import "env" "{name}"
+                (func $import (param {params}) (result {results})))
+            (memory (export "memory") 1)
+            (func (export "call") (call $import {args}) {drops}))"#,
+        name = info.name,
+        params = params(info).join(" "),
+        results = results(info).join(" "),
+    );
+
+    Module::new(engine, wat).unwrap()
+}
+
+#[test]
+fn every_import_is_linked_with_its_signature() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+    let mut store = store(&engine);
+
+    let names = IMPORTS
+        .iter()
+        .map(|info| info.name)
+        .collect::<BTreeSet<_>>();
+    assert_eq!(names.len(), IMPORTS.len());
+    assert_eq!(linker.iter(&mut store).count(), IMPORTS.len());
+
+    for info in IMPORTS {
+        let module = caller_module(&engine, info);
+
+        if let Err(e) = linker.instantiate(&mut store, &module) {
+            panic!(
+                "{} is not linked as {}: {}",
+                info.name,
+                info.signature(),
+                e
+            );
+        }
+    }
+}
+
+#[test]
+fn imports_fail_outside_their_phases() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+
+    for info in IMPORTS {
+        for phase in PHASES {
+            if info.available_in(phase) {
+                continue;
+            }
+
+            let mut store = store(&engine);
+            store.data_mut().phase = phase;
+            let module = caller_module(&engine, info);
+            let instance = linker.instantiate(&mut store, &module).unwrap();
+            let call = instance
+                .get_typed_func::<(), ()>(&mut store, "call")
+                .unwrap();
+
+            let e = call.call(&mut store, ()).err().unwrap();
+            let expected = format!(
+                "\"{}\" not available in phase \"{}\"",
+                info.name, phase
+            );
+
+            assert!(format!("{:?}", e).contains(&expected), "{:?}", e);
+            assert!(store.data().host_calls.lock().unwrap().is_empty());
+        }
+    }
+
+    // every import belongs to some phase, and the table covers both
+    assert!(IMPORTS
+        .iter()
+        .all(|info| PHASES.iter().any(|&phase| info.available_in(phase))));
+
+    for phase in PHASES {
+        assert!(IMPORTS.iter().any(|info| info.available_in(phase)));
+    }
+}
+
+#[test]
+fn imports_run_in_their_phases() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+    let count = IMPORTS
+        .iter()
+        .find(|info| info.name == "QMPP_ehandle_count")
+        .unwrap();
+
+    let mut store = store(&engine);
+    store.data_mut().phase = Phase::Process;
+    let module = caller_module(&engine, count);
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+    let call = instance
+        .get_typed_func::<(), ()>(&mut store, "call")
+        .unwrap();
+
+    call.call(&mut store, ()).unwrap();
+    assert_eq!(
+        store
+            .data()
+            .host_calls
+            .lock()
+            .unwrap()
+            .get("QMPP_ehandle_count"),
+        Some(&1)
+    );
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use quake_util::qmap::{Entity, QuakeMap};
+
+use wasmtime::{Instance, Linker, Module, Store, WasmParams};
+
+use super::common::{native_to_wasm_size, Phase};
+use super::env::PluginEnv;
+use super::imports::IMPORTS;
+use crate::diagnostics::Diagnostic;
+use crate::logging::Logger;
+use crate::map_data::MapData;
+use crate::patch::{PatchResult, PatchedMap, QuakeMapPatcher};
+use crate::pattern::glob_match;
+
+/// A plugin instantiated once and kept alive across all of its hooks, so
+/// state built during `QMPP_Hook_init` is still there in later hooks
+pub struct Plugin {
+    store: Store<PluginEnv>,
+    instance: Instance,
+    read_only: bool,
+    hook_stats: BTreeMap<&'static str, HookStats>,
+    changes: PatchResult,
+}
+
+/// How often a hook was called and the time spent in it
+#[derive(Copy, Clone, Default)]
+pub struct HookStats {
+    pub calls: u64,
+    pub time: Duration,
+}
+
+impl Plugin {
+    pub fn new(
+        linker: &Linker<PluginEnv>,
+        module: &Module,
+        name: &str,
+        options: BTreeMap<String, String>,
+        logger: Arc<Logger>,
+    ) -> anyhow::Result<Self> {
+        let env = PluginEnv::new(name.to_string(), options, logger);
+        let mut store = Store::new(linker.engine(), env);
+        let instance = linker.instantiate(&mut store, module)?;
+
+        let read_only = module.imports().all(|import| {
+            !IMPORTS
+                .iter()
+                .any(|info| info.name == import.name() && info.writes())
+        });
+
+        Ok(Self {
+            store,
+            instance,
+            read_only,
+            hook_stats: BTreeMap::new(),
+            changes: PatchResult::default(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        self.store.data().plugin_name()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.store.data().plugin_version.as_deref()
+    }
+
+    /// Whether the plugin imports nothing that can modify the map, and so
+    /// may run alongside other read-only plugins
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Hold log messages until `flush_logs` rather than writing them as they
+    /// arrive
+    pub fn buffer_logs(&mut self) {
+        let env = self.store.data();
+        let mut log_buffer = env.log_buffer.lock().unwrap();
+
+        if log_buffer.is_none() {
+            *log_buffer = Some(Vec::new());
+        }
+    }
+
+    /// Write any held log messages and stop buffering
+    pub fn flush_logs(&mut self) {
+        let env = self.store.data();
+        let buffered = env.log_buffer.lock().unwrap().take();
+
+        for (level, mesg) in buffered.into_iter().flatten() {
+            env.logger.write(env.plugin_name(), level, &mesg);
+        }
+    }
+
+    /// Diagnostics the plugin has reported since this was last called
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.store.data().diagnostics.lock().unwrap())
+    }
+
+    /// Calls and time spent in each hook the plugin exports
+    pub fn hook_stats(&self) -> &BTreeMap<&'static str, HookStats> {
+        &self.hook_stats
+    }
+
+    /// Number of calls the plugin made to each import
+    pub fn host_calls(&self) -> BTreeMap<&'static str, u64> {
+        self.store.data().host_calls.lock().unwrap().clone()
+    }
+
+    /// Everything the plugin has changed in the maps it processed
+    pub fn changes(&self) -> PatchResult {
+        self.changes
+    }
+
+    /// The map with the changes made during the last `process` applied, or
+    /// `None` if the plugin made none
+    ///
+    /// `map` must be the map that was processed.
+    pub fn patched(&mut self, map: &QuakeMap) -> Option<PatchedMap> {
+        let patcher = std::mem::replace(
+            &mut *self.store.data().patcher.lock().unwrap(),
+            QuakeMapPatcher::new(0),
+        );
+
+        if patcher.is_empty() {
+            return None;
+        }
+
+        let patched = patcher.patch(map);
+        self.changes += patched.result;
+        Some(patched)
+    }
+
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        self.store.data_mut().phase = Phase::Init;
+
+        if self.hook::<()>("QMPP_Hook_init")? {
+            self.call_hook("QMPP_Hook_init", ())
+        } else {
+            Err(anyhow::anyhow!(
+                "Plugin '{}' does not export QMPP_Hook_init",
+                self.name()
+            ))
+        }
+    }
+
+    /// Run the process hook followed by the entity and brush hooks and
+    /// finally the finish hook, skipping any the plugin does not export
+    ///
+    /// Entity hooks are called in entity order, each followed by the brush
+    /// hooks for that entity's brushes.  If the plugin declared classname
+    /// filters, only entities matching one of them are visited.
+    pub fn process(&mut self, map: Arc<MapData>) -> anyhow::Result<()> {
+        let env = self.store.data_mut();
+        env.phase = Phase::Process;
+        env.map = map.clone();
+        *env.patcher.lock().unwrap() = QuakeMapPatcher::new(map.entities.len());
+
+        self.call_hook("QMPP_Hook_process", ())?;
+
+        let entity_hook = self.hook::<(i32,)>("QMPP_Hook_entity")?;
+        let brush_hook = self.hook::<(i32, i32)>("QMPP_Hook_brush")?;
+
+        if entity_hook || brush_hook {
+            for (ent_idx, entity) in map.entities.iter().enumerate() {
+                if !self.visits(entity) {
+                    continue;
+                }
+
+                let ehandle = native_to_wasm_size(ent_idx)?;
+
+                if entity_hook {
+                    self.call_hook("QMPP_Hook_entity", (ehandle,))?;
+                }
+
+                if brush_hook {
+                    for brush_idx in 0..entity.brushes.len() {
+                        let brush_idx = native_to_wasm_size(brush_idx)?;
+
+                        self.call_hook(
+                            "QMPP_Hook_brush",
+                            (ehandle, brush_idx),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        self.call_hook("QMPP_Hook_finish", ())
+    }
+
+    fn visits(&self, entity: &Entity) -> bool {
+        let patterns = self.store.data().classname_patterns.lock().unwrap();
+
+        if patterns.is_empty() {
+            return true;
+        }
+
+        let classname = entity
+            .edict
+            .get(c"classname")
+            .map_or(&b""[..], |classname| classname.to_bytes());
+
+        patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, classname))
+    }
+
+    /// Whether the plugin exports `hook` with the given parameters
+    fn hook<Params: WasmParams>(&mut self, hook: &str) -> anyhow::Result<bool> {
+        match self.instance.get_func(&mut self.store, hook) {
+            Some(func) => {
+                func.typed::<Params, ()>(&self.store)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn call_hook<Params: WasmParams>(
+        &mut self,
+        hook: &'static str,
+        params: Params,
+    ) -> anyhow::Result<()> {
+        let func = match self.instance.get_func(&mut self.store, hook) {
+            Some(func) => func.typed::<Params, ()>(&self.store)?,
+            None => {
+                return Ok(());
+            }
+        };
+
+        let started = Instant::now();
+        let result = func.call(&mut self.store, params);
+        let stats = self.hook_stats.entry(hook).or_default();
+        stats.calls += 1;
+        stats.time += started.elapsed();
+
+        // wasmtime wraps the host error or trap that stopped the hook in a
+        // backtrace, which says nothing about what went wrong
+        result.map_err(|e| {
+            anyhow::anyhow!(
+                "{} failed in plugin '{}': {}",
+                hook,
+                self.name(),
+                e.root_cause()
+            )
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use quake_util::qmap;
+use wasmtime::{Engine, Module};
+
+use super::imports::linker;
+use super::instance::Plugin;
+use crate::cli::LogOptions;
+use crate::logging::{LogLevel, Logger};
+use crate::map_data::MapData;
+
+/// Plugin that counts its runs from `QMPP_Hook_init` on, logging the run
+/// number each time it processes a map
+const COUNTER: &str = r#"(module
+    (import "env" "QMPP_register" (func $register (param i32 i32)))
+    (import "env" "QMPP_log_info" (func $log_info (param i32 i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 0) "counterrun 1run 2")
+    (global $run (mut i32) (i32.const 0))
+    (func (export "QMPP_Hook_init")
+        (call $register (i32.const 7) (i32.const 0))
+        (global.set $run (i32.const 1)))
+    (func (export "QMPP_Hook_process")
+        (if (i32.eqz (global.get $run)) (then unreachable))
+        (call $log_info
+            (i32.const 5)
+            (i32.add (i32.const 2) (i32.mul (global.get $run) (i32.const 5))))
+        (global.set $run (i32.add (global.get $run) (i32.const 1)))))"#;
+
+fn logger(path: Option<&Path>) -> Arc<Logger> {
+    let logger = Logger::new(&LogOptions {
+        level: LogLevel::Info,
+        plugin_levels: Vec::new(),
+        timestamps: false,
+        file_path: path.map(Path::to_path_buf),
+    })
+    .unwrap();
+
+    Arc::new(logger)
+}
+
+#[test]
+fn state_from_init_lasts_across_hooks() {
+    let log_path = std::env::temp_dir()
+        .join(format!("qmpp-instance-test-{}.log", std::process::id()));
+
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+    let module = Module::new(&engine, COUNTER).unwrap();
+    let map = Arc::new(MapData::new(qmap::parse(&b"{\n}\n"[..]).unwrap()));
+
+    let mut plugin = Plugin::new(
+        &linker,
+        &module,
+        "counter-file",
+        BTreeMap::new(),
+        logger(Some(&log_path)),
+    )
+    .unwrap();
+
+    plugin.init().unwrap();
+    assert_eq!(plugin.name(), "counter");
+
+    plugin.process(map.clone()).unwrap();
+    plugin.process(map.clone()).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&log_path).unwrap(),
+        "counter\tINFO\trun 1\ncounter\tINFO\trun 2\n"
+    );
+
+    // a fresh instance has not been through init
+    let mut fresh = Plugin::new(
+        &linker,
+        &module,
+        "counter-file",
+        BTreeMap::new(),
+        logger(Some(&log_path)),
+    )
+    .unwrap();
+
+    assert!(fresh.process(map).is_err());
+
+    fs::remove_file(&log_path).unwrap();
+}
+
+#[test]
+fn pointers_outside_memory_are_errors() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+
+    for (len, ptr) in [(4, 65535), (-1, 0), (8, -4)] {
+        let wat = format!(
+            r#"(module
+                (import "env" "QMPP_register_version"
+                    (func $version (param i32 i32)))
+                (memory (export "memory") 1)
+                (func (export "QMPP_Hook_init")
+                    (call $version (i32.const {}) (i32.const {}))))"#,
+            len, ptr
+        );
+
+        let module = Module::new(&engine, wat).unwrap();
+        let mut plugin = Plugin::new(
+            &linker,
+            &module,
+            "pointer",
+            BTreeMap::new(),
+            logger(None),
+        )
+        .unwrap();
+
+        // a trap from the host call, where slicing memory used to panic
+        let e = plugin.init().err().unwrap();
+        assert!(e.to_string().starts_with("QMPP_Hook_init failed"));
+    }
+}
+
+#[test]
+fn unquotable_keyvalues_are_errors() {
+    let engine = Engine::default();
+    let linker = linker(&engine).unwrap();
+    let map = Arc::new(MapData::new(qmap::parse(&b"{\n}\n"[..]).unwrap()));
+
+    for (key, value) in [("a\"b", "c"), ("a", "b\nc"), ("a", "b\rc")] {
+        // the rest of memory is zeroed, terminating both strings
+        let wat = format!(
+            r#"(module
+                (import "env" "QMPP_keyvalue_write"
+                    (func $write (param i32 i32 i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) {:?})
+                (data (i32.const 16) {:?})
+                (func (export "QMPP_Hook_process")
+                    (call $write (i32.const 0) (i32.const 0) (i32.const 16))))"#,
+            key, value
+        );
+
+        let module = Module::new(&engine, wat).unwrap();
+        let mut plugin = Plugin::new(
+            &linker,
+            &module,
+            "quoting",
+            BTreeMap::new(),
+            logger(None),
+        )
+        .unwrap();
+
+        let e = plugin.process(map.clone()).err().unwrap();
+        assert!(e.to_string().ends_with("which map files cannot quote"));
+    }
+}
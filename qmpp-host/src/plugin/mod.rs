@@ -1,8 +1,19 @@
 #[macro_use]
 mod common;
 
+mod env;
+mod imports;
 mod init;
+mod instance;
 mod process;
+mod write;
 
-pub use init::init;
-pub use process::process;
+#[cfg(test)]
+mod imports_test;
+#[cfg(test)]
+mod instance_test;
+
+pub use common::Phase;
+pub use env::PluginEnv;
+pub use imports::{linker, IMPORTS};
+pub use instance::Plugin;
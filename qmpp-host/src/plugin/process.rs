@@ -1,172 +1,30 @@
 use std::convert::TryFrom;
 use std::convert::TryInto;
-use std::sync::Arc;
-use std::sync::Mutex;
 
-use quake_util::qmap::{Brush, QuakeMap, Surface};
+use quake_util::qmap::{Brush, Point, Surface};
 
-use wasmtime::{Caller, Engine, Linker, Module, Store};
+use wasmtime::Caller;
 
 use super::common::{
-    log_error, log_info, native_to_wasm_size, recv_c_string, send_bytes,
-    wasm_to_native_size, PluginEnv,
+    native_to_wasm_size, recv_bytes, recv_c_string, send_bytes,
+    wasm_to_native_size,
 };
-
-#[derive(Clone)]
-struct ProcessEnv {
-    plugin_name: String,
-    map: Arc<QuakeMap>,
-    keyvalue_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
-    keys_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
-    texture_read_transaction: Arc<Mutex<Transaction<Vec<u8>>>>,
-}
-
-impl PluginEnv for ProcessEnv {
-    fn plugin_name(&self) -> &str {
-        &self.plugin_name
-    }
-}
-
-enum TransactionState<T> {
-    Closed,
-    Open(T),
-}
-
-struct Transaction<T> {
-    state: TransactionState<T>,
-}
-
-impl<T> Transaction<T> {
-    pub fn new() -> Self {
-        Self {
-            state: TransactionState::Closed,
-        }
-    }
-
-    pub fn open(&mut self, payload: T) -> Result<(), ()> {
-        match self.state {
-            TransactionState::Closed => {
-                self.state = TransactionState::Open(payload);
-                Ok(())
-            }
-            TransactionState::Open(_) => Err(()),
-        }
-    }
-
-    pub fn close(&mut self) -> Result<T, ()> {
-        match std::mem::replace(&mut self.state, TransactionState::Closed) {
-            TransactionState::Closed => Err(()),
-            TransactionState::Open(payload) => {
-                self.state = TransactionState::Closed;
-                Ok(payload)
-            }
-        }
-    }
-}
-
-pub fn process(engine: &Engine, module: &Module, map: Arc<QuakeMap>) {
-    let process_env = ProcessEnv {
-        plugin_name: String::from("hello"),
-        map,
-        keyvalue_read_transaction: Arc::new(Mutex::new(Transaction::new())),
-        keys_read_transaction: Arc::new(Mutex::new(Transaction::new())),
-        texture_read_transaction: Arc::new(Mutex::new(Transaction::new())),
-    };
-
-    let mut store = Store::new(engine, process_env);
-    let mut linker = Linker::new(engine);
-
-    stub_func!(linker, "env", "process", "QMPP_register", (i32, i32), (),)
-        .unwrap();
-
-    linker.func_wrap("env", "QMPP_log_info", log_info).unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_log_error", log_error)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_ehandle_count", ehandle_count)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_bhandle_count", bhandle_count)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_shandle_count", shandle_count)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_entity_exists", entity_exists)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_brush_exists", brush_exists)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_surface_exists", surface_exists)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_keyvalue_init_read", keyvalue_init_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_keyvalue_read", keyvalue_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_keys_init_read", keys_init_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_keys_read", keys_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_texture_init_read", texture_init_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_texture_read", texture_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_half_space_read", half_space_read)
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_texture_alignment_read", texture_alignment_read)
-        .unwrap();
-
-    linker
-        .func_wrap(
-            "env",
-            "QMPP_texture_alignment_is_valve",
-            texture_alignment_is_valve,
-        )
-        .unwrap();
-
-    linker
-        .func_wrap("env", "QMPP_texture_axes_read", texture_axes_read)
-        .unwrap();
-
-    let instance = linker.instantiate(&mut store, module).unwrap();
-
-    let process_func =
-        instance.get_func(&mut store, "QMPP_Hook_process").unwrap();
-    process_func.call(&mut store, &[], &mut []).unwrap();
-}
-
-fn ehandle_count(caller: Caller<'_, ProcessEnv>) -> anyhow::Result<i32> {
+use super::env::PluginEnv;
+use crate::extras::PatchMesh;
+use crate::geometry::{Bounds, BrushGeometry, Plane};
+use crate::links::{Link, LinkGraph};
+use crate::map_data::{MapData, MapPart};
+use crate::pattern::MatchMode;
+
+pub(super) fn ehandle_count(
+    caller: Caller<'_, PluginEnv>,
+) -> anyhow::Result<i32> {
     let env = caller.data();
     native_to_wasm_size(env.map.entities.len())
 }
 
-fn keyvalue_init_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn keyvalue_init_read(
+    mut caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     key_ptr: i32,
     size_ptr: i32,
@@ -217,8 +75,8 @@ fn keyvalue_init_read(
     }
 }
 
-fn keyvalue_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn keyvalue_read(
+    mut caller: Caller<'_, PluginEnv>,
     val_ptr: i32,
 ) -> anyhow::Result<()> {
     let env = caller.data().clone();
@@ -238,8 +96,8 @@ fn keyvalue_read(
     }
 }
 
-fn keys_init_read(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn keys_init_read(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
 ) -> anyhow::Result<i32> {
     let env = caller.data();
@@ -267,8 +125,8 @@ fn keys_init_read(
     }
 }
 
-fn keys_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn keys_read(
+    mut caller: Caller<'_, PluginEnv>,
     keys_ptr: i32,
 ) -> anyhow::Result<()> {
     let env = caller.data().clone();
@@ -288,8 +146,128 @@ fn keys_read(
     }
 }
 
-fn bhandle_count(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn find_entities_init(
+    mut caller: Caller<'_, PluginEnv>,
+    key_ptr: i32,
+    pattern_ptr: i32,
+    mode: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut fet = env.find_entities_transaction.lock().unwrap();
+
+    let mode = MatchMode::try_from(mode)?;
+
+    let key = recv_c_string(&mut caller, key_ptr)
+        .map_err(|_| anyhow::anyhow!("Key pointer out of bounds"))?;
+
+    let pattern = recv_c_string(&mut caller, pattern_ptr)
+        .map_err(|_| anyhow::anyhow!("Pattern pointer out of bounds"))?;
+
+    let found = env.map.entity_index().find(&key, pattern.to_bytes(), mode);
+    let found_count = native_to_wasm_size(found.len())?;
+
+    let mut ehandles = Vec::<u8>::with_capacity(found.len() * 4);
+
+    for ent_idx in found {
+        ehandles.extend(native_to_wasm_size(ent_idx)?.to_le_bytes());
+    }
+
+    match fet.open(ehandles) {
+        Ok(_) => Ok(found_count),
+        Err(_) => {
+            Err(anyhow::anyhow!("Entity search transaction already open"))
+        }
+    }
+}
+
+pub(super) fn find_entities_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandles_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut fet = env.find_entities_transaction.lock().unwrap();
+
+    let payload = fet
+        .close()
+        .map_err(|_| anyhow::anyhow!("Entity search transaction is closed"))?;
+
+    if send_bytes(&mut caller, ehandles_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send entity handles in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn links_out_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+) -> anyhow::Result<i32> {
+    links_init_read(caller, ehandle, LinkGraph::outgoing)
+}
+
+pub(super) fn links_in_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+) -> anyhow::Result<i32> {
+    links_init_read(caller, ehandle, LinkGraph::incoming)
+}
+
+fn links_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    direction: fn(&LinkGraph, usize) -> &[Link],
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut lrt = env.links_read_transaction.lock().unwrap();
+
+    let ent_idx = wasm_to_native_size(ehandle);
+
+    if ent_idx >= env.map.entities.len() {
+        return Err(anyhow::anyhow!("Bad entity index {}", ent_idx));
+    }
+
+    let links = direction(env.map.link_graph(), ent_idx);
+    let link_count = native_to_wasm_size(links.len())?;
+
+    let mut payload = Vec::<u8>::with_capacity(links.len() * 8);
+
+    for link in links {
+        payload.extend(link.kind.code().to_le_bytes());
+        payload.extend(native_to_wasm_size(link.ent_idx)?.to_le_bytes());
+    }
+
+    match lrt.open(payload) {
+        Ok(_) => Ok(link_count),
+        Err(_) => Err(anyhow::anyhow!("Links read transaction already open")),
+    }
+}
+
+pub(super) fn links_read(
+    mut caller: Caller<'_, PluginEnv>,
+    links_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut lrt = env.links_read_transaction.lock().unwrap();
+
+    let payload = lrt
+        .close()
+        .map_err(|_| anyhow::anyhow!("Links read transaction is closed"))?;
+
+    if send_bytes(&mut caller, links_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send links in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn bhandle_count(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
 ) -> anyhow::Result<i32> {
     let entity_idx = usize::try_from(ehandle as u32).unwrap();
@@ -305,8 +283,8 @@ fn bhandle_count(
     native_to_wasm_size(entity.brushes.len())
 }
 
-fn shandle_count(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn shandle_count(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
 ) -> anyhow::Result<i32> {
@@ -315,8 +293,8 @@ fn shandle_count(
     native_to_wasm_size(brush.len())
 }
 
-fn entity_exists(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn entity_exists(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
 ) -> anyhow::Result<i32> {
     Ok(if ehandle < ehandle_count(caller)? {
@@ -326,8 +304,8 @@ fn entity_exists(
     })
 }
 
-fn brush_exists(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn brush_exists(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
 ) -> anyhow::Result<i32> {
@@ -340,8 +318,8 @@ fn brush_exists(
     })
 }
 
-fn surface_exists(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn surface_exists(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -357,8 +335,8 @@ fn surface_exists(
     )
 }
 
-fn texture_init_read(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn texture_init_read(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -379,8 +357,8 @@ fn texture_init_read(
     }
 }
 
-fn texture_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn texture_read(
+    mut caller: Caller<'_, PluginEnv>,
     texture_ptr: i32,
 ) -> anyhow::Result<()> {
     let env = caller.data().clone();
@@ -403,8 +381,8 @@ fn texture_read(
     }
 }
 
-fn half_space_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn half_space_read(
+    mut caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -432,8 +410,82 @@ fn half_space_read(
     }
 }
 
-fn texture_alignment_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn surface_plane_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+
+    let surface =
+        get_surface(env.map.as_ref(), ehandle, brush_idx, surface_idx)?;
+
+    let plane = match Plane::from_half_space(&surface.half_space) {
+        Some(plane) => plane,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let payload = plane
+        .normal
+        .into_iter()
+        .chain([plane.dist])
+        .flat_map(|num| num.to_le_bytes().into_iter())
+        .collect::<Vec<u8>>();
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send plane in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(1i32)
+    }
+}
+
+pub(super) fn surface_flags_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    get_surface(env.map.as_ref(), ehandle, brush_idx, surface_idx)?;
+
+    let flags = env.map.extras().surface_flags(
+        wasm_to_native_size(ehandle),
+        wasm_to_native_size(brush_idx),
+        wasm_to_native_size(surface_idx),
+    );
+
+    let flags = match flags {
+        Some(flags) => flags,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let payload = [flags.contents, flags.flags, flags.value]
+        .into_iter()
+        .flat_map(|num| num.to_le_bytes().into_iter())
+        .collect::<Vec<u8>>();
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send surface flags in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(1i32)
+    }
+}
+
+pub(super) fn texture_alignment_read(
+    mut caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -449,8 +501,8 @@ fn texture_alignment_read(
     let payload = alignment
         .offset
         .into_iter()
-        .chain([alignment.rotation].into_iter())
-        .chain(alignment.scale.into_iter())
+        .chain([alignment.rotation])
+        .chain(alignment.scale)
         .flat_map(|num| num.to_le_bytes().into_iter())
         .collect::<Vec<u8>>();
 
@@ -464,8 +516,8 @@ fn texture_alignment_read(
     }
 }
 
-fn texture_alignment_is_valve(
-    caller: Caller<'_, ProcessEnv>,
+pub(super) fn texture_alignment_is_valve(
+    caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -481,8 +533,8 @@ fn texture_alignment_is_valve(
     })
 }
 
-fn texture_axes_read(
-    mut caller: Caller<'_, ProcessEnv>,
+pub(super) fn texture_axes_read(
+    mut caller: Caller<'_, PluginEnv>,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -516,12 +568,514 @@ fn texture_axes_read(
     }
 }
 
-fn get_brush(
-    map: &QuakeMap,
+pub(super) fn brush_flags(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let geometry = get_brush_geometry(&env.map, ehandle, brush_idx)?;
+    Ok(geometry.flags as i32)
+}
+
+pub(super) fn face_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut frt = env.face_read_transaction.lock().unwrap();
+
+    get_surface(env.map.as_ref(), ehandle, brush_idx, surface_idx)?;
+    let geometry = get_brush_geometry(&env.map, ehandle, brush_idx)?;
+    let face = &geometry.faces[wasm_to_native_size(surface_idx)];
+    let vertex_count = native_to_wasm_size(face.len())?;
+
+    match frt.open(points_to_bytes(face)) {
+        Ok(_) => Ok(vertex_count),
+        Err(_) => Err(anyhow::anyhow!("Face read transaction already open")),
+    }
+}
+
+pub(super) fn face_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut frt = env.face_read_transaction.lock().unwrap();
+
+    let payload = frt
+        .close()
+        .map_err(|_| anyhow::anyhow!("Face read transaction is closed"))?;
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send face in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn vertices_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut vrt = env.vertices_read_transaction.lock().unwrap();
+
+    let geometry = get_brush_geometry(&env.map, ehandle, brush_idx)?;
+    let vertex_count = native_to_wasm_size(geometry.vertices.len())?;
+
+    match vrt.open(points_to_bytes(&geometry.vertices)) {
+        Ok(_) => Ok(vertex_count),
+        Err(_) => {
+            Err(anyhow::anyhow!("Vertices read transaction already open"))
+        }
+    }
+}
+
+pub(super) fn vertices_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut vrt = env.vertices_read_transaction.lock().unwrap();
+
+    let payload = vrt
+        .close()
+        .map_err(|_| anyhow::anyhow!("Vertices read transaction is closed"))?;
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send vertices in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn brush_bounds_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let geometry = get_brush_geometry(&env.map, ehandle, brush_idx)?;
+    send_bounds(&mut caller, ptr, geometry.bounds)
+}
+
+pub(super) fn entity_bounds_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let ent_idx = wasm_to_native_size(ehandle);
+
+    if ent_idx >= env.map.entities.len() {
+        return Err(anyhow::anyhow!("Bad entity index {}", ent_idx));
+    }
+
+    send_bounds(&mut caller, ptr, env.map.geometry().entity_bounds(ent_idx))
+}
+
+pub(super) fn entities_in_box_init(
+    mut caller: Caller<'_, PluginEnv>,
+    bounds_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let mut ebt = env.entities_in_box_transaction.lock().unwrap();
+
+    let region = recv_bounds(&mut caller, bounds_ptr)?;
+    let found = env.map.spatial_index().entities_in(&region);
+    let found_count = native_to_wasm_size(found.len())?;
+
+    let mut ehandles = Vec::<u8>::with_capacity(found.len() * 4);
+
+    for ent_idx in found {
+        ehandles.extend(native_to_wasm_size(ent_idx)?.to_le_bytes());
+    }
+
+    match ebt.open(ehandles) {
+        Ok(_) => Ok(found_count),
+        Err(_) => {
+            Err(anyhow::anyhow!("Entity box query transaction already open"))
+        }
+    }
+}
+
+pub(super) fn entities_in_box_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandles_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut ebt = env.entities_in_box_transaction.lock().unwrap();
+
+    let payload = ebt.close().map_err(|_| {
+        anyhow::anyhow!("Entity box query transaction is closed")
+    })?;
+
+    if send_bytes(&mut caller, ehandles_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send entity handles in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn brushes_in_box_init(
+    mut caller: Caller<'_, PluginEnv>,
+    bounds_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let region = recv_bounds(&mut caller, bounds_ptr)?;
+    let found = env.map.spatial_index().brushes_in(&region);
+    open_brushes_read(&env, &found)
+}
+
+pub(super) fn brushes_at_point_init(
+    mut caller: Caller<'_, PluginEnv>,
+    point_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let [point] = recv_points(&mut caller, point_ptr)?;
+
+    let found = env
+        .map
+        .spatial_index()
+        .brushes_at(point, env.map.geometry());
+
+    open_brushes_read(&env, &found)
+}
+
+fn open_brushes_read(
+    env: &PluginEnv,
+    brushes: &[(usize, usize)],
+) -> anyhow::Result<i32> {
+    let mut brt = env.brushes_read_transaction.lock().unwrap();
+    let brush_count = native_to_wasm_size(brushes.len())?;
+
+    let mut payload = Vec::<u8>::with_capacity(brushes.len() * 8);
+
+    for &(ent_idx, brush_idx) in brushes {
+        payload.extend(native_to_wasm_size(ent_idx)?.to_le_bytes());
+        payload.extend(native_to_wasm_size(brush_idx)?.to_le_bytes());
+    }
+
+    match brt.open(payload) {
+        Ok(_) => Ok(brush_count),
+        Err(_) => Err(anyhow::anyhow!("Brushes read transaction already open")),
+    }
+}
+
+pub(super) fn brushes_read(
+    mut caller: Caller<'_, PluginEnv>,
+    brushes_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut brt = env.brushes_read_transaction.lock().unwrap();
+
+    let payload = brt
+        .close()
+        .map_err(|_| anyhow::anyhow!("Brushes read transaction is closed"))?;
+
+    if send_bytes(&mut caller, brushes_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send brushes in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn brush_contains_point(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    point_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let geometry = get_brush_geometry(&env.map, ehandle, brush_idx)?;
+    let [point] = recv_points(&mut caller, point_ptr)?;
+
+    Ok(if geometry.contains(point) { 1i32 } else { 0i32 })
+}
+
+pub(super) fn nearest_brush(
+    mut caller: Caller<'_, PluginEnv>,
+    point_ptr: i32,
+    brush_ptr: i32,
+    dist_ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let [point] = recv_points(&mut caller, point_ptr)?;
+
+    let nearest = env
+        .map
+        .spatial_index()
+        .nearest_brush(point, env.map.geometry());
+
+    let ((ent_idx, brush_idx), dist) = match nearest {
+        Some(nearest) => nearest,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let mut brush = Vec::<u8>::with_capacity(8);
+    brush.extend(native_to_wasm_size(ent_idx)?.to_le_bytes());
+    brush.extend(native_to_wasm_size(brush_idx)?.to_le_bytes());
+
+    if send_bytes(&mut caller, brush_ptr, &brush[..]).is_err()
+        || send_bytes(&mut caller, dist_ptr, &dist.to_le_bytes()).is_err()
+    {
+        Err(anyhow::anyhow!("Failed to send nearest brush to plugin"))
+    } else {
+        Ok(1i32)
+    }
+}
+
+fn recv_bounds(
+    caller: &mut Caller<'_, PluginEnv>,
+    ptr: i32,
+) -> anyhow::Result<Bounds> {
+    let [mins, maxs] = recv_points(caller, ptr)?;
+    Ok(Bounds { mins, maxs })
+}
+
+/// Read `N` points of 3 f64s each from plugin memory
+fn recv_points<const N: usize>(
+    caller: &mut Caller<'_, PluginEnv>,
+    ptr: i32,
+) -> anyhow::Result<[Point; N]> {
+    let len = native_to_wasm_size(N * 24)?;
+
+    let bytes = recv_bytes(caller, len, ptr)
+        .map_err(|_| anyhow::anyhow!("Point pointer out of bounds"))?;
+
+    let mut coords = bytes
+        .chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+
+    Ok([(); N].map(|_| [(); 3].map(|_| coords.next().unwrap())))
+}
+
+fn send_bounds(
+    caller: &mut Caller<'_, PluginEnv>,
+    ptr: i32,
+    bounds: Option<Bounds>,
+) -> anyhow::Result<i32> {
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let payload = points_to_bytes(&[bounds.mins, bounds.maxs]);
+
+    if send_bytes(caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send bounds in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(1i32)
+    }
+}
+
+pub(super) fn phandle_count(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+) -> anyhow::Result<i32> {
+    let entity_idx = wasm_to_native_size(ehandle);
+    let env = caller.data();
+
+    if entity_idx >= env.map.entities.len() {
+        return Err(anyhow::anyhow!("Bad entity index {}", entity_idx));
+    }
+
+    native_to_wasm_size(env.map.extras().patches(entity_idx).len())
+}
+
+pub(super) fn patch_size_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    patch_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data();
+    let patch = get_patch(env.map.as_ref(), ehandle, patch_idx)?;
+
+    let mut payload = Vec::<u8>::with_capacity(8);
+    payload.extend(native_to_wasm_size(patch.width)?.to_le_bytes());
+    payload.extend(native_to_wasm_size(patch.height)?.to_le_bytes());
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send patch size in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(super) fn patch_texture_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    patch_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let mut trt = env.texture_read_transaction.lock().unwrap();
+
+    let patch = get_patch(env.map.as_ref(), ehandle, patch_idx)?;
+    let texture = patch.texture.as_bytes_with_nul().to_vec();
+    let texture_length = native_to_wasm_size(texture.len())?;
+
+    match trt.open(texture) {
+        Ok(_) => Ok(texture_length),
+        Err(_) => Err(anyhow::anyhow!("Texture transaction already open")),
+    }
+}
+
+pub(super) fn patch_points_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    patch_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data();
+    let patch = get_patch(env.map.as_ref(), ehandle, patch_idx)?;
+
+    let payload = patch
+        .points
+        .iter()
+        .flatten()
+        .flat_map(|num| num.to_le_bytes().into_iter())
+        .collect::<Vec<u8>>();
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send patch points in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn points_to_bytes(points: &[[f64; 3]]) -> Vec<u8> {
+    points
+        .iter()
+        .flatten()
+        .flat_map(|num| num.to_le_bytes().into_iter())
+        .collect()
+}
+
+pub(super) fn source_lines_read(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    ptr: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data().clone();
+    let part = get_part(&env.map, ehandle, brush_idx, surface_idx)?;
+
+    let lines = match env.map.source_lines(part) {
+        Some(lines) => lines,
+        None => {
+            return Ok(0i32);
+        }
+    };
+
+    let mut payload = Vec::<u8>::with_capacity(8);
+    payload.extend(native_to_wasm_size(*lines.start())?.to_le_bytes());
+    payload.extend(native_to_wasm_size(*lines.end())?.to_le_bytes());
+
+    if send_bytes(&mut caller, ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send lines in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(1i32)
+    }
+}
+
+pub(super) fn location_init_read(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let mut lrt = env.location_read_transaction.lock().unwrap();
+
+    let part = get_part(&env.map, ehandle, brush_idx, surface_idx)?;
+    let location = env.map.location(part).into_bytes();
+    let location_length = native_to_wasm_size(location.len())?;
+
+    match lrt.open(location) {
+        Ok(_) => Ok(location_length),
+        Err(_) => {
+            Err(anyhow::anyhow!("Location read transaction already open"))
+        }
+    }
+}
+
+pub(super) fn location_read(
+    mut caller: Caller<'_, PluginEnv>,
+    location_ptr: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data().clone();
+    let mut lrt = env.location_read_transaction.lock().unwrap();
+
+    let payload = lrt
+        .close()
+        .map_err(|_| anyhow::anyhow!("Location read transaction is closed"))?;
+
+    if send_bytes(&mut caller, location_ptr, &payload[..]).is_err() {
+        Err(anyhow::anyhow!(
+            "Failed to send location in {} bytes to plugin",
+            payload.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn get_brush_geometry(
+    map: &MapData,
+    ehandle: i32,
+    brush_idx: i32,
+) -> anyhow::Result<&BrushGeometry> {
+    get_brush(map, ehandle, brush_idx)?;
+
+    Ok(map
+        .geometry()
+        .brush(wasm_to_native_size(ehandle), wasm_to_native_size(brush_idx))
+        .unwrap())
+}
+
+pub(super) fn get_brush(
+    map: &MapData,
     ehandle: i32,
     brush_idx: i32,
 ) -> anyhow::Result<&Brush> {
-    let entity = match map.entities.get(wasm_to_native_size(ehandle)) {
+    let ent_idx = wasm_to_native_size(ehandle);
+
+    let entity = match map.entities.get(ent_idx) {
         Some(ent) => ent,
         None => {
             return Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32));
@@ -532,12 +1086,41 @@ fn get_brush(
 
     match brushes.get(wasm_to_native_size(brush_idx)) {
         Some(b) => Ok(b),
-        None => Err(anyhow::anyhow!("Bad brush index {}", brush_idx as u32)),
+        None => Err(anyhow::anyhow!(
+            "{}: Bad brush index {}",
+            map.location(MapPart::Entity(ent_idx)),
+            brush_idx as u32
+        )),
     }
 }
 
-fn get_surface(
-    map: &QuakeMap,
+fn get_patch(
+    map: &MapData,
+    ehandle: i32,
+    patch_idx: i32,
+) -> anyhow::Result<&PatchMesh> {
+    let ent_idx = wasm_to_native_size(ehandle);
+
+    if ent_idx >= map.entities.len() {
+        return Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32));
+    }
+
+    match map
+        .extras()
+        .patches(ent_idx)
+        .get(wasm_to_native_size(patch_idx))
+    {
+        Some(patch) => Ok(patch),
+        None => Err(anyhow::anyhow!(
+            "{}: Bad patch index {}",
+            map.location(MapPart::Entity(ent_idx)),
+            patch_idx as u32
+        )),
+    }
+}
+
+pub(super) fn get_surface(
+    map: &MapData,
     ehandle: i32,
     brush_idx: i32,
     surface_idx: i32,
@@ -546,10 +1129,44 @@ fn get_surface(
         Ok(brush) => match brush.get(wasm_to_native_size(surface_idx)) {
             Some(s) => Ok(s),
             None => Err(anyhow::anyhow!(
-                "Bad surface index {}",
+                "{}: Bad surface index {}",
+                map.location(MapPart::Brush(
+                    wasm_to_native_size(ehandle),
+                    wasm_to_native_size(brush_idx),
+                )),
                 surface_idx as u32,
             )),
         },
         Err(failure) => Err(failure),
     }
 }
+
+/// Part named by an ehandle, brush index and surface index, where a negative
+/// brush or surface index names the entity or brush as a whole
+pub(super) fn get_part(
+    map: &MapData,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+) -> anyhow::Result<MapPart> {
+    let ent_idx = wasm_to_native_size(ehandle);
+
+    if brush_idx < 0 {
+        if ent_idx >= map.entities.len() {
+            return Err(anyhow::anyhow!("Bad entity index {}", ent_idx));
+        }
+
+        Ok(MapPart::Entity(ent_idx))
+    } else if surface_idx < 0 {
+        get_brush(map, ehandle, brush_idx)?;
+        Ok(MapPart::Brush(ent_idx, wasm_to_native_size(brush_idx)))
+    } else {
+        get_surface(map, ehandle, brush_idx, surface_idx)?;
+
+        Ok(MapPart::Surface(
+            ent_idx,
+            wasm_to_native_size(brush_idx),
+            wasm_to_native_size(surface_idx),
+        ))
+    }
+}
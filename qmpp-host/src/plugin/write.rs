@@ -0,0 +1,207 @@
+use std::ffi::CStr;
+
+use wasmtime::Caller;
+
+use super::common::{native_to_wasm_size, recv_c_string, wasm_to_native_size};
+use super::env::PluginEnv;
+use super::process::{get_brush, get_surface};
+use crate::alignment::{convert, AlignmentStyle, Conversion};
+
+pub(super) fn keyvalue_write(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    key_ptr: i32,
+    val_ptr: i32,
+) -> anyhow::Result<()> {
+    let key = recv_c_string(&mut caller, key_ptr)
+        .map_err(|_| anyhow::anyhow!("Key pointer out of bounds"))?;
+
+    let value = recv_c_string(&mut caller, val_ptr)
+        .map_err(|_| anyhow::anyhow!("Value pointer out of bounds"))?;
+
+    check_quotable(&key, "Key")?;
+    check_quotable(&value, "Value")?;
+
+    let env = caller.data();
+    let mut patcher = env.patcher.lock().unwrap();
+
+    if patcher.set_keyvalue(wasm_to_native_size(ehandle), key, value) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32))
+    }
+}
+
+/// Make sure `text` can be written between the quotes of a map file's key
+/// or value
+fn check_quotable(text: &CStr, what: &str) -> anyhow::Result<()> {
+    match text.to_bytes().iter().find(|ch| b"\"\n\r".contains(ch)) {
+        Some(ch) => Err(anyhow::anyhow!(
+            "{} {:?} contains {:?}, which map files cannot quote",
+            what,
+            text,
+            *ch as char
+        )),
+        None => Ok(()),
+    }
+}
+
+pub(super) fn keyvalue_delete(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    key_ptr: i32,
+) -> anyhow::Result<()> {
+    let key = recv_c_string(&mut caller, key_ptr)
+        .map_err(|_| anyhow::anyhow!("Key pointer out of bounds"))?;
+
+    let env = caller.data();
+    let mut patcher = env.patcher.lock().unwrap();
+
+    if patcher.delete_keyvalue(wasm_to_native_size(ehandle), key) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32))
+    }
+}
+
+pub(super) fn entity_add(caller: Caller<'_, PluginEnv>) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let ent_idx = env.patcher.lock().unwrap().add_entity();
+    native_to_wasm_size(ent_idx)
+}
+
+pub(super) fn entity_delete(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data();
+    let mut patcher = env.patcher.lock().unwrap();
+
+    if patcher.delete_entity(wasm_to_native_size(ehandle)) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32))
+    }
+}
+
+pub(super) fn brush_add(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    src_ehandle: i32,
+    src_brush_idx: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let brush = get_brush(&env.map, src_ehandle, src_brush_idx)?;
+
+    let brush_def = env.map.extras().is_brush_def(
+        wasm_to_native_size(src_ehandle),
+        wasm_to_native_size(src_brush_idx),
+    );
+
+    if brush_def {
+        return Ok(0i32);
+    }
+
+    let added = env
+        .patcher
+        .lock()
+        .unwrap()
+        .add_brush(wasm_to_native_size(ehandle), brush.clone());
+
+    if added {
+        Ok(1i32)
+    } else {
+        Err(anyhow::anyhow!("Bad entity index {}", ehandle as u32))
+    }
+}
+
+pub(super) fn brush_delete(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+) -> anyhow::Result<()> {
+    let env = caller.data();
+    get_brush(&env.map, ehandle, brush_idx)?;
+
+    let deleted = env.patcher.lock().unwrap().delete_brush(
+        wasm_to_native_size(ehandle),
+        wasm_to_native_size(brush_idx),
+    );
+
+    if deleted {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Entity {} was deleted", ehandle as u32))
+    }
+}
+
+pub(super) fn texture_write(
+    mut caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    texture_ptr: i32,
+) -> anyhow::Result<()> {
+    let texture = recv_c_string(&mut caller, texture_ptr)
+        .map_err(|_| anyhow::anyhow!("Texture pointer out of bounds"))?;
+
+    let env = caller.data();
+    get_surface(&env.map, ehandle, brush_idx, surface_idx)?;
+
+    let written = env.patcher.lock().unwrap().set_texture(
+        wasm_to_native_size(ehandle),
+        wasm_to_native_size(brush_idx),
+        wasm_to_native_size(surface_idx),
+        texture,
+    );
+
+    if written {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Entity {} was deleted", ehandle as u32))
+    }
+}
+
+pub(super) fn texture_alignment_convert(
+    caller: Caller<'_, PluginEnv>,
+    ehandle: i32,
+    brush_idx: i32,
+    surface_idx: i32,
+    valve: i32,
+) -> anyhow::Result<i32> {
+    let env = caller.data();
+    let surface = get_surface(&env.map, ehandle, brush_idx, surface_idx)?;
+    let ent_idx = wasm_to_native_size(ehandle);
+    let brush_idx = wasm_to_native_size(brush_idx);
+
+    if env.map.extras().is_brush_def(ent_idx, brush_idx) {
+        return Ok(-1i32);
+    }
+
+    let style = if valve != 0 {
+        AlignmentStyle::Valve220
+    } else {
+        AlignmentStyle::Standard
+    };
+
+    let (alignment, exact) = match convert(surface, style) {
+        Some(Conversion::Exact(alignment)) => (alignment, 1i32),
+        Some(Conversion::Lossy(alignment)) => (alignment, 0i32),
+        None => {
+            return Ok(-1i32);
+        }
+    };
+
+    let written = env.patcher.lock().unwrap().set_alignment(
+        ent_idx,
+        brush_idx,
+        wasm_to_native_size(surface_idx),
+        alignment,
+    );
+
+    if written {
+        Ok(exact)
+    } else {
+        Err(anyhow::anyhow!("Entity {} was deleted", ehandle as u32))
+    }
+}
@@ -0,0 +1,300 @@
+use std::ffi::CString;
+use std::ops::Range;
+
+use quake_util::qmap;
+
+use crate::extras::{
+    EntityExtras, MapExtras, PatchMesh, SurfaceFlags, TextureMatrix,
+};
+use crate::map_data::{MapData, MapPart};
+use crate::source::MapSource;
+
+/// Tokens of a surface's three points: `( x y z )` each
+const POINT_TOKENS: usize = 15;
+/// Tokens of a standard alignment: offsets, rotation and scales
+const STANDARD_ALIGNMENT_TOKENS: usize = 5;
+/// Tokens of a Valve 220 alignment: `[ x y z offset ]` for each axis, then
+/// rotation and scales
+const VALVE_ALIGNMENT_TOKENS: usize = 15;
+/// Tokens of a `brushDef` texture matrix: `( ( a b c ) ( d e f ) )`
+const MATRIX_TOKENS: usize = 12;
+/// Alignment given to `brushDef` surfaces in the parsed map
+const DEFAULT_ALIGNMENT: &[u8] = b"0 0 0 1 1";
+
+/// Parse a map along with the text it was read from
+///
+/// The map parser only understands Quake 1 maps, so what other formats add
+/// is taken out of the text before it is parsed and kept with the map's
+/// extras: surface flags from Quake 2 and 3 maps, texture matrices from
+/// Quake 3 `brushDef`s, which are parsed as standard brushes, and Quake 3
+/// curve patches.
+pub fn read_map(name: String, text: Vec<u8>) -> anyhow::Result<MapData> {
+    let source = MapSource::new(name, text);
+    let mut extras = MapExtras::default();
+    // spans of the text to replace before parsing, in no particular order
+    let mut edits = Vec::<(Range<usize>, Vec<u8>)>::new();
+
+    for (ent_idx, ent_source) in source.entities.iter().enumerate() {
+        let mut ent_extras = EntityExtras::default();
+
+        for brush_source in &ent_source.brushes {
+            let mut brush_flags = Vec::new();
+            let mut matrices = Vec::new();
+            let mut standard_brush = b"{\n".to_vec();
+
+            for surface in &brush_source.surfaces {
+                let tokens = source.tokens(surface);
+
+                if brush_source.brush_def {
+                    let (surface, matrix, flags) = read_brush_def_surface(
+                        &source, &tokens,
+                    )
+                    .ok_or_else(|| malformed(&source, ent_idx, "brushDef"))?;
+
+                    standard_brush.extend(surface);
+                    matrices.push(matrix);
+                    brush_flags.push(flags);
+                } else if let Some((flags, span)) =
+                    surface_flags(&source, &tokens)
+                {
+                    edits.push((span, Vec::new()));
+                    brush_flags.push(Some(flags));
+                } else {
+                    brush_flags.push(None);
+                }
+            }
+
+            if brush_source.brush_def {
+                standard_brush.extend(b"}\n");
+                edits.push((brush_source.body.clone(), standard_brush));
+                ent_extras.texture_matrices.push(Some(matrices));
+            } else {
+                ent_extras.texture_matrices.push(None);
+            }
+
+            ent_extras.surface_flags.push(brush_flags);
+        }
+
+        for patch_source in &ent_source.patches {
+            let tokens = source.tokens(&patch_source.body);
+
+            let patch = read_patch(&source, &tokens)
+                .ok_or_else(|| malformed(&source, ent_idx, "patch"))?;
+
+            ent_extras.patches.push(patch);
+            edits.push((patch_source.body.clone(), Vec::new()));
+        }
+
+        extras.entities.push(ent_extras);
+    }
+
+    let text = source.text(&(0..source.trailer.end));
+
+    let map = if edits.is_empty() {
+        extras = MapExtras::default();
+        qmap::parse(text)?
+    } else {
+        edits.sort_by_key(|(span, _)| span.start);
+
+        let mut parsed_text = Vec::with_capacity(text.len());
+        let mut copied = 0;
+
+        for (span, replacement) in edits {
+            parsed_text.extend(&text[copied..span.start]);
+            parsed_text.extend(replacement);
+            copied = span.end;
+        }
+
+        parsed_text.extend(&text[copied..]);
+        qmap::parse(&parsed_text[..])?
+    };
+
+    Ok(MapData::with_source(map, source).with_extras(extras))
+}
+
+/// Parse an entity file, the entities of a map without their brushes as
+/// kept in a compiled BSP
+pub fn read_ent(name: String, text: Vec<u8>) -> anyhow::Result<MapData> {
+    let map = read_map(name, text)?;
+
+    match (0..map.entities.len()).find(|&idx| map.has_brushes(idx)) {
+        Some(ent_idx) => Err(anyhow::anyhow!(
+            "{} has brushes, which entity files cannot hold",
+            map.location(MapPart::Entity(ent_idx))
+        )),
+        None => Ok(map),
+    }
+}
+
+fn malformed(source: &MapSource, ent_idx: usize, what: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Malformed {} in entity {} of {}",
+        what,
+        ent_idx,
+        source.name()
+    )
+}
+
+/// Flags following a surface's alignment, along with the span from the end
+/// of the alignment through the flags
+fn surface_flags(
+    source: &MapSource,
+    tokens: &[Range<usize>],
+) -> Option<(SurfaceFlags, Range<usize>)> {
+    let alignment_start = POINT_TOKENS + 1;
+    let valve = source.text(tokens.get(alignment_start)?) == b"[";
+
+    let alignment_tokens = if valve {
+        VALVE_ALIGNMENT_TOKENS
+    } else {
+        STANDARD_ALIGNMENT_TOKENS
+    };
+
+    let alignment_end = alignment_start + alignment_tokens;
+    let flags = read_flags(source, tokens.get(alignment_end..)?)?;
+    Some((flags, tokens[alignment_end - 1].end..tokens.last()?.end))
+}
+
+fn read_flags(
+    source: &MapSource,
+    tokens: &[Range<usize>],
+) -> Option<SurfaceFlags> {
+    let [contents, flags, value] = tokens else {
+        return None;
+    };
+
+    Some(SurfaceFlags {
+        contents: number(source, contents)?,
+        flags: number(source, flags)?,
+        value: number(source, value)?,
+    })
+}
+
+/// A `brushDef` surface written as a standard one, along with its texture
+/// matrix and flags
+fn read_brush_def_surface(
+    source: &MapSource,
+    tokens: &[Range<usize>],
+) -> Option<(Vec<u8>, TextureMatrix, Option<SurfaceFlags>)> {
+    let matrix_tokens =
+        tokens.get(POINT_TOKENS..POINT_TOKENS + MATRIX_TOKENS)?;
+    let texture = tokens.get(POINT_TOKENS + MATRIX_TOKENS)?;
+    let rest = &tokens[POINT_TOKENS + MATRIX_TOKENS + 1..];
+
+    let mut numbers = matrix_tokens
+        .iter()
+        .filter(|token| !matches!(source.text(token), b"(" | b")"))
+        .map(|token| number(source, token));
+
+    let mut matrix = [[0.0; 3]; 2];
+
+    for row in &mut matrix {
+        for element in row {
+            *element = numbers.next()??;
+        }
+    }
+
+    let flags = match rest {
+        [] => None,
+        _ => Some(read_flags(source, rest)?),
+    };
+
+    let mut surface = source
+        .text(&(tokens[0].start..tokens[POINT_TOKENS - 1].end))
+        .to_vec();
+
+    surface.push(b' ');
+    surface.extend(source.text(texture));
+    surface.push(b' ');
+    surface.extend(DEFAULT_ALIGNMENT);
+    surface.push(b'\n');
+
+    Some((surface, matrix, flags))
+}
+
+/// Read a patch from the tokens of its whole block:
+///
+/// ```text
+/// { patchDef2 { texture ( width height params... )
+///   ( ( ( x y z s t ) ... ) ... ) } }
+/// ```
+fn read_patch(
+    source: &MapSource,
+    tokens: &[Range<usize>],
+) -> Option<PatchMesh> {
+    let words = &mut tokens.iter().map(|token| source.text(token));
+
+    expect(words, b"{")?;
+    let def = String::from_utf8(words.next()?.to_vec()).ok()?;
+    expect(words, b"{")?;
+    let texture = CString::new(words.next()?).ok()?;
+    expect(words, b"(")?;
+
+    let mut header = Vec::new();
+
+    loop {
+        match words.next()? {
+            b")" => break,
+            word => header.push(parse(word)?),
+        }
+    }
+
+    let [width, height, ref params @ ..] = header[..] else {
+        return None;
+    };
+
+    let width = usize::try_from(width).ok()?;
+    let height = usize::try_from(height).ok()?;
+    let mut points = Vec::with_capacity(width * height);
+
+    expect(words, b"(")?;
+
+    for _ in 0..width {
+        expect(words, b"(")?;
+
+        for _ in 0..height {
+            expect(words, b"(")?;
+            let mut point = [0.0; 5];
+
+            for coord in &mut point {
+                *coord = parse(words.next()?)?;
+            }
+
+            expect(words, b")")?;
+            points.push(point);
+        }
+
+        expect(words, b")")?;
+    }
+
+    expect(words, b")")?;
+    expect(words, b"}")?;
+    expect(words, b"}")?;
+
+    Some(PatchMesh {
+        def,
+        texture,
+        width,
+        height,
+        params: params.to_vec(),
+        points,
+    })
+}
+
+fn expect<'a>(
+    words: &mut impl Iterator<Item = &'a [u8]>,
+    word: &[u8],
+) -> Option<()> {
+    (words.next()? == word).then_some(())
+}
+
+fn parse<T: std::str::FromStr>(word: &[u8]) -> Option<T> {
+    std::str::from_utf8(word).ok()?.parse().ok()
+}
+
+fn number<T: std::str::FromStr>(
+    source: &MapSource,
+    token: &Range<usize>,
+) -> Option<T> {
+    parse(source.text(token))
+}
@@ -0,0 +1,261 @@
+use quake_util::qmap::QuakeMap;
+
+use crate::extras::SurfaceFlags;
+use crate::map_data::MapData;
+use crate::patch::QuakeMapPatcher;
+use crate::reader::{read_ent, read_map};
+use crate::writer::{write_ent, write_map};
+
+const QUAKE2_MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) e1u1/floor1_3 0 0 0 1 1 1 0 0
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) e1u1/sky1 0 0 0 1 1 1 4 200
+}
+{
+( 32 -16 -16 ) ( 32 -15 -16 ) ( 32 -16 -15 ) e1u1/water4 [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1 32 0 0 // water
+( 32 -16 -16 ) ( 32 -16 -15 ) ( 33 -16 -16 ) e1u1/water4 [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1 32 0 0
+( 32 -16 -16 ) ( 33 -16 -16 ) ( 32 -15 -16 ) e1u1/water4 [ -1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1 32 0 0
+( 64 16 16 ) ( 64 17 16 ) ( 65 16 16 ) e1u1/water4 [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1 32 0 0
+( 64 16 16 ) ( 65 16 16 ) ( 64 16 17 ) e1u1/water4 [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1 32 0 0
+( 64 16 16 ) ( 64 16 17 ) ( 64 17 16 ) e1u1/water4 [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1 32 0 0
+}
+}
+";
+
+const QUAKE3_MAP: &str = "\
+{
+\"classname\" \"worldspawn\"
+// brush 0
+{
+brushDef
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) ( ( 0.0625 0 0 ) ( 0 0.0625 0 ) ) textures/base/floor 0 0 0
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) ( ( 0.0625 0 0 ) ( 0 0.0625 0 ) ) textures/base/floor 0 0 0
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) ( ( 0.0625 0 0 ) ( 0 0.0625 0 ) ) textures/base/floor 0 0 0
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) ( ( 0.0625 0 0 ) ( 0 0.0625 0 ) ) textures/base/floor 0 0 0
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) ( ( 0.0625 0 0 ) ( 0 0.0625 0 ) ) textures/base/floor 0 0 0
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) ( ( 0.125 0 0.5 ) ( 0 0.125 0 ) ) textures/base/sky 1 4 0
+}
+}
+// patch 0
+{
+patchDef2
+{
+textures/base/curve
+( 3 3 0 0 0 )
+(
+( ( -64 0 0 0 0 ) ( -64 0 32 0 0.5 ) ( -64 0 64 0 1 ) )
+( ( 0 0 0 0.5 0 ) ( 0 0 32 0.5 0.5 ) ( 0 0 64 0.5 1 ) )
+( ( 64 0 0 1 0 ) ( 64 0 32 1 0.5 ) ( 64 0 64 1 1 ) )
+)
+}
+}
+}
+";
+
+#[test]
+fn quake2_surface_flags_are_read() {
+    let map = read_map("q2.map".into(), QUAKE2_MAP.into()).unwrap();
+
+    assert_eq!(map.entities[0].brushes.len(), 2);
+    assert_eq!(&*map.entities[0].brushes[0][5].texture, c"e1u1/sky1");
+
+    let sky = SurfaceFlags {
+        contents: 1,
+        flags: 4,
+        value: 200,
+    };
+
+    assert_eq!(map.extras().surface_flags(0, 0, 5), Some(sky));
+    assert_eq!(map.extras().surface_flags(0, 1, 0).unwrap().contents, 32);
+    assert_eq!(map.extras().surface_flags(0, 2, 0), None);
+}
+
+#[test]
+fn quake1_maps_have_no_extras() {
+    let text = std::fs::read("test-res/q25_limits_4lt.map").unwrap();
+    let map = read_map("q1.map".into(), text).unwrap();
+
+    assert!(map.extras().entities.is_empty());
+    assert_eq!(map.extras().surface_flags(0, 0, 0), None);
+}
+
+#[test]
+fn surface_flags_are_written_back() {
+    let map = read_map("q2.map".into(), QUAKE2_MAP.into()).unwrap();
+
+    let mut out = Vec::new();
+    write_map(&mut out, &map, Some(&map), None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), QUAKE2_MAP);
+
+    let mut out = Vec::new();
+    write_map(&mut out, &map, None, None).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains(" e1u1/sky1 0 0 0 1 1 1 4 200\n"));
+    assert!(out.contains(" 0 1 1 32 0 0\n"));
+}
+
+#[test]
+fn surface_flags_follow_patched_brushes() {
+    let map = read_map("q2.map".into(), QUAKE2_MAP.into()).unwrap();
+
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+    patcher.delete_brush(0, 0);
+    let patched = patcher.patch(&map);
+    let extras = map.extras().patched(&patched);
+
+    assert_eq!(extras.surface_flags(0, 0, 0).unwrap().contents, 32);
+    assert_eq!(extras.surface_flags(0, 1, 0), None);
+}
+
+#[test]
+fn quake3_brush_defs_and_patches_are_read() {
+    let map = read_map("q3.map".into(), QUAKE3_MAP.into()).unwrap();
+    let brush = &map.entities[0].brushes[0];
+
+    assert_eq!(map.entities[0].brushes.len(), 1);
+    assert_eq!(brush.len(), 6);
+    assert_eq!(&*brush[5].texture, c"textures/base/sky");
+    assert_eq!(map.extras().surface_flags(0, 0, 5).unwrap().flags, 4);
+
+    let matrices = map.extras().entities[0].texture_matrices[0].as_ref();
+    assert_eq!(matrices.unwrap()[5], [[0.125, 0.0, 0.5], [0.0, 0.125, 0.0]]);
+
+    let patches = map.extras().patches(0);
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].def, "patchDef2");
+    assert_eq!(&*patches[0].texture, c"textures/base/curve");
+    assert_eq!((patches[0].width, patches[0].height), (3, 3));
+    assert_eq!(patches[0].params, [0, 0, 0]);
+    assert_eq!(patches[0].points[5], [0.0, 0.0, 64.0, 0.5, 1.0]);
+}
+
+#[test]
+fn quake3_maps_are_written_back() {
+    let map = read_map("q3.map".into(), QUAKE3_MAP.into()).unwrap();
+
+    let mut out = Vec::new();
+    write_map(&mut out, &map, Some(&map), None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), QUAKE3_MAP);
+
+    let mut out = Vec::new();
+    write_map(&mut out, &map, None, None).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let reread = read_map("q3.map".into(), out.clone().into()).unwrap();
+
+    assert!(out.contains("{\nbrushDef\n{\n"));
+    assert!(
+        out.contains(" ( ( 0.125 0 0.5 ) ( 0 0.125 0 ) ) textures/base/sky")
+    );
+    assert!(out.contains("{\npatchDef2\n{\ntextures/base/curve\n( 3 3 0 0 0 )"));
+    assert_eq!(
+        reread.extras().entities[0].texture_matrices,
+        map.extras().entities[0].texture_matrices
+    );
+    assert_eq!(
+        reread.extras().entities[0].surface_flags,
+        map.extras().entities[0].surface_flags
+    );
+    assert_eq!(reread.extras().patches(0), map.extras().patches(0));
+}
+
+#[test]
+fn patches_keep_their_place_among_changed_brushes() {
+    // a brush after the patch, and a comment on the entity's opening brace
+    let text = QUAKE3_MAP.replacen("{\n", "{ // world\n", 1).replace(
+        "}\n}\n}\n",
+        "}\n}\n// brush 1\n{\n( -8 -8 -8 ) ( -8 -7 -8 ) ( -8 -8 -7 ) \
+         base 0 0 0 1 1\n( -8 -8 -8 ) ( -8 -8 -7 ) ( -7 -8 -8 ) \
+         base 0 0 0 1 1\n( -8 -8 -8 ) ( -7 -8 -8 ) ( -8 -7 -8 ) \
+         base 0 0 0 1 1\n( 8 8 8 ) ( 8 9 8 ) ( 9 8 8 ) base 0 0 0 1 1\n\
+         ( 8 8 8 ) ( 9 8 8 ) ( 8 8 9 ) base 0 0 0 1 1\n\
+         ( 8 8 8 ) ( 8 8 9 ) ( 8 9 8 ) base 0 0 0 1 1\n}\n}\n",
+    );
+
+    let map = read_map("q3.map".into(), text.clone().into()).unwrap();
+
+    let mut patcher = QuakeMapPatcher::new(map.entities.len());
+    patcher.set_texture(0, 1, 0, c"wall".into());
+    let patched = patcher.patch(&map);
+    let extras = map.extras().patched(&patched);
+
+    let mut out = Vec::new();
+
+    write_map(
+        &mut out,
+        &MapData::new(patched.map).with_extras(extras),
+        Some(&map),
+        Some(&patched.origins),
+    )
+    .unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let brush_1 = out.find("// brush 1\n{\n").unwrap();
+
+    // everything before the changed brush is copied, patch included
+    assert_eq!(out[..brush_1], text[..brush_1]);
+    assert!(out.starts_with("{ // world\n"));
+    assert!(out[brush_1..].contains(") wall 0 0 0 1 1\n"));
+    assert!(out.ends_with("}\n}\n"));
+}
+
+#[test]
+fn malformed_patches_are_reported() {
+    let text = QUAKE3_MAP.replace("( 3 3 0 0 0 )", "( 3 2 0 0 0 )");
+    let e = read_map("q3.map".into(), text.into()).err().unwrap();
+
+    assert_eq!(e.to_string(), "Malformed patch in entity 0 of q3.map");
+}
+
+#[test]
+fn entity_files_keep_their_formatting() {
+    let text = "\
+// lights
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"   // above the start
+}
+{
+\"model\" \"*1\"
+\"classname\" \"func_door\"
+}
+";
+
+    let map = read_ent("e1m1.ent".into(), text.into()).unwrap();
+    assert_eq!(map.entities.len(), 2);
+
+    let mut out = Vec::new();
+    write_ent(&mut out, &map, Some(&map), None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), text);
+
+    let mut changed = QuakeMap::new();
+    changed.entities = map.entities.clone();
+    changed.entities[1]
+        .edict
+        .insert(c"speed".into(), c"200".into());
+
+    let mut out = Vec::new();
+    write_ent(&mut out, &MapData::new(changed), Some(&map), None).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("// lights\n"));
+    assert!(out.contains("\"model\" \"*1\"\n\"classname\" \"func_door\"\n"));
+    assert!(out.contains("\"speed\" \"200\"\n"));
+}
+
+#[test]
+fn entity_files_cannot_hold_brushes() {
+    let e = read_ent("q2.ent".into(), QUAKE2_MAP.into()).err().unwrap();
+
+    assert_eq!(
+        e.to_string(),
+        "q2.ent:1: worldspawn has brushes, which entity files cannot hold"
+    );
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::diagnostics::Diagnostic;
+use crate::patch::PatchResult;
+use crate::plugin::Plugin;
+
+/// Summary of a run for tools tracking map pipelines over time
+#[derive(Serialize)]
+pub struct RunReport<'a> {
+    pub map: &'a Path,
+    pub output: Option<&'a Path>,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub seconds: f64,
+    pub plugins: Vec<PluginReport<'a>>,
+    /// Changes made by every plugin together
+    pub changes: PatchResult,
+    pub diagnostics: &'a [Diagnostic],
+}
+
+#[derive(Serialize)]
+pub struct PluginReport<'a> {
+    pub name: &'a str,
+    pub path: &'a Path,
+    pub version: Option<&'a str>,
+    pub read_only: bool,
+    pub hooks: BTreeMap<&'static str, HookReport>,
+    /// Number of calls made to each import
+    pub host_calls: BTreeMap<&'static str, u64>,
+    pub changes: PatchResult,
+}
+
+#[derive(Serialize)]
+pub struct HookReport {
+    pub calls: u64,
+    pub seconds: f64,
+}
+
+impl<'a> PluginReport<'a> {
+    pub fn new(plugin: &'a Plugin, path: &'a Path) -> Self {
+        let hooks = plugin
+            .hook_stats()
+            .iter()
+            .map(|(&hook, stats)| {
+                let report = HookReport {
+                    calls: stats.calls,
+                    seconds: stats.time.as_secs_f64(),
+                };
+
+                (hook, report)
+            })
+            .collect();
+
+        Self {
+            name: plugin.name(),
+            path,
+            version: plugin.version(),
+            read_only: plugin.is_read_only(),
+            hooks,
+            host_calls: plugin.host_calls(),
+            changes: plugin.changes(),
+        }
+    }
+}
+
+impl<'a> RunReport<'a> {
+    pub fn new(
+        map: &'a Path,
+        output: Option<&'a Path>,
+        outcome: &anyhow::Result<()>,
+        elapsed: Duration,
+        plugins: impl IntoIterator<Item = (&'a Plugin, &'a PathBuf)>,
+        diagnostics: &'a [Diagnostic],
+    ) -> Self {
+        let plugins = plugins
+            .into_iter()
+            .map(|(plugin, path)| PluginReport::new(plugin, path))
+            .collect::<Vec<_>>();
+
+        let mut changes = PatchResult::default();
+
+        for plugin in &plugins {
+            changes += plugin.changes;
+        }
+
+        Self {
+            map,
+            output,
+            succeeded: outcome.is_ok(),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+            seconds: elapsed.as_secs_f64(),
+            plugins,
+            changes,
+            diagnostics,
+        }
+    }
+}
+
+/// Write the report of a single map on its own, or of several maps as an
+/// array
+pub fn write_reports(path: &Path, reports: &[RunReport]) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match reports {
+        [report] => serde_json::to_writer_pretty(&mut writer, report)?,
+        _ => serde_json::to_writer_pretty(&mut writer, reports)?,
+    }
+
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,432 @@
+use std::ffi::CString;
+use std::ops::{Range, RangeInclusive};
+
+use crate::map_data::MapPart;
+
+/// Where each part of a map file lies in its text, so that parts left
+/// untouched by plugins can be written back exactly as they were read
+///
+/// Spans of entities, brushes and keys cover whole lines where nothing else
+/// shares the line, and the comments and blank lines between them are kept
+/// as separate spans.
+pub struct MapSource {
+    /// Name of the file the text was read from, as given by the user
+    name: String,
+    text: Vec<u8>,
+    /// Position of the first character of each line
+    line_starts: Vec<usize>,
+    /// Comments and blank lines before the first entity
+    pub header: Range<usize>,
+    pub entities: Vec<EntitySource>,
+    /// Whatever follows the last entity
+    pub trailer: Range<usize>,
+}
+
+pub struct EntitySource {
+    /// Comments and blank lines between the previous entity and this one
+    pub leading: Range<usize>,
+    /// From the opening brace through the closing brace
+    pub body: Range<usize>,
+    /// The opening brace and whatever follows it on its line
+    pub open: Range<usize>,
+    /// Each key along with the span holding it and its value, in file order
+    pub keyvalues: Vec<(CString, Range<usize>)>,
+    pub brushes: Vec<BrushSource>,
+    pub patches: Vec<PatchSource>,
+    /// Comments and blank lines between the last key, brush or patch and the
+    /// closing brace
+    pub trailing: Range<usize>,
+    /// The closing brace and whatever follows it on its line
+    pub close: Range<usize>,
+}
+
+pub struct BrushSource {
+    /// Comments and blank lines between the previous key or brush and this
+    /// brush
+    pub leading: Range<usize>,
+    /// From the opening brace through the closing brace
+    pub body: Range<usize>,
+    /// Each surface's points, texture and alignment
+    pub surfaces: Vec<Range<usize>>,
+    /// Whether the brush is a Quake 3 `brushDef`, whose surfaces have a
+    /// texture matrix in place of an alignment
+    pub brush_def: bool,
+}
+
+/// A Quake 3 curve patch, which takes the place of a brush in its entity
+pub struct PatchSource {
+    /// Comments and blank lines between the previous key, brush or patch and
+    /// this patch
+    pub leading: Range<usize>,
+    /// From the opening brace through the closing brace
+    pub body: Range<usize>,
+}
+
+impl MapSource {
+    /// Find the parts of a map's text
+    ///
+    /// The text is expected to have already been accepted by the map
+    /// parser; anything it cannot make sense of is skipped.
+    pub fn new(name: String, text: Vec<u8>) -> Self {
+        let mut scanner = Scanner {
+            text: &text,
+            pos: 0,
+        };
+
+        let mut header = 0..0;
+        let mut entities = Vec::new();
+        let mut entity = None::<EntitySource>;
+        let mut key = None::<(Range<usize>, usize)>;
+        // end of the last entity, key or brush seen at the current depth
+        let mut item_end = 0;
+
+        while let Some((start, token)) = scanner.next_token() {
+            let end = scanner.pos;
+
+            match (&mut entity, token) {
+                (None, Token::Open) => {
+                    let body_start = scanner.line_start(start, item_end);
+
+                    let leading = if entities.is_empty() {
+                        header = item_end..body_start;
+                        body_start..body_start
+                    } else {
+                        item_end..body_start
+                    };
+
+                    item_end = scanner.line_end(end);
+
+                    entity = Some(EntitySource {
+                        leading,
+                        body: body_start..body_start,
+                        open: body_start..item_end,
+                        keyvalues: Vec::new(),
+                        brushes: Vec::new(),
+                        patches: Vec::new(),
+                        trailing: 0..0,
+                        close: 0..0,
+                    });
+                }
+                (Some(_), Token::Quoted(contents)) if key.is_none() => {
+                    key = Some((contents, start));
+                }
+                (Some(entity), Token::Quoted(_)) => {
+                    let (contents, key_start) = key.take().unwrap();
+                    let span_start = scanner.line_start(key_start, item_end);
+                    let span_end = scanner.line_end(end);
+
+                    if let Ok(key) = CString::new(&text[contents]) {
+                        entity.keyvalues.push((key, span_start..span_end));
+                    }
+
+                    item_end = span_end;
+                }
+                (Some(entity), Token::Open) => {
+                    let word = scanner.peek_word();
+                    let brush_def = word == b"brushDef";
+
+                    let surfaces = if word.starts_with(b"patchDef") {
+                        scanner.skip_block();
+                        None
+                    } else {
+                        Some(scanner.scan_brush(brush_def))
+                    };
+
+                    let body_start = scanner.line_start(start, item_end);
+                    let body_end = scanner.line_end(scanner.pos);
+                    let leading = item_end..body_start;
+                    let body = body_start..body_end;
+
+                    match surfaces {
+                        Some(surfaces) => entity.brushes.push(BrushSource {
+                            leading,
+                            body,
+                            surfaces,
+                            brush_def,
+                        }),
+                        None => {
+                            entity.patches.push(PatchSource { leading, body })
+                        }
+                    }
+
+                    item_end = body_end;
+                }
+                (Some(_), Token::Close) => {
+                    let mut finished = entity.take().unwrap();
+                    let body_end = scanner.line_end(end);
+
+                    let close_start = scanner.line_start(start, item_end);
+
+                    finished.trailing = item_end..close_start;
+                    finished.close = close_start..body_end;
+                    finished.body.end = body_end;
+                    entities.push(finished);
+
+                    key = None;
+                    item_end = body_end;
+                }
+                _ => {}
+            }
+        }
+
+        if entities.is_empty() {
+            header = 0..text.len();
+            item_end = text.len();
+        }
+
+        let line_starts = [0]
+            .into_iter()
+            .chain(
+                text.iter()
+                    .enumerate()
+                    .filter(|&(_, &ch)| ch == b'\n')
+                    .map(|(pos, _)| pos + 1),
+            )
+            .collect();
+
+        Self {
+            name,
+            header,
+            entities,
+            trailer: item_end..text.len(),
+            text,
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self, span: &Range<usize>) -> &[u8] {
+        &self.text[span.clone()]
+    }
+
+    /// First and last line numbers of the part, counting from 1
+    pub fn lines(&self, part: MapPart) -> Option<RangeInclusive<usize>> {
+        let entity = self.entities.get(part.ent_idx())?;
+
+        let span = match part {
+            MapPart::Entity(_) => &entity.body,
+            MapPart::Brush(_, brush_idx) => {
+                &entity.brushes.get(brush_idx)?.body
+            }
+            MapPart::Surface(_, brush_idx, surface_idx) => {
+                entity.brushes.get(brush_idx)?.surfaces.get(surface_idx)?
+            }
+        };
+
+        let first = self.line_of(span.start);
+        let last = self.line_of(span.end.saturating_sub(1)).max(first);
+        Some(first..=last)
+    }
+
+    /// Span of each token within `span`, skipping whitespace and comments
+    pub fn tokens(&self, span: &Range<usize>) -> Vec<Range<usize>> {
+        let mut scanner = Scanner {
+            text: &self.text[..span.end],
+            pos: span.start,
+        };
+
+        let mut tokens = Vec::new();
+
+        while let Some((start, _)) = scanner.next_token() {
+            tokens.push(start..scanner.pos);
+        }
+
+        tokens
+    }
+
+    fn line_of(&self, pos: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= pos)
+    }
+}
+
+enum Token {
+    Open,
+    Close,
+    OpenParen,
+    /// Range of the characters between the quotes
+    Quoted(Range<usize>),
+    Other,
+}
+
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    /// Next token along with where it starts, skipping whitespace and
+    /// comments
+    fn next_token(&mut self) -> Option<(usize, Token)> {
+        loop {
+            while self.text.get(self.pos)?.is_ascii_whitespace() {
+                self.pos += 1;
+            }
+
+            if !self.text[self.pos..].starts_with(b"//") {
+                break;
+            }
+
+            while self.text.get(self.pos).is_some_and(|&ch| ch != b'\n') {
+                self.pos += 1;
+            }
+        }
+
+        let start = self.pos;
+
+        if self.text[start] == b'"' {
+            let contents_start = start + 1;
+            let contents_end = self.text[contents_start..]
+                .iter()
+                .position(|&ch| ch == b'"')
+                .map_or(self.text.len(), |len| contents_start + len);
+
+            self.pos = (contents_end + 1).min(self.text.len());
+            return Some((start, Token::Quoted(contents_start..contents_end)));
+        }
+
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|ch| !ch.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+
+        // texture names may start with a brace, so only a brace standing
+        // alone counts
+        let token = match &self.text[start..self.pos] {
+            b"{" => Token::Open,
+            b"}" => Token::Close,
+            b"(" => Token::OpenParen,
+            _ => Token::Other,
+        };
+
+        Some((start, token))
+    }
+
+    /// Text of the next token, without reading it
+    fn peek_word(&mut self) -> &'a [u8] {
+        let pos = self.pos;
+        let start = self.next_token().map_or(pos, |(start, _)| start);
+        let end = self.pos;
+        self.pos = pos;
+        &self.text[start..end]
+    }
+
+    /// Read up to the end of a block whose opening brace was just read,
+    /// along with any blocks nested in it
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+
+        while let Some((_, token)) = self.next_token() {
+            match token {
+                Token::Open => depth += 1,
+                Token::Close if depth == 1 => break,
+                Token::Close => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Read up to the end of a brush whose opening brace was just read,
+    /// returning the span of each surface
+    ///
+    /// A surface starts at the first of its three points, so a new one
+    /// begins at every fourth opening parenthesis, or at every seventh in a
+    /// `brushDef`, where each surface's texture matrix adds three more.  A
+    /// `brushDef` wraps its surfaces in a block of their own.
+    fn scan_brush(&mut self, brush_def: bool) -> Vec<Range<usize>> {
+        let mut surfaces = Vec::new();
+        let mut surface = None::<Range<usize>>;
+        let mut points = 0;
+        let parens = if brush_def { 6 } else { 3 };
+
+        if brush_def {
+            // the `brushDef` keyword and the opening brace of its block
+            self.next_token();
+            self.next_token();
+        }
+
+        while let Some((start, token)) = self.next_token() {
+            match token {
+                Token::Close => {
+                    if brush_def {
+                        self.next_token();
+                    }
+
+                    break;
+                }
+                Token::OpenParen if points == parens || surface.is_none() => {
+                    surfaces.extend(surface.replace(start..self.pos));
+                    points = 1;
+                }
+                Token::OpenParen => {
+                    points += 1;
+                }
+                _ => {}
+            }
+
+            if let Some(surface) = &mut surface {
+                surface.end = self.pos;
+            }
+        }
+
+        surfaces.extend(surface);
+
+        let mut floor = 0;
+
+        surfaces
+            .into_iter()
+            .map(|surface| {
+                let start = self.line_start(surface.start, floor);
+                floor = self.line_end(surface.end);
+                start..floor
+            })
+            .collect()
+    }
+
+    /// `pos` moved back to the start of its line if only whitespace comes
+    /// before it there, without going back past `floor`
+    fn line_start(&self, pos: usize, floor: usize) -> usize {
+        let mut start = pos;
+
+        while start > floor && matches!(self.text[start - 1], b' ' | b'\t') {
+            start -= 1;
+        }
+
+        if start == floor || self.text[start - 1] == b'\n' {
+            start
+        } else {
+            pos
+        }
+    }
+
+    /// `pos` moved past the end of its line if only whitespace or a comment
+    /// follows it there
+    fn line_end(&self, pos: usize) -> usize {
+        let mut end = pos;
+
+        while self
+            .text
+            .get(end)
+            .is_some_and(|ch| matches!(ch, b' ' | b'\t' | b'\r'))
+        {
+            end += 1;
+        }
+
+        if self.text[end..].starts_with(b"//") {
+            while self.text.get(end).is_some_and(|&ch| ch != b'\n') {
+                end += 1;
+            }
+        }
+
+        match self.text.get(end) {
+            None => end,
+            Some(b'\n') => end + 1,
+            Some(_) => pos,
+        }
+    }
+}
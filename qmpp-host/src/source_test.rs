@@ -0,0 +1,85 @@
+use quake_util::qmap;
+
+use crate::map_data::{MapData, MapPart};
+use crate::patch::QuakeMapPatcher;
+use crate::source::MapSource;
+
+const MAP: &str = "\
+// Game: Quake
+// entity 0
+{
+\"classname\" \"worldspawn\"
+// brush 0
+{
+( -16 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) {water 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+}
+// entity 1
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+";
+
+fn map_data() -> MapData {
+    let map = qmap::parse(MAP.as_bytes()).unwrap();
+    MapData::with_source(map, MapSource::new("test.map".into(), MAP.into()))
+}
+
+#[test]
+fn part_lines() {
+    let source = MapSource::new("test.map".into(), MAP.into());
+
+    assert_eq!(source.lines(MapPart::Entity(0)), Some(3..=14));
+    assert_eq!(source.lines(MapPart::Brush(0, 0)), Some(6..=13));
+    assert_eq!(source.lines(MapPart::Surface(0, 0, 0)), Some(7..=7));
+    assert_eq!(source.lines(MapPart::Surface(0, 0, 5)), Some(12..=12));
+    assert_eq!(source.lines(MapPart::Entity(1)), Some(16..=19));
+    assert_eq!(source.lines(MapPart::Brush(1, 0)), None);
+}
+
+#[test]
+fn part_locations() {
+    let map = map_data();
+
+    assert_eq!(
+        map.location(MapPart::Surface(0, 0, 2)),
+        "test.map:9: worldspawn brush 0 surface 2"
+    );
+    assert_eq!(map.location(MapPart::Entity(1)), "test.map:16: light");
+
+    let map = MapData::new(qmap::parse(MAP.as_bytes()).unwrap());
+    assert_eq!(map.location(MapPart::Brush(0, 0)), "worldspawn brush 0");
+}
+
+#[test]
+fn patched_parts_keep_their_locations() {
+    let input = map_data();
+
+    let mut patcher = QuakeMapPatcher::new(input.entities.len());
+    patcher.delete_entity(0);
+    patcher.add_entity();
+    let patched = patcher.patch(&input);
+    let once = MapData::patched(patched.map, &input, &patched.origins);
+
+    assert!(once.source().is_none());
+    assert_eq!(once.source_name(), Some("test.map"));
+    assert_eq!(once.location(MapPart::Entity(0)), "test.map:16: light");
+    assert_eq!(once.location(MapPart::Entity(1)), "entity 1");
+
+    // a second patch follows the first back to the input
+    let mut patcher = QuakeMapPatcher::new(once.entities.len());
+    patcher.delete_entity(1);
+    patcher.set_keyvalue(0, c"light".into(), c"300".into());
+    let patched = patcher.patch(&once);
+    let twice = MapData::patched(patched.map, &once, &patched.origins);
+
+    assert_eq!(twice.entities.len(), 1);
+    assert_eq!(twice.source_lines(MapPart::Entity(0)), Some(16..=19));
+    assert_eq!(twice.location(MapPart::Entity(0)), "test.map:16: light");
+}
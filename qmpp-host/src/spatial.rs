@@ -0,0 +1,293 @@
+use quake_util::qmap::{Entity, Point, QuakeMap};
+
+use crate::geometry::{Bounds, MapGeometry, EPSILON};
+
+/// Most items kept together in one leaf of a `Bvh`
+const LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over items with bounds
+pub struct Bvh<T> {
+    items: Vec<(Bounds, T)>,
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    bounds: Bounds,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    /// Range of items in the leaf
+    Leaf { start: usize, end: usize },
+    /// Indices of the two child nodes
+    Branch { left: usize, right: usize },
+}
+
+impl<T: Copy> Bvh<T> {
+    pub fn new(mut items: Vec<(Bounds, T)>) -> Self {
+        let mut nodes = Vec::new();
+
+        if !items.is_empty() {
+            build(&mut nodes, &mut items, 0);
+        }
+
+        Self { items, nodes }
+    }
+
+    /// Every item whose bounds intersect the region, in no particular order
+    pub fn query(&self, region: &Bounds) -> Vec<T> {
+        let mut found = Vec::new();
+        let mut pending = self.root().into_iter().collect::<Vec<_>>();
+
+        while let Some(node_idx) = pending.pop() {
+            let node = &self.nodes[node_idx];
+
+            if !node.bounds.intersects(region) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, end } => found.extend(
+                    self.items[start..end]
+                        .iter()
+                        .filter(|(bounds, _)| bounds.intersects(region))
+                        .map(|&(_, item)| item),
+                ),
+                NodeKind::Branch { left, right } => {
+                    pending.extend([left, right]);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Item closest to the point according to `distance`, along with that
+    /// distance
+    ///
+    /// `distance` must never be less than the distance from the point to the
+    /// item's bounds, which lets whole subtrees be skipped.  Items it gives
+    /// an infinite distance are never returned.
+    pub fn nearest(
+        &self,
+        point: Point,
+        mut distance: impl FnMut(T) -> f64,
+    ) -> Option<(T, f64)> {
+        let mut best: Option<(T, f64)> = None;
+        let mut pending = self.root().into_iter().collect::<Vec<_>>();
+
+        let beaten = |best: &Option<(T, f64)>, bounds: &Bounds| {
+            best.is_some_and(|(_, dist)| bounds.distance_to(point) >= dist)
+        };
+
+        while let Some(node_idx) = pending.pop() {
+            let node = &self.nodes[node_idx];
+
+            if beaten(&best, &node.bounds) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, end } => {
+                    for (bounds, item) in &self.items[start..end] {
+                        if beaten(&best, bounds) {
+                            continue;
+                        }
+
+                        let dist = distance(*item);
+
+                        if dist.is_finite()
+                            && best.is_none_or(|(_, best)| dist < best)
+                        {
+                            best = Some((*item, dist));
+                        }
+                    }
+                }
+                NodeKind::Branch { left, right } => {
+                    let left_dist = self.nodes[left].bounds.distance_to(point);
+                    let right_dist =
+                        self.nodes[right].bounds.distance_to(point);
+
+                    // nearer child is pushed last so it is searched first
+                    if left_dist <= right_dist {
+                        pending.extend([right, left]);
+                    } else {
+                        pending.extend([left, right]);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn root(&self) -> Option<usize> {
+        (!self.nodes.is_empty()).then_some(0)
+    }
+}
+
+/// Add a node for the items and everything below it, returning its index
+///
+/// Items are split in half along the axis their centers are most spread
+/// out on, and reordered so that each node's items are contiguous.
+/// `offset` is the index of the first item within the whole list.
+fn build<T>(
+    nodes: &mut Vec<Node>,
+    items: &mut [(Bounds, T)],
+    offset: usize,
+) -> usize {
+    let bounds = items
+        .iter()
+        .map(|(bounds, _)| *bounds)
+        .reduce(|a, b| a.union(&b))
+        .unwrap();
+
+    let node_idx = nodes.len();
+
+    nodes.push(Node {
+        bounds,
+        kind: NodeKind::Leaf {
+            start: offset,
+            end: offset + items.len(),
+        },
+    });
+
+    if items.len() <= LEAF_SIZE {
+        return node_idx;
+    }
+
+    let centers = items
+        .iter()
+        .map(|(bounds, _)| Bounds::point(bounds.center()))
+        .reduce(|a, b| a.union(&b))
+        .unwrap();
+
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            let a = centers.maxs[a] - centers.mins[a];
+            let b = centers.maxs[b] - centers.mins[b];
+            a.total_cmp(&b)
+        })
+        .unwrap();
+
+    let mid = items.len() / 2;
+
+    items.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+        a.center()[axis].total_cmp(&b.center()[axis])
+    });
+
+    let (left_items, right_items) = items.split_at_mut(mid);
+    let left = build(nodes, left_items, offset);
+    let right = build(nodes, right_items, offset + mid);
+    nodes[node_idx].kind = NodeKind::Branch { left, right };
+
+    node_idx
+}
+
+/// Where every entity and brush in a map is, for answering region and
+/// proximity queries without visiting each one
+///
+/// Point entities are placed at their `origin` and brush entities span
+/// their brushes.  Entities with neither, and brushes that do not enclose a
+/// finite volume, are left out.
+pub struct SpatialIndex {
+    entities: Bvh<usize>,
+    brushes: Bvh<(usize, usize)>,
+}
+
+impl SpatialIndex {
+    pub fn new(map: &QuakeMap, geometry: &MapGeometry) -> Self {
+        let entities = map
+            .entities
+            .iter()
+            .enumerate()
+            .filter_map(|(ent_idx, entity)| {
+                let bounds = if entity.brushes.is_empty() {
+                    Bounds::point(origin(entity)?)
+                } else {
+                    geometry.entity_bounds(ent_idx)?
+                };
+
+                Some((bounds, ent_idx))
+            })
+            .collect();
+
+        let brushes = map
+            .entities
+            .iter()
+            .enumerate()
+            .flat_map(|(ent_idx, entity)| {
+                (0..entity.brushes.len()).filter_map(move |brush_idx| {
+                    let bounds = geometry.brush(ent_idx, brush_idx)?.bounds?;
+                    Some((bounds, (ent_idx, brush_idx)))
+                })
+            })
+            .collect();
+
+        Self {
+            entities: Bvh::new(entities),
+            brushes: Bvh::new(brushes),
+        }
+    }
+
+    /// Indices of entities within or overlapping the region, in ascending
+    /// order
+    pub fn entities_in(&self, region: &Bounds) -> Vec<usize> {
+        let mut found = self.entities.query(region);
+        found.sort_unstable();
+        found
+    }
+
+    /// Entity and brush indices of brushes whose bounds overlap the region,
+    /// in ascending order
+    pub fn brushes_in(&self, region: &Bounds) -> Vec<(usize, usize)> {
+        let mut found = self.brushes.query(region);
+        found.sort_unstable();
+        found
+    }
+
+    /// Entity and brush indices of brushes containing the point, in
+    /// ascending order
+    pub fn brushes_at(
+        &self,
+        point: Point,
+        geometry: &MapGeometry,
+    ) -> Vec<(usize, usize)> {
+        let mut found = self.brushes_in(&Bounds::point(point).expand(EPSILON));
+
+        found.retain(|&(ent_idx, brush_idx)| {
+            geometry
+                .brush(ent_idx, brush_idx)
+                .is_some_and(|brush| brush.contains(point))
+        });
+
+        found
+    }
+
+    /// Entity and brush indices of the brush nearest the point along with
+    /// its distance, which is 0 if the brush contains the point
+    pub fn nearest_brush(
+        &self,
+        point: Point,
+        geometry: &MapGeometry,
+    ) -> Option<((usize, usize), f64)> {
+        self.brushes.nearest(point, |(ent_idx, brush_idx)| {
+            geometry
+                .brush(ent_idx, brush_idx)
+                .map_or(f64::INFINITY, |brush| brush.distance_to(point))
+        })
+    }
+}
+
+/// Entity's `origin` key parsed as a point, if it has a valid one
+fn origin(entity: &Entity) -> Option<Point> {
+    let value = entity.edict.get(c"origin")?.to_str().ok()?;
+    let mut coords = value.split_whitespace().map(str::parse::<f64>);
+    let point = [
+        coords.next()?.ok()?,
+        coords.next()?.ok()?,
+        coords.next()?.ok()?,
+    ];
+
+    coords.next().is_none().then_some(point)
+}
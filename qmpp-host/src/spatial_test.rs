@@ -0,0 +1,174 @@
+use std::ffi::CString;
+
+use quake_util::qmap::{Alignment, Brush, Entity, QuakeMap, Surface};
+
+use crate::geometry::{Bounds, MapGeometry};
+use crate::spatial::{Bvh, SpatialIndex};
+
+fn surface(half_space: [[f64; 3]; 3]) -> Surface {
+    Surface {
+        half_space,
+        texture: c"base".into(),
+        alignment: Alignment {
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            axes: None,
+        },
+    }
+}
+
+/// Axis-aligned box brush with the given corners
+fn cuboid(mins: [f64; 3], maxs: [f64; 3]) -> Brush {
+    let [x0, y0, z0] = mins;
+    let [x1, y1, z1] = maxs;
+
+    vec![
+        surface([[x0, y0, z0], [x0, y0 + 1.0, z0], [x0, y0, z0 + 1.0]]),
+        surface([[x0, y0, z0], [x0, y0, z0 + 1.0], [x0 + 1.0, y0, z0]]),
+        surface([[x0, y0, z0], [x0 + 1.0, y0, z0], [x0, y0 + 1.0, z0]]),
+        surface([[x1, y1, z1], [x1, y1 + 1.0, z1], [x1 + 1.0, y1, z1]]),
+        surface([[x1, y1, z1], [x1 + 1.0, y1, z1], [x1, y1, z1 + 1.0]]),
+        surface([[x1, y1, z1], [x1, y1, z1 + 1.0], [x1, y1 + 1.0, z1]]),
+    ]
+}
+
+fn entity(
+    classname: &str,
+    origin: Option<&str>,
+    brushes: Vec<Brush>,
+) -> Entity {
+    let mut entity = Entity::new();
+
+    entity
+        .edict
+        .insert(c"classname".to_owned(), CString::new(classname).unwrap());
+
+    if let Some(origin) = origin {
+        entity
+            .edict
+            .insert(c"origin".to_owned(), CString::new(origin).unwrap());
+    }
+
+    entity.brushes = brushes;
+    entity
+}
+
+fn test_map() -> QuakeMap {
+    QuakeMap {
+        entities: vec![
+            entity(
+                "worldspawn",
+                None,
+                vec![
+                    cuboid([-256.0, -256.0, -16.0], [256.0, 256.0, 0.0]),
+                    cuboid([-256.0, -256.0, 0.0], [-240.0, 256.0, 128.0]),
+                    cuboid([240.0, -256.0, 0.0], [256.0, 256.0, 128.0]),
+                ],
+            ),
+            entity("light", Some("-200 0 64"), Vec::new()),
+            entity(
+                "trigger_once",
+                None,
+                vec![cuboid([0.0, 0.0, 0.0], [64.0, 64.0, 64.0])],
+            ),
+            entity("monster_ogre", Some("32 32 24"), Vec::new()),
+            entity("info_null", None, Vec::new()),
+            entity("info_notnull", Some("not a point"), Vec::new()),
+        ],
+    }
+}
+
+fn region(mins: [f64; 3], maxs: [f64; 3]) -> Bounds {
+    Bounds { mins, maxs }
+}
+
+#[test]
+fn entities_in_region() {
+    let map = test_map();
+    let geometry = MapGeometry::new(&map);
+    let index = SpatialIndex::new(&map, &geometry);
+
+    let around_ogre = region([16.0, 16.0, 0.0], [48.0, 48.0, 56.0]);
+    assert_eq!(index.entities_in(&around_ogre), vec![0, 2, 3]);
+
+    let above_map = region([-8.0, -8.0, 256.0], [8.0, 8.0, 512.0]);
+    assert_eq!(index.entities_in(&above_map), Vec::<usize>::new());
+}
+
+#[test]
+fn brushes_in_region_and_at_point() {
+    let map = test_map();
+    let geometry = MapGeometry::new(&map);
+    let index = SpatialIndex::new(&map, &geometry);
+
+    let left_wall = region([-250.0, -8.0, 32.0], [-248.0, 8.0, 40.0]);
+    assert_eq!(index.brushes_in(&left_wall), vec![(0, 1)]);
+
+    assert_eq!(
+        index.brushes_at([32.0, 32.0, 0.0], &geometry),
+        vec![(0, 0), (2, 0)]
+    );
+    assert_eq!(index.brushes_at([100.0, 100.0, 64.0], &geometry), vec![]);
+}
+
+#[test]
+fn nearest_brush() {
+    let map = test_map();
+    let geometry = MapGeometry::new(&map);
+    let index = SpatialIndex::new(&map, &geometry);
+
+    let (brush, dist) =
+        index.nearest_brush([-200.0, 0.0, 64.0], &geometry).unwrap();
+    assert_eq!(brush, (0, 1));
+    assert!((dist - 40.0).abs() < 1e-9);
+
+    let (brush, dist) =
+        index.nearest_brush([32.0, 32.0, 32.0], &geometry).unwrap();
+    assert_eq!(brush, (2, 0));
+    assert_eq!(dist, 0.0);
+
+    let empty = QuakeMap::new();
+    let geometry = MapGeometry::new(&empty);
+    let index = SpatialIndex::new(&empty, &geometry);
+    assert!(index.nearest_brush([0.0; 3], &geometry).is_none());
+}
+
+#[test]
+fn bvh_matches_brute_force() {
+    let items = (0..200)
+        .map(|i| {
+            let f = i as f64;
+            let mins =
+                [(f * 37.0) % 500.0, (f * 91.0) % 300.0, (f * 13.0) % 50.0];
+            let maxs = mins.map(|coord| coord + (f % 7.0) * 4.0);
+            (region(mins, maxs), i)
+        })
+        .collect::<Vec<_>>();
+
+    let bvh = Bvh::new(items.clone());
+    let query = region([100.0, 50.0, 10.0], [250.0, 120.0, 30.0]);
+
+    let mut found = bvh.query(&query);
+    found.sort_unstable();
+
+    let expected = items
+        .iter()
+        .filter(|(bounds, _)| bounds.intersects(&query))
+        .map(|&(_, i)| i)
+        .collect::<Vec<_>>();
+
+    assert_eq!(found, expected);
+
+    let point = [321.0, 12.0, 77.0];
+    let (_, nearest) = bvh
+        .nearest(point, |i| items[i].0.distance_to(point))
+        .unwrap();
+
+    let expected = items
+        .iter()
+        .map(|(bounds, _)| bounds.distance_to(point))
+        .fold(f64::INFINITY, f64::min);
+
+    assert_eq!(nearest, expected);
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Notices changes to a set of files by polling their modification times
+///
+/// Polling keeps the host free of platform-specific notification APIs, and
+/// a quarter of a second is well below the time it takes to switch from
+/// the editor to a compile.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    /// Modification time of each path when last checked, or `None` if it
+    /// could not be read
+    stamps: Vec<Option<SystemTime>>,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let stamps = paths.iter().map(|path| modified(path)).collect();
+        Self { paths, stamps }
+    }
+
+    /// Watch `paths` from now on
+    ///
+    /// Paths already watched keep their last modification time, and the
+    /// others count as changed once they can be read.
+    pub fn set_paths(&mut self, paths: Vec<PathBuf>) {
+        self.stamps = paths
+            .iter()
+            .map(|path| {
+                let idx = self.paths.iter().position(|old| old == path)?;
+                self.stamps[idx]
+            })
+            .collect();
+
+        self.paths = paths;
+    }
+
+    /// Paths that changed since they were last checked
+    ///
+    /// A file that disappears is not reported until it is back, since
+    /// editors commonly save by replacing the file.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+
+        for (idx, path) in self.paths.iter().enumerate() {
+            let stamp = modified(path);
+
+            if stamp.is_some() && stamp != self.stamps[idx] {
+                changed.push(path.clone());
+            }
+
+            self.stamps[idx] = stamp;
+        }
+
+        changed
+    }
+
+    /// Block until at least one path changes, returning those that did
+    ///
+    /// The paths to watch are asked for again before each poll, so that
+    /// maps appearing in a watched directory are noticed.  Changes keep
+    /// being collected until a poll finds none, so a file still being
+    /// written is not picked up half way.
+    pub fn wait(
+        &mut self,
+        mut paths: impl FnMut() -> Vec<PathBuf>,
+    ) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            self.set_paths(paths());
+            let newly_changed = self.changed();
+
+            if newly_changed.is_empty() && !changed.is_empty() {
+                return changed;
+            }
+
+            for path in newly_changed {
+                if !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
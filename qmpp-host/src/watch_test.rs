@@ -0,0 +1,56 @@
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime};
+
+use crate::watch::Watcher;
+
+#[test]
+fn modified_files_are_reported_once() {
+    let dir = std::env::temp_dir()
+        .join(format!("qmpp-watch-test-{}", std::process::id()));
+
+    fs::create_dir_all(&dir).unwrap();
+    let map_path = dir.join("test.map");
+    let wasm_path = dir.join("test.wasm");
+    fs::write(&map_path, "{\n}\n").unwrap();
+    fs::write(&wasm_path, b"\0asm").unwrap();
+
+    let mut watcher = Watcher::new(vec![map_path.clone(), wasm_path.clone()]);
+    assert!(watcher.changed().is_empty());
+
+    // set the time explicitly since file systems may only store whole seconds
+    let later = SystemTime::now() + Duration::from_secs(10);
+    let file = File::options().write(true).open(&wasm_path).unwrap();
+    file.set_modified(later).unwrap();
+
+    assert_eq!(watcher.changed(), vec![wasm_path.clone()]);
+    assert!(watcher.changed().is_empty());
+
+    fs::remove_file(&map_path).unwrap();
+    assert!(watcher.changed().is_empty());
+
+    fs::write(&map_path, "{\n}\n").unwrap();
+    assert_eq!(watcher.changed(), vec![map_path.clone()]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paths_joining_the_set_count_as_changed() {
+    let dir = std::env::temp_dir()
+        .join(format!("qmpp-watch-set-test-{}", std::process::id()));
+
+    fs::create_dir_all(&dir).unwrap();
+    let old_path = dir.join("old.map");
+    let new_path = dir.join("new.map");
+    fs::write(&old_path, "{\n}\n").unwrap();
+    fs::write(&new_path, "{\n}\n").unwrap();
+
+    let mut watcher = Watcher::new(vec![old_path.clone()]);
+
+    // the map already watched keeps its time, and the new one is reported
+    watcher.set_paths(vec![new_path.clone(), old_path.clone()]);
+    assert_eq!(watcher.changed(), vec![new_path.clone()]);
+    assert!(watcher.changed().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
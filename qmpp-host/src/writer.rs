@@ -0,0 +1,422 @@
+use std::ffi::CStr;
+use std::io::{self, Write};
+
+use quake_util::qmap::{Brush, Entity, Surface};
+
+use crate::alignment::same_alignment;
+use crate::extras::{EntityExtras, PatchMesh, SurfaceFlags, TextureMatrix};
+use crate::map_data::MapData;
+use crate::patch::EntityOrigin;
+use crate::source::{EntitySource, MapSource};
+
+/// Write the map in the `.map` format, in the Quake 2 variant for surfaces
+/// with flags and the Quake 3 one for `brushDef`s and patches
+///
+/// If `original` is given along with the text it was read from, every part
+/// of `map` equal to the corresponding part of `original` is copied from
+/// that text, keeping its comments and number formatting.  Keys of a changed
+/// entity stay in their original order and new keys follow them.
+///
+/// `origins` gives where in `original` each entity of `map` and each of its
+/// brushes came from, or `None` for entities with no counterpart there.  If
+/// it is not given, each entity and brush corresponds to the one at the same
+/// index.
+pub fn write_map(
+    writer: &mut impl Write,
+    map: &MapData,
+    original: Option<&MapData>,
+    origins: Option<&[Option<EntityOrigin>]>,
+) -> io::Result<()> {
+    let (original, source) = match original {
+        Some(original) => match original.source() {
+            Some(source) => (original, source),
+            None => {
+                return write_entities(writer, map);
+            }
+        },
+        None => {
+            return write_entities(writer, map);
+        }
+    };
+
+    writer.write_all(source.text(&source.header))?;
+
+    for (ent_idx, entity) in map.entities.iter().enumerate() {
+        let (orig_idx, brush_origins) = match origins {
+            Some(origins) => {
+                match origins.get(ent_idx).and_then(Option::as_ref) {
+                    Some(origin) => {
+                        (Some(origin.ent_idx), Some(&origin.brushes[..]))
+                    }
+                    None => (None, None),
+                }
+            }
+            None => (Some(ent_idx), None),
+        };
+
+        let extras = map.extras().entities.get(ent_idx);
+        let ent_source = orig_idx.and_then(|idx| source.entities.get(idx));
+        let orig_entity = orig_idx.and_then(|idx| original.entities.get(idx));
+
+        match (orig_entity, ent_source) {
+            (Some(original), Some(ent_source)) => {
+                writer.write_all(source.text(&ent_source.leading))?;
+
+                if same_entity(entity, original) {
+                    writer.write_all(source.text(&ent_source.body))?;
+                } else {
+                    write_changed_entity(
+                        writer,
+                        entity,
+                        extras,
+                        original,
+                        brush_origins,
+                        ent_source,
+                        source,
+                    )?;
+                }
+            }
+            _ => write_entity(writer, entity, extras)?,
+        }
+    }
+
+    writer.write_all(source.text(&source.trailer))
+}
+
+/// Write only the keys and values of the map's entities, as in the `.ent`
+/// files BSP tools extract and replace
+///
+/// Maps that have no brushes are written as by `write_map`.  Otherwise
+/// every entity is written afresh without its brushes, so an entity file
+/// made from a `.map` lacks the `model` keys a compiler would add to brush
+/// entities.
+pub fn write_ent(
+    writer: &mut impl Write,
+    map: &MapData,
+    original: Option<&MapData>,
+    origins: Option<&[Option<EntityOrigin>]>,
+) -> io::Result<()> {
+    let has_brushes =
+        |map: &MapData| (0..map.entities.len()).any(|idx| map.has_brushes(idx));
+
+    if !has_brushes(map) && !original.is_some_and(has_brushes) {
+        return write_map(writer, map, original, origins);
+    }
+
+    for entity in &map.entities {
+        let entity = Entity {
+            edict: entity.edict.clone(),
+            brushes: Vec::new(),
+        };
+
+        write_entity(writer, &entity, None)?;
+    }
+
+    Ok(())
+}
+
+fn write_entities(writer: &mut impl Write, map: &MapData) -> io::Result<()> {
+    for (ent_idx, entity) in map.entities.iter().enumerate() {
+        write_entity(writer, entity, map.extras().entities.get(ent_idx))?;
+    }
+
+    Ok(())
+}
+
+fn write_changed_entity(
+    writer: &mut impl Write,
+    entity: &Entity,
+    extras: Option<&EntityExtras>,
+    original: &Entity,
+    brush_origins: Option<&[Option<usize>]>,
+    ent_source: &EntitySource,
+    source: &MapSource,
+) -> io::Result<()> {
+    writer.write_all(source.text(&ent_source.open))?;
+
+    for (key, span) in &ent_source.keyvalues {
+        match entity.edict.get(key.as_c_str()) {
+            Some(value)
+                if original.edict.get(key.as_c_str()) == Some(value) =>
+            {
+                writer.write_all(source.text(span))?;
+            }
+            Some(value) => write_keyvalue(writer, key, value)?,
+            None => {}
+        }
+    }
+
+    let mut added = entity
+        .edict
+        .iter()
+        .filter(|(key, _)| {
+            !ent_source
+                .keyvalues
+                .iter()
+                .any(|(source_key, _)| source_key == *key)
+        })
+        .collect::<Vec<_>>();
+
+    added.sort();
+
+    for (key, value) in added {
+        write_keyvalue(writer, key, value)?;
+    }
+
+    // the brush each source brush became, or `None` if it was removed
+    let mut brushes_by_source = vec![None; ent_source.brushes.len()];
+    let mut added_brushes = Vec::new();
+
+    for brush_idx in 0..entity.brushes.len() {
+        let orig_idx = match brush_origins {
+            Some(brush_origins) => {
+                brush_origins.get(brush_idx).copied().flatten()
+            }
+            None => Some(brush_idx),
+        };
+
+        match orig_idx.filter(|&idx| {
+            idx < ent_source.brushes.len() && idx < original.brushes.len()
+        }) {
+            Some(orig_idx) => brushes_by_source[orig_idx] = Some(brush_idx),
+            None => added_brushes.push(brush_idx),
+        }
+    }
+
+    // brushes and patches are written in the order they appear in the
+    // source, and plugins cannot change patches, so they are always copied
+    let mut patches = ent_source.patches.iter().peekable();
+
+    for (orig_idx, brush_source) in ent_source.brushes.iter().enumerate() {
+        while let Some(patch_source) =
+            patches.next_if(|patch| patch.body.start < brush_source.body.start)
+        {
+            writer.write_all(source.text(&patch_source.leading))?;
+            writer.write_all(source.text(&patch_source.body))?;
+        }
+
+        let Some(brush_idx) = brushes_by_source[orig_idx] else {
+            continue;
+        };
+
+        let brush = &entity.brushes[brush_idx];
+        writer.write_all(source.text(&brush_source.leading))?;
+
+        if same_brush(brush, &original.brushes[orig_idx]) {
+            writer.write_all(source.text(&brush_source.body))?;
+        } else {
+            write_brush(writer, brush, extras, brush_idx)?;
+        }
+    }
+
+    for patch_source in patches {
+        writer.write_all(source.text(&patch_source.leading))?;
+        writer.write_all(source.text(&patch_source.body))?;
+    }
+
+    for brush_idx in added_brushes {
+        write_brush(writer, &entity.brushes[brush_idx], extras, brush_idx)?;
+    }
+
+    writer.write_all(source.text(&ent_source.trailing))?;
+    writer.write_all(source.text(&ent_source.close))
+}
+
+fn write_entity(
+    writer: &mut impl Write,
+    entity: &Entity,
+    extras: Option<&EntityExtras>,
+) -> io::Result<()> {
+    writer.write_all(b"{\n")?;
+
+    let mut keyvalues = entity.edict.iter().collect::<Vec<_>>();
+    keyvalues.sort();
+
+    for (key, value) in keyvalues {
+        write_keyvalue(writer, key, value)?;
+    }
+
+    for (brush_idx, brush) in entity.brushes.iter().enumerate() {
+        write_brush(writer, brush, extras, brush_idx)?;
+    }
+
+    for patch in extras.map_or(&[][..], |extras| &extras.patches) {
+        write_patch(writer, patch)?;
+    }
+
+    writer.write_all(b"}\n")
+}
+
+fn write_keyvalue(
+    writer: &mut impl Write,
+    key: &CStr,
+    value: &CStr,
+) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    writer.write_all(key.to_bytes())?;
+    writer.write_all(b"\" \"")?;
+    writer.write_all(value.to_bytes())?;
+    writer.write_all(b"\"\n")
+}
+
+/// Write a brush as a `brushDef` if it was read as one and its surfaces
+/// still have the default alignment they were given, or else as a
+/// standard brush
+fn write_brush(
+    writer: &mut impl Write,
+    brush: &Brush,
+    extras: Option<&EntityExtras>,
+    brush_idx: usize,
+) -> io::Result<()> {
+    let flags = extras
+        .and_then(|extras| extras.surface_flags.get(brush_idx))
+        .map_or(&[][..], Vec::as_slice);
+
+    let matrices = extras
+        .and_then(|extras| extras.texture_matrices.get(brush_idx)?.as_ref())
+        .filter(|matrices| {
+            matrices.len() == brush.len() && brush.iter().all(default_alignment)
+        });
+
+    if matrices.is_some() {
+        writer.write_all(b"{\nbrushDef\n{\n")?;
+    } else {
+        writer.write_all(b"{\n")?;
+    }
+
+    for (surface_idx, surface) in brush.iter().enumerate() {
+        let flags = flags.get(surface_idx).copied().flatten();
+
+        match matrices {
+            Some(matrices) => write_brush_def_surface(
+                writer,
+                surface,
+                &matrices[surface_idx],
+                flags,
+            )?,
+            None => write_surface(writer, surface, flags)?,
+        }
+    }
+
+    if matrices.is_some() {
+        writer.write_all(b"}\n")?;
+    }
+
+    writer.write_all(b"}\n")
+}
+
+fn write_surface(
+    writer: &mut impl Write,
+    surface: &Surface,
+    flags: Option<SurfaceFlags>,
+) -> io::Result<()> {
+    write_points(writer, surface)?;
+    writer.write_all(surface.texture.to_bytes())?;
+
+    let alignment = &surface.alignment;
+    let [u_offset, v_offset] = alignment.offset;
+    let [u_scale, v_scale] = alignment.scale;
+
+    match alignment.axes {
+        Some([[ux, uy, uz], [vx, vy, vz]]) => write!(
+            writer,
+            " [ {} {} {} {} ] [ {} {} {} {} ]",
+            ux, uy, uz, u_offset, vx, vy, vz, v_offset
+        )?,
+        None => write!(writer, " {} {}", u_offset, v_offset)?,
+    }
+
+    write!(writer, " {} {} {}", alignment.rotation, u_scale, v_scale)?;
+    write_flags(writer, flags)?;
+    writeln!(writer)
+}
+
+fn write_brush_def_surface(
+    writer: &mut impl Write,
+    surface: &Surface,
+    matrix: &TextureMatrix,
+    flags: Option<SurfaceFlags>,
+) -> io::Result<()> {
+    let [[a, b, c], [d, e, f]] = matrix;
+
+    write_points(writer, surface)?;
+    write!(writer, "( ( {} {} {} ) ( {} {} {} ) ) ", a, b, c, d, e, f)?;
+    writer.write_all(surface.texture.to_bytes())?;
+    write_flags(writer, flags)?;
+    writeln!(writer)
+}
+
+fn write_points(writer: &mut impl Write, surface: &Surface) -> io::Result<()> {
+    for [x, y, z] in surface.half_space {
+        write!(writer, "( {} {} {} ) ", x, y, z)?;
+    }
+
+    Ok(())
+}
+
+fn write_flags(
+    writer: &mut impl Write,
+    flags: Option<SurfaceFlags>,
+) -> io::Result<()> {
+    match flags {
+        Some(flags) => write!(
+            writer,
+            " {} {} {}",
+            flags.contents, flags.flags, flags.value
+        ),
+        None => Ok(()),
+    }
+}
+
+fn write_patch(writer: &mut impl Write, patch: &PatchMesh) -> io::Result<()> {
+    writeln!(writer, "{{\n{}\n{{", patch.def)?;
+    writer.write_all(patch.texture.to_bytes())?;
+    write!(writer, "\n( {} {}", patch.width, patch.height)?;
+
+    for param in &patch.params {
+        write!(writer, " {}", param)?;
+    }
+
+    writer.write_all(b" )\n(\n")?;
+
+    for row in patch.points.chunks(patch.height.max(1)) {
+        writer.write_all(b"(")?;
+
+        for [x, y, z, s, t] in row {
+            write!(writer, " ( {} {} {} {} {} )", x, y, z, s, t)?;
+        }
+
+        writer.write_all(b" )\n")?;
+    }
+
+    writer.write_all(b")\n}\n}\n")
+}
+
+/// Whether a surface has the alignment `brushDef` surfaces are read with
+fn default_alignment(surface: &Surface) -> bool {
+    let alignment = &surface.alignment;
+
+    alignment.offset == [0.0, 0.0]
+        && alignment.rotation == 0.0
+        && alignment.scale == [1.0, 1.0]
+        && alignment.axes.is_none()
+}
+
+fn same_entity(a: &Entity, b: &Entity) -> bool {
+    a.edict == b.edict
+        && a.brushes.len() == b.brushes.len()
+        && a.brushes
+            .iter()
+            .zip(&b.brushes)
+            .all(|(a, b)| same_brush(a, b))
+}
+
+fn same_brush(a: &Brush, b: &Brush) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_surface(a, b))
+}
+
+fn same_surface(a: &Surface, b: &Surface) -> bool {
+    a.half_space == b.half_space
+        && a.texture == b.texture
+        && same_alignment(&a.alignment, &b.alignment)
+}
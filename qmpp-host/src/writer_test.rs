@@ -0,0 +1,247 @@
+use std::ffi::CString;
+
+use quake_util::qmap::{self, QuakeMap};
+
+use crate::map_data::MapData;
+use crate::patch::QuakeMapPatcher;
+use crate::source::MapSource;
+use crate::writer::{write_ent, write_map};
+
+const FIXTURE_PATH: &str = "test-res/q25_limits_4lt.map";
+
+const SMALL_MAP: &str = "\
+// Game: Quake
+// Format: Standard
+// entity 0
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"gfx.wad\"
+// brush 0
+{
+( -16.000 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+// brush 1
+{
+( 32 -16 -16 ) ( 32 -15 -16 ) ( 32 -16 -15 ) base 0 0 0 1.0 1
+( 32 -16 -16 ) ( 32 -16 -15 ) ( 33 -16 -16 ) base 0 0 0 1 1
+( 32 -16 -16 ) ( 33 -16 -16 ) ( 32 -15 -16 ) base 0 0 0 1 1
+( 64 16 16 ) ( 64 17 16 ) ( 65 16 16 ) base 0 0 0 1 1
+( 64 16 16 ) ( 65 16 16 ) ( 64 16 17 ) base 0 0 0 1 1
+( 64 16 16 ) ( 64 16 17 ) ( 64 17 16 ) base 0 0 0 1 1
+}
+}
+// entity 1
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+";
+
+fn parse(text: &str) -> QuakeMap {
+    qmap::parse(text.as_bytes()).unwrap()
+}
+
+fn written(map: QuakeMap, original: Option<&MapData>) -> String {
+    let mut out = Vec::new();
+    write_map(&mut out, &MapData::new(map), original, None).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn unchanged_map_round_trips() {
+    let text = std::fs::read(FIXTURE_PATH).unwrap();
+    let map = qmap::parse(&text[..]).unwrap();
+    let original = MapData::with_source(
+        map,
+        MapSource::new(FIXTURE_PATH.into(), text.clone()),
+    );
+
+    let mut out = Vec::new();
+    write_map(&mut out, &original, Some(&original), None).unwrap();
+    assert!(out == text);
+}
+
+#[test]
+fn changed_parts_are_rewritten() {
+    let original = MapData::with_source(
+        parse(SMALL_MAP),
+        MapSource::new("small.map".into(), SMALL_MAP.into()),
+    );
+
+    let mut map = parse(SMALL_MAP);
+    let worldspawn = &mut map.entities[0];
+
+    worldspawn
+        .edict
+        .insert(c"message".to_owned(), CString::new("hello").unwrap());
+
+    worldspawn.edict.remove(c"wad");
+
+    for surface in &mut worldspawn.brushes[1] {
+        surface.texture = c"sky1".to_owned();
+    }
+
+    let expected = "\
+// Game: Quake
+// Format: Standard
+// entity 0
+{
+\"classname\" \"worldspawn\"
+\"message\" \"hello\"
+// brush 0
+{
+( -16.000 -16 -16 ) ( -16 -15 -16 ) ( -16 -16 -15 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -16 -16 -15 ) ( -15 -16 -16 ) base 0 0 0 1 1
+( -16 -16 -16 ) ( -15 -16 -16 ) ( -16 -15 -16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 17 16 ) ( 17 16 16 ) base 0 0 0 1 1
+( 16 16 16 ) ( 17 16 16 ) ( 16 16 17 ) base 0 0 0 1 1
+( 16 16 16 ) ( 16 16 17 ) ( 16 17 16 ) base 0 0 0 1 1
+}
+// brush 1
+{
+( 32 -16 -16 ) ( 32 -15 -16 ) ( 32 -16 -15 ) sky1 0 0 0 1 1
+( 32 -16 -16 ) ( 32 -16 -15 ) ( 33 -16 -16 ) sky1 0 0 0 1 1
+( 32 -16 -16 ) ( 33 -16 -16 ) ( 32 -15 -16 ) sky1 0 0 0 1 1
+( 64 16 16 ) ( 64 17 16 ) ( 65 16 16 ) sky1 0 0 0 1 1
+( 64 16 16 ) ( 65 16 16 ) ( 64 16 17 ) sky1 0 0 0 1 1
+( 64 16 16 ) ( 64 16 17 ) ( 64 17 16 ) sky1 0 0 0 1 1
+}
+}
+// entity 1
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+";
+
+    assert_eq!(written(map, Some(&original)), expected);
+}
+
+#[test]
+fn reformatted_map_drops_comments() {
+    let map = parse(SMALL_MAP);
+    let out = written(map, None);
+
+    assert!(!out.contains("//"));
+    assert!(out.starts_with("{\n\"classname\" \"worldspawn\"\n\"wad\""));
+    assert!(
+        out.ends_with("{\n\"classname\" \"light\"\n\"origin\" \"0 0 32\"\n}\n")
+    );
+}
+
+#[test]
+fn entities_follow_their_origins() {
+    let original = MapData::with_source(
+        parse(SMALL_MAP),
+        MapSource::new("small.map".into(), SMALL_MAP.into()),
+    );
+
+    let mut patcher = QuakeMapPatcher::new(original.entities.len());
+    patcher.delete_entity(0);
+    let added = patcher.add_entity();
+    patcher.set_keyvalue(added, c"classname".into(), c"info_null".into());
+    let patched = patcher.patch(&original);
+
+    let mut out = Vec::new();
+
+    write_map(
+        &mut out,
+        &MapData::new(patched.map),
+        Some(&original),
+        Some(&patched.origins),
+    )
+    .unwrap();
+
+    // comments before the first entity are part of the header, so they stay
+    let expected = "\
+// Game: Quake
+// Format: Standard
+// entity 0
+// entity 1
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+{
+\"classname\" \"info_null\"
+}
+";
+
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
+
+#[test]
+fn brushes_follow_their_origins() {
+    // a middle brush, under a comment of its own, for the patch to delete
+    let text = SMALL_MAP.replacen(
+        "// brush 1\n",
+        "\
+// brush 1
+{
+( 96 -16 -16 ) ( 96 -15 -16 ) ( 96 -16 -15 ) base 0 0 0 1 1
+( 96 -16 -16 ) ( 96 -16 -15 ) ( 97 -16 -16 ) base 0 0 0 1 1
+( 96 -16 -16 ) ( 97 -16 -16 ) ( 96 -15 -16 ) base 0 0 0 1 1
+( 128 16 16 ) ( 128 17 16 ) ( 129 16 16 ) base 0 0 0 1 1
+( 128 16 16 ) ( 129 16 16 ) ( 128 16 17 ) base 0 0 0 1 1
+( 128 16 16 ) ( 128 16 17 ) ( 128 17 16 ) base 0 0 0 1 1
+}
+// brush 2
+",
+        1,
+    );
+
+    let original = MapData::with_source(
+        parse(&text),
+        MapSource::new("small.map".into(), text.into()),
+    );
+
+    let mut patcher = QuakeMapPatcher::new(original.entities.len());
+    patcher.delete_brush(0, 1);
+    let patched = patcher.patch(&original);
+
+    let mut out = Vec::new();
+
+    write_map(
+        &mut out,
+        &MapData::new(patched.map),
+        Some(&original),
+        Some(&patched.origins),
+    )
+    .unwrap();
+
+    // the brushes left are copied, and the deleted one's comment goes too
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        SMALL_MAP.replace("// brush 1", "// brush 2")
+    );
+}
+
+#[test]
+fn entity_files_leave_out_brushes() {
+    let map = MapData::with_source(
+        parse(SMALL_MAP),
+        MapSource::new("small.map".into(), SMALL_MAP.into()),
+    );
+
+    let mut out = Vec::new();
+    write_ent(&mut out, &map, Some(&map), None).unwrap();
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"gfx.wad\"
+}
+{
+\"classname\" \"light\"
+\"origin\" \"0 0 32\"
+}
+"
+    );
+}